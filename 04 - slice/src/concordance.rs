@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// a keyword-in-context (KWIC) concordance. The text is split into words
+// once, and every result hands back slices of the original text, so
+// nothing is copied no matter how many times the index is queried.
+// The lifetime `'a` ties the index (and its results) to the text.
+pub struct Concordance<'a> {
+    text: &'a str,
+    // byte ranges of every word in the text, in order
+    words: Vec<(usize, usize)>,
+    // lowercase word -> positions in `words`. A BTreeMap keeps the keys
+    // sorted which turns prefix queries into a range scan
+    index: BTreeMap<String, Vec<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Query {
+    // number of words shown on each side of the keyword
+    pub context: usize,
+    pub ignore_case: bool,
+    // match every word starting with the query instead of the word itself
+    pub prefix: bool,
+}
+
+impl Default for Query {
+    fn default() -> Query {
+        Query {
+            context: 3,
+            ignore_case: false,
+            prefix: false,
+        }
+    }
+}
+
+// a single occurrence. All three parts borrow from the indexed text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub left: &'a str,
+    pub keyword: &'a str,
+    pub right: &'a str,
+    // index of the keyword among the words of the text
+    pub position: usize,
+}
+
+impl<'a> Concordance<'a> {
+    pub fn new(text: &'a str) -> Concordance<'a> {
+        let words = split_words(text);
+        let mut index: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+        for (position, &(start, end)) in words.iter().enumerate() {
+            index
+                .entry(text[start..end].to_lowercase())
+                .or_default()
+                .push(position);
+        }
+
+        Concordance { text, words, index }
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn lookup(&self, word: &str, query: &Query) -> Vec<Line<'a>> {
+        let key = word.to_lowercase();
+        let mut positions: Vec<usize> = Vec::new();
+
+        if query.prefix {
+            // every key starting with `key` sorts right after it
+            for (_, found) in self
+                .index
+                .range(key.clone()..)
+                .take_while(|(k, _)| k.starts_with(&key))
+            {
+                positions.extend(found);
+            }
+        } else if let Some(found) = self.index.get(&key) {
+            positions.extend(found);
+        }
+
+        // the index is case-insensitive, so case-sensitive queries have
+        // to check the original spelling of every candidate
+        if !query.ignore_case {
+            positions.retain(|&p| {
+                let w = self.word(p);
+                if query.prefix {
+                    w.starts_with(word)
+                } else {
+                    w == word
+                }
+            });
+        }
+
+        // prefix queries collect positions from several keys
+        positions.sort_unstable();
+        positions
+            .into_iter()
+            .map(|p| self.line(p, query.context))
+            .collect()
+    }
+
+    fn word(&self, position: usize) -> &'a str {
+        let (start, end) = self.words[position];
+        &self.text[start..end]
+    }
+
+    fn line(&self, position: usize, context: usize) -> Line<'a> {
+        let (start, end) = self.words[position];
+        let first = position.saturating_sub(context);
+        let last = position.saturating_add(context).min(self.words.len() - 1);
+
+        // the context spans from the first context word up to the
+        // keyword, including any punctuation in between
+        let left = if first < position {
+            &self.text[self.words[first].0..start]
+        } else {
+            ""
+        };
+        let right = if last > position {
+            &self.text[end..self.words[last].1]
+        } else {
+            ""
+        };
+
+        Line {
+            left,
+            keyword: &self.text[start..end],
+            right,
+            position,
+        }
+    }
+}
+
+// loading is separate from indexing since the index can only borrow the
+// text. The caller owns the `String` and keeps it alive
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    fs::read_to_string(path)
+}
+
+// lines up the keywords in a column. The left context is right-aligned
+// and line breaks inside the context are shown as spaces
+pub fn format_lines(lines: &[Line]) -> String {
+    let lefts: Vec<String> = lines.iter().map(|l| flatten(l.left)).collect();
+    let width = lefts.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+    let mut out = String::new();
+    for (line, left) in lines.iter().zip(&lefts) {
+        out.push_str(&format!(
+            "{:>width$}[{}]{}\n",
+            left,
+            line.keyword,
+            flatten(line.right),
+            width = width
+        ));
+    }
+
+    out
+}
+
+fn flatten(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect()
+}
+
+// a word is a run of alphanumeric characters. Apostrophes and hyphens
+// are kept when they join two such runs, as in "don't" or "forty-two"
+fn split_words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let joins = (c == '\'' || c == '-')
+            && start.is_some()
+            && matches!(chars.peek(), Some((_, next)) if next.is_alphanumeric());
+
+        if c.is_alphanumeric() || joins {
+            if start.is_none() {
+                start = Some(i);
+            }
+        } else if let Some(s) = start.take() {
+            words.push((s, i));
+        }
+    }
+
+    if let Some(s) = start {
+        words.push((s, text.len()));
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "The cat sat. the Cat ran, then the catalogue fell.";
+
+    fn positions(lines: &[Line]) -> Vec<usize> {
+        lines.iter().map(|l| l.position).collect()
+    }
+
+    #[test]
+    fn case_is_folded_only_when_asked() {
+        let index = Concordance::new(TEXT);
+        assert_eq!(positions(&index.lookup("the", &Query::default())), [3, 7]);
+
+        let query = Query {
+            ignore_case: true,
+            ..Query::default()
+        };
+        let lines = index.lookup("THE", &query);
+        assert_eq!(positions(&lines), [0, 3, 7]);
+        // the keyword is the text as written, not the folded query
+        assert_eq!(lines[0].keyword, "The");
+    }
+
+    #[test]
+    fn prefixes_match_longer_words() {
+        let index = Concordance::new(TEXT);
+        let query = Query {
+            prefix: true,
+            ..Query::default()
+        };
+        assert_eq!(positions(&index.lookup("cat", &query)), [1, 8]);
+
+        let query = Query {
+            ignore_case: true,
+            ..query
+        };
+        let lines = index.lookup("cat", &query);
+        assert_eq!(positions(&lines), [1, 4, 8]);
+        assert_eq!(lines[2].keyword, "catalogue");
+        assert!(index.lookup("dog", &query).is_empty());
+    }
+
+    #[test]
+    fn context_includes_the_punctuation_between_words() {
+        let index = Concordance::new(TEXT);
+        let query = Query {
+            context: 1,
+            ..Query::default()
+        };
+        let line = index.lookup("sat", &query)[0];
+        assert_eq!(
+            (line.left, line.keyword, line.right),
+            ("cat ", "sat", ". the")
+        );
+
+        // at either end there is nothing more to show
+        let line = index.lookup("The", &query)[0];
+        assert_eq!((line.left, line.right), ("", " cat"));
+    }
+
+    #[test]
+    fn huge_contexts_stop_at_the_ends() {
+        let index = Concordance::new(TEXT);
+        let query = Query {
+            context: usize::MAX,
+            ..Query::default()
+        };
+        let line = index.lookup("ran", &query)[0];
+        assert_eq!(line.left, "The cat sat. the Cat ");
+        assert_eq!(line.right, ", then the catalogue fell");
+    }
+
+    // the line break in the context is shown as a space
+    #[test]
+    fn keywords_are_lined_up() {
+        let index = Concordance::new("a b x\nlonger words x end");
+        let query = Query {
+            context: 2,
+            ..Query::default()
+        };
+        let lines = index.lookup("x", &query);
+        assert_eq!(
+            format_lines(&lines),
+            "         a b [x] longer words\n\
+             longer words [x] end\n"
+        );
+    }
+
+    #[test]
+    fn words_keep_inner_apostrophes_and_hyphens() {
+        let index = Concordance::new("don't stop -- forty-two 'quoted'");
+        assert_eq!(index.word_count(), 4);
+        let query = Query::default();
+        assert_eq!(index.lookup("forty-two", &query).len(), 1);
+        assert_eq!(index.lookup("don't", &query).len(), 1);
+        assert_eq!(index.lookup("quoted", &query).len(), 1);
+    }
+}
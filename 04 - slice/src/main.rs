@@ -1,3 +1,9 @@
+mod concordance;
+
+use concordance::{Concordance, Query};
+use std::env;
+use std::process;
+
 fn main() {
     // a slice lets you reference a contiguous sequence of elements in a collection
 
@@ -16,6 +22,66 @@ fn main() {
 
     let word = last_word(&s);
     println!("Last word: `{}`", word);
+
+    // a concordance hands back slices of the text for every occurrence
+    // of a word, together with the words around it
+    let quote = "The answer to the ultimate question of life, the universe \
+                 and everything is forty-two. The question, however, is unknown.";
+    let index = Concordance::new(quote);
+    let query = Query {
+        context: 2,
+        ignore_case: true,
+        ..Query::default()
+    };
+    print!(
+        "{}",
+        concordance::format_lines(&index.lookup("the", &query))
+    );
+
+    // usage: cargo run -- <file> <word> [context] [-i] [-p]
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = run(&args) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut query = Query::default();
+    let mut positional = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-i" => query.ignore_case = true,
+            "-p" => query.prefix = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    let (path, word) = match positional.as_slice() {
+        [path, word] | [path, word, _] => (path, word),
+        _ => {
+            return Err(String::from(
+                "usage: slice <file> <word> [context] [-i] [-p]",
+            ))
+        }
+    };
+    if let Some(context) = positional.get(2) {
+        query.context = context
+            .parse()
+            .map_err(|_| format!("invalid context size: {}", context))?;
+    }
+
+    // the text must outlive the index since results borrow from it
+    let text = concordance::load(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let index = Concordance::new(&text);
+    let lines = index.lookup(word, &query);
+
+    print!("{}", concordance::format_lines(&lines));
+    println!("{} of {} words matched", lines.len(), index.word_count());
+    Ok(())
 }
 
 fn last_word(s: &str) -> &str {
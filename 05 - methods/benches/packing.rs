@@ -0,0 +1,69 @@
+use methods::packing::{self, Algorithm};
+use methods::Rectangle;
+use std::time::Instant;
+
+// a tiny xorshift generator so the workloads are random but the same on
+// every run, without depending on the rand crate
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn range(&mut self, low: u32, high: u32) -> u32 {
+        low + (self.next() % (high - low) as u64) as u32
+    }
+}
+
+fn workload(rng: &mut XorShift, count: usize, max_side: u32) -> Vec<Rectangle> {
+    (0..count)
+        .map(|_| Rectangle::new(0, 0, rng.range(1, max_side), rng.range(1, max_side)))
+        .collect()
+}
+
+fn main() {
+    let container = Rectangle::new(0, 0, 1024, 1024);
+    let workloads = [
+        ("800 small", 800, 64),
+        ("200 mixed", 200, 160),
+        ("50 large", 50, 400),
+    ];
+    let algorithms = [Algorithm::Shelf, Algorithm::Guillotine, Algorithm::MaxRects];
+    let runs = 20;
+
+    println!(
+        "{:<10} {:<11} {:>10} {:>8} {:>9}",
+        "workload", "algorithm", "time", "util %", "unplaced"
+    );
+    for (name, count, max_side) in workloads {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let items: Vec<Vec<Rectangle>> = (0..runs)
+            .map(|_| workload(&mut rng, count, max_side))
+            .collect();
+
+        for algorithm in algorithms {
+            let start = Instant::now();
+            let mut utilisation = 0.0;
+            let mut unplaced = 0;
+            for set in &items {
+                let result = packing::pack(algorithm, set, &container, true);
+                utilisation += result.utilisation();
+                unplaced += result.unplaced.len();
+            }
+            let elapsed = start.elapsed() / runs as u32;
+
+            println!(
+                "{:<10} {:<11} {:>10.2?} {:>8.1} {:>9.1}",
+                name,
+                format!("{:?}", algorithm),
+                elapsed,
+                utilisation / runs as f64,
+                unplaced as f64 / runs as f64
+            );
+        }
+    }
+}
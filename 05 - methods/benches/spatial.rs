@@ -0,0 +1,153 @@
+use methods::spatial::SpatialIndex;
+use methods::Rectangle;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// the same generator as in the packing benchmark
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn range(&mut self, low: u32, high: u32) -> u32 {
+        low + (self.next() % (high - low) as u64) as u32
+    }
+
+    fn rect(&mut self, max_side: u32) -> Rectangle {
+        Rectangle::new(
+            self.range(0, 10_000),
+            self.range(0, 10_000),
+            self.range(1, max_side),
+            self.range(1, max_side),
+        )
+    }
+}
+
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let start = Instant::now();
+    let result = f();
+    (start.elapsed(), result)
+}
+
+fn report(name: &str, brute: (Duration, usize), index: (Duration, usize)) {
+    // both sides must find the same number of results
+    assert_eq!(brute.1, index.1, "{} disagrees", name);
+    println!(
+        "{:<16} {:>12.2?} {:>12.2?} {:>8.1}x",
+        name,
+        brute.0,
+        index.0,
+        brute.0.as_secs_f64() / index.0.as_secs_f64()
+    );
+}
+
+fn main() {
+    for count in [1_000, 10_000] {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let items: Vec<(usize, Rectangle)> = (0..count).map(|i| (i, rng.rect(100))).collect();
+        let windows: Vec<Rectangle> = (0..1_000).map(|_| rng.rect(500)).collect();
+        let points: Vec<(u32, u32)> = (0..1_000)
+            .map(|_| (rng.range(0, 10_000), rng.range(0, 10_000)))
+            .collect();
+
+        println!("\n{} rectangles", count);
+        println!(
+            "{:<16} {:>12} {:>12} {:>9}",
+            "", "brute force", "r-tree", "speedup"
+        );
+
+        let (bulk, _) = time(|| SpatialIndex::bulk_load(items.clone()).len());
+        let (inserted, _) = time(|| {
+            let mut index = SpatialIndex::new();
+            for &(id, r) in &items {
+                index.insert(id, r);
+            }
+            index.len()
+        });
+        println!("{:<16} {:>12} {:>12.2?}", "bulk load", "", bulk);
+        println!("{:<16} {:>12} {:>12.2?}", "insert one by one", "", inserted);
+
+        let index = SpatialIndex::bulk_load(items.clone());
+
+        report(
+            "window queries",
+            time(|| {
+                windows
+                    .iter()
+                    .map(|w| items.iter().filter(|(_, r)| r.intersects(w)).count())
+                    .sum()
+            }),
+            time(|| windows.iter().map(|w| index.query_window(w).len()).sum()),
+        );
+
+        report(
+            "point queries",
+            time(|| {
+                points
+                    .iter()
+                    .map(|&(x, y)| items.iter().filter(|(_, r)| r.contains_point(x, y)).count())
+                    .sum()
+            }),
+            time(|| {
+                points
+                    .iter()
+                    .map(|&(x, y)| index.query_point(x, y).len())
+                    .sum()
+            }),
+        );
+
+        report(
+            "nearest",
+            time(|| {
+                points
+                    .iter()
+                    .map(|&(x, y)| {
+                        // black_box keeps the search from being optimised
+                        // away, since only the count is compared
+                        black_box(items.iter().min_by_key(|(_, r)| {
+                            let dx = gap(x, r.left(), r.right());
+                            let dy = gap(y, r.top(), r.bottom());
+                            dx * dx + dy * dy
+                        }))
+                        .map_or(0, |_| 1)
+                    })
+                    .sum()
+            }),
+            time(|| {
+                points
+                    .iter()
+                    .map(|&(x, y)| index.nearest(x, y, 1).len())
+                    .sum()
+            }),
+        );
+
+        report(
+            "overlapping pairs",
+            time(|| {
+                let mut pairs = 0;
+                for (i, (_, a)) in items.iter().enumerate() {
+                    pairs += items[i + 1..]
+                        .iter()
+                        .filter(|(_, b)| a.intersects(b))
+                        .count();
+                }
+                pairs
+            }),
+            time(|| index.overlapping_pairs().len()),
+        );
+    }
+}
+
+fn gap(p: u32, low: u64, high: u64) -> u64 {
+    let p = p as u64;
+    if p < low {
+        low - p
+    } else {
+        p.saturating_sub(high)
+    }
+}
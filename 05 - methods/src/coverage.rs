@@ -0,0 +1,493 @@
+use crate::rectangle::Rectangle;
+use crate::shape::{Point, Polygon};
+use std::collections::HashMap;
+
+// summing `area()` over overlapping rectangles counts the overlaps more
+// than once. These functions sweep a vertical line across the plane
+// instead: between two consecutive left or right edges nothing changes,
+// so every such slab adds (its width) x (the length of the line that is
+// covered). Coordinates are compressed to the distinct edges, so the cost
+// depends on the number of rectangles and not on their size.
+
+// the area covered by at least one rectangle. It is a u128 since a few
+// rectangles near the end of the u32 grid already cover more than a u64
+// can hold
+pub fn union_area(rects: &[Rectangle]) -> u128 {
+    area_covered_at_least(rects, 1)
+}
+
+// the area covered by at least `k` of the rectangles. For k = 0 that is
+// the whole plane, which is reported as 0 since it has no finite area.
+// More than the number of rectangles is never reached, and returns early
+// as well
+pub fn area_covered_at_least(rects: &[Rectangle], k: usize) -> u128 {
+    if k == 0 {
+        return 0;
+    }
+
+    let rects: Vec<&Rectangle> = rects.iter().filter(|r| !r.is_empty()).collect();
+    if k > rects.len() {
+        return 0;
+    }
+    let ys = edges(rects.iter().flat_map(|r| [r.top(), r.bottom()]));
+    if ys.len() < 2 {
+        return 0;
+    }
+
+    let mut events: Vec<(u64, usize, usize, i32)> = Vec::with_capacity(rects.len() * 2);
+    for r in &rects {
+        let (y0, y1) = (slot(&ys, r.top()), slot(&ys, r.bottom()));
+        events.push((r.left(), y0, y1, 1));
+        events.push((r.right(), y0, y1, -1));
+    }
+    events.sort_unstable();
+
+    // the tree keeps `k` lengths per node. For a large `k` that is too
+    // much memory, and plain counts per slot are used instead, which
+    // takes time proportional to the number of slots per event
+    if (ys.len() - 1).saturating_mul(4).saturating_mul(k) <= TREE_LIMIT {
+        let mut tree = CoverageTree::new(&ys, k);
+        sweep(&events, |y0, y1, delta| {
+            tree.update(y0, y1, delta);
+            tree.covered()
+        })
+    } else {
+        let mut counts = SlotCounts::new(&ys, k);
+        sweep(&events, |y0, y1, delta| counts.update(y0, y1, delta))
+    }
+}
+
+// the most lengths a `CoverageTree` may keep, 64 MiB worth
+const TREE_LIMIT: usize = 1 << 23;
+
+// adds up the slabs between the events. `update` applies one event and
+// returns the length of the line covered after it
+fn sweep<F>(events: &[(u64, usize, usize, i32)], mut update: F) -> u128
+where
+    F: FnMut(usize, usize, i32) -> u64,
+{
+    let mut area = 0;
+    let mut covered = 0;
+    let mut last_x = events[0].0;
+    for &(x, y0, y1, delta) in events {
+        area += u128::from(x - last_x) * u128::from(covered);
+        covered = update(y0, y1, delta);
+        last_x = x;
+    }
+    area
+}
+
+// the simple version of `CoverageTree`: how many rectangles cover each
+// slot, and the length of the slots covered at least `k` times
+struct SlotCounts<'a> {
+    ys: &'a [u64],
+    k: i32,
+    counts: Vec<i32>,
+    covered: u64,
+}
+
+impl<'a> SlotCounts<'a> {
+    fn new(ys: &'a [u64], k: usize) -> SlotCounts<'a> {
+        SlotCounts {
+            ys,
+            k: i32::try_from(k).unwrap_or(i32::MAX),
+            counts: vec![0; ys.len() - 1],
+            covered: 0,
+        }
+    }
+
+    fn update(&mut self, y0: usize, y1: usize, delta: i32) -> u64 {
+        for s in y0..y1 {
+            let was = self.counts[s] >= self.k;
+            self.counts[s] += delta;
+            let is = self.counts[s] >= self.k;
+            let length = self.ys[s + 1] - self.ys[s];
+            match (was, is) {
+                (false, true) => self.covered += length,
+                (true, false) => self.covered -= length,
+                _ => {}
+            }
+        }
+        self.covered
+    }
+}
+
+// a segment tree over the compressed y axis. Each node keeps how many
+// rectangles cover its whole range (and are not counted further up), and
+// for j = 1..=k the length of its range that is covered at least j times
+// by the rectangles counted at or below it
+struct CoverageTree<'a> {
+    ys: &'a [u64],
+    k: usize,
+    count: Vec<i32>,
+    // `k` entries per node, the one for j at index j - 1
+    covered: Vec<u64>,
+}
+
+impl<'a> CoverageTree<'a> {
+    fn new(ys: &'a [u64], k: usize) -> CoverageTree<'a> {
+        let slots = ys.len() - 1;
+        CoverageTree {
+            ys,
+            k,
+            count: vec![0; 4 * slots],
+            covered: vec![0; 4 * slots * k],
+        }
+    }
+
+    // length covered at least k times
+    fn covered(&self) -> u64 {
+        self.covered[self.k + self.k - 1]
+    }
+
+    fn update(&mut self, y0: usize, y1: usize, delta: i32) {
+        let slots = self.ys.len() - 1;
+        self.apply(1, 0, slots, y0, y1, delta);
+    }
+
+    fn apply(&mut self, node: usize, low: usize, high: usize, y0: usize, y1: usize, delta: i32) {
+        if y1 <= low || high <= y0 {
+            return;
+        }
+        if y0 <= low && high <= y1 {
+            self.count[node] += delta;
+        } else {
+            let mid = (low + high) / 2;
+            self.apply(2 * node, low, mid, y0, y1, delta);
+            self.apply(2 * node + 1, mid, high, y0, y1, delta);
+        }
+        self.pull(node, low, high);
+    }
+
+    fn pull(&mut self, node: usize, low: usize, high: usize) {
+        let k = self.k;
+        let full = self.ys[high] - self.ys[low];
+        let own = self.count[node].max(0) as usize;
+        let leaf = high - low == 1;
+
+        for j in 1..=k {
+            self.covered[node * k + j - 1] = if own >= j {
+                full
+            } else if leaf {
+                0
+            } else {
+                // the rest has to come from rectangles further down
+                let need = j - own;
+                self.covered[2 * node * k + need - 1] + self.covered[(2 * node + 1) * k + need - 1]
+            };
+        }
+    }
+}
+
+// the outline of the union as closed polygons. Every vertex is a corner,
+// and the interior is always on the left of an edge when the y axis
+// points up, so outer boundaries have a positive `signed_area` and holes
+// a negative one. Where two parts of the union touch only at a corner
+// they are returned as separate polygons.
+//
+// the boundary edges are found with two sweeps: along each vertical line
+// through a left or right edge, a piece of that line is on the boundary
+// when it is covered on exactly one side of it (and likewise for the
+// horizontal lines). Each sweep step looks at the whole compressed axis,
+// which makes this quadratic in the number of rectangles
+pub fn union_outline(rects: &[Rectangle]) -> Vec<Polygon> {
+    let rects: Vec<(u64, u64, u64, u64)> = rects
+        .iter()
+        .filter(|r| !r.is_empty())
+        .map(|r| (r.left(), r.top(), r.right(), r.bottom()))
+        .collect();
+
+    // edges as (from, to), in (x, y)
+    let mut segments: Vec<((u64, u64), (u64, u64))> = Vec::new();
+    for (a, b, flip) in [(0, 1, false), (1, 0, true)] {
+        // for the horizontal sweep the axes are swapped, which also turns
+        // "interior on the left" into "interior on the right"
+        let swapped: Vec<(u64, u64, u64, u64)> = rects
+            .iter()
+            .map(|r| {
+                let e = [r.0, r.1, r.2, r.3];
+                (e[a], e[b], e[a + 2], e[b + 2])
+            })
+            .collect();
+
+        for (line, low, high, covered_after) in boundary(&swapped) {
+            // a line with the interior after it is a left edge, walked
+            // towards smaller coordinates to keep the interior on the left
+            let down = covered_after != flip;
+            let (from, to) = if down { (high, low) } else { (low, high) };
+            segments.push(if flip {
+                ((from, line), (to, line))
+            } else {
+                ((line, from), (line, to))
+            });
+        }
+    }
+
+    // link the edges into rings by matching their ends
+    let mut outgoing: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, (from, _)) in segments.iter().enumerate() {
+        outgoing.entry(*from).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut polygons = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+
+        let mut ring = Vec::new();
+        let mut edge = start;
+        loop {
+            used[edge] = true;
+            let (from, to) = segments[edge];
+            ring.push(Point::new(from.0 as f64, from.1 as f64));
+
+            // where two parts touch at a corner, two edges leave the same
+            // point. Turning left keeps the parts apart
+            let next = outgoing
+                .get(&to)
+                .into_iter()
+                .flatten()
+                .filter(|&&i| !used[i])
+                .max_by_key(|&&i| turn(segments[edge], segments[i]));
+            match next {
+                Some(&i) => edge = i,
+                None => break,
+            }
+        }
+        polygons.push(Polygon::new(ring));
+    }
+
+    polygons
+}
+
+// +1 for a left turn, -1 for a right turn, 0 for straight on (y up)
+fn turn(a: ((u64, u64), (u64, u64)), b: ((u64, u64), (u64, u64))) -> i64 {
+    let d = |s: ((u64, u64), (u64, u64))| {
+        (
+            (s.1 .0 as i64 - s.0 .0 as i64).signum(),
+            (s.1 .1 as i64 - s.0 .1 as i64).signum(),
+        )
+    };
+    let (da, db) = (d(a), d(b));
+    (da.0 * db.1 - da.1 * db.0).signum()
+}
+
+// the pieces of the vertical lines x = const that lie on the boundary of
+// the union of `rects` (given as left, top, right, bottom), as
+// (x, y0, y1, covered to the right of the line)
+fn boundary(rects: &[(u64, u64, u64, u64)]) -> Vec<(u64, u64, u64, bool)> {
+    let ys = edges(rects.iter().flat_map(|r| [r.1, r.3]));
+    if ys.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut events: Vec<(u64, usize, usize, i32)> = Vec::with_capacity(rects.len() * 2);
+    for r in rects {
+        let (y0, y1) = (slot(&ys, r.1), slot(&ys, r.3));
+        events.push((r.0, y0, y1, 1));
+        events.push((r.2, y0, y1, -1));
+    }
+    events.sort_unstable();
+
+    let mut counts = vec![0i32; ys.len() - 1];
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < events.len() {
+        let x = events[i].0;
+        let before: Vec<bool> = counts.iter().map(|&c| c > 0).collect();
+        while i < events.len() && events[i].0 == x {
+            let (_, y0, y1, delta) = events[i];
+            for c in &mut counts[y0..y1] {
+                *c += delta;
+            }
+            i += 1;
+        }
+
+        // runs of slots whose coverage changed the same way
+        let mut run: Option<(usize, bool)> = None;
+        for s in 0..=counts.len() {
+            let change = if s < counts.len() && (counts[s] > 0) != before[s] {
+                Some(counts[s] > 0)
+            } else {
+                None
+            };
+            if let Some((start, after)) = run {
+                if change != Some(after) {
+                    pieces.push((x, ys[start], ys[s], after));
+                    run = None;
+                }
+            }
+            if run.is_none() {
+                run = change.map(|after| (s, after));
+            }
+        }
+    }
+
+    pieces
+}
+
+// the sorted distinct coordinates
+fn edges<I: Iterator<Item = u64>>(coords: I) -> Vec<u64> {
+    let mut v: Vec<u64> = coords.collect();
+    v.sort_unstable();
+    v.dedup();
+    v
+}
+
+fn slot(ys: &[u64], y: u64) -> usize {
+    ys.binary_search(&y).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn overlaps_are_counted_once() {
+        let rects = [Rectangle::new(0, 0, 4, 4), Rectangle::new(2, 2, 4, 4)];
+        assert_eq!(union_area(&rects), 28);
+        assert_eq!(area_covered_at_least(&rects, 2), 4);
+    }
+
+    #[test]
+    fn k_outside_the_number_of_rectangles_covers_nothing() {
+        let rects = [Rectangle::new(0, 0, 4, 4), Rectangle::new(2, 2, 4, 4)];
+        assert_eq!(area_covered_at_least(&rects, 0), 0);
+        assert_eq!(area_covered_at_least(&rects, 3), 0);
+        assert_eq!(area_covered_at_least(&rects, usize::MAX), 0);
+        assert_eq!(union_area(&[]), 0);
+    }
+
+    // the same sweep with both ways of counting
+    fn both_ways(rects: &[Rectangle], k: usize) -> (u128, u128) {
+        let rects: Vec<&Rectangle> = rects.iter().filter(|r| !r.is_empty()).collect();
+        let ys = edges(rects.iter().flat_map(|r| [r.top(), r.bottom()]));
+        if ys.len() < 2 {
+            return (0, 0);
+        }
+        let mut events = Vec::new();
+        for r in &rects {
+            let (y0, y1) = (slot(&ys, r.top()), slot(&ys, r.bottom()));
+            events.push((r.left(), y0, y1, 1));
+            events.push((r.right(), y0, y1, -1));
+        }
+        events.sort_unstable();
+
+        let mut tree = CoverageTree::new(&ys, k);
+        let mut counts = SlotCounts::new(&ys, k);
+        (
+            sweep(&events, |y0, y1, delta| {
+                tree.update(y0, y1, delta);
+                tree.covered()
+            }),
+            sweep(&events, |y0, y1, delta| counts.update(y0, y1, delta)),
+        )
+    }
+
+    // counts every unit cell of a small grid
+    fn by_cells(rects: &[Rectangle], k: usize) -> u128 {
+        let mut area = 0;
+        for x in 0..40 {
+            for y in 0..40 {
+                let n = rects.iter().filter(|r| r.contains_point(x, y)).count();
+                if n >= k {
+                    area += 1;
+                }
+            }
+        }
+        area
+    }
+
+    proptest! {
+        #[test]
+        fn the_tree_and_the_counts_agree(
+            rects in prop::collection::vec((0u32..30, 0u32..30, 0u32..10, 0u32..10), 1..25),
+            k in 1usize..6,
+        ) {
+            let rects: Vec<Rectangle> = rects
+                .into_iter()
+                .map(|(x, y, w, h)| Rectangle::new(x, y, w, h))
+                .collect();
+            let (tree, counts) = both_ways(&rects, k);
+            prop_assert_eq!(tree, counts);
+            prop_assert_eq!(area_covered_at_least(&rects, k), by_cells(&rects, k));
+        }
+    }
+
+    #[test]
+    fn large_k_does_not_build_a_huge_tree() {
+        // 4000 nested squares, each counted by 4·8000·4000 tree lengths
+        let rects: Vec<Rectangle> = (0..4000)
+            .map(|i| Rectangle::new(i, i, 8002 - 2 * i, 8002 - 2 * i))
+            .collect();
+        assert_eq!(area_covered_at_least(&rects, 4000), 16);
+        assert_eq!(area_covered_at_least(&rects, 3999), 36);
+    }
+
+    // the signed areas of the polygons, largest first
+    fn areas(polygons: &[Polygon]) -> Vec<f64> {
+        let mut areas: Vec<f64> = polygons.iter().map(|p| p.signed_area()).collect();
+        areas.sort_by(|a, b| b.total_cmp(a));
+        areas
+    }
+
+    #[test]
+    fn outlines_with_a_hole() {
+        // a 6×6 frame around a 2×2 hole, made of four overlapping bars
+        let rects = [
+            Rectangle::new(0, 0, 6, 2),
+            Rectangle::new(0, 4, 6, 2),
+            Rectangle::new(0, 0, 2, 6),
+            Rectangle::new(4, 0, 2, 6),
+        ];
+        let outline = union_outline(&rects);
+        assert_eq!(areas(&outline), vec![36.0, -4.0]);
+        for polygon in &outline {
+            assert_eq!(polygon.vertices.len(), 4);
+            assert!(!polygon.is_self_intersecting());
+        }
+        let hole = outline.iter().find(|p| p.signed_area() < 0.0).unwrap();
+        for corner in [(2.0, 2.0), (4.0, 2.0), (4.0, 4.0), (2.0, 4.0)] {
+            assert!(hole.vertices.contains(&Point::new(corner.0, corner.1)));
+        }
+        assert_eq!(union_area(&rects), 32);
+    }
+
+    #[test]
+    fn parts_touching_at_a_corner_stay_apart() {
+        let rects = [
+            Rectangle::new(0, 0, 2, 2),
+            Rectangle::new(2, 2, 3, 3),
+            Rectangle::new(5, 0, 1, 2),
+        ];
+        let outline = union_outline(&rects);
+        assert_eq!(areas(&outline), vec![9.0, 4.0, 2.0]);
+        for polygon in &outline {
+            assert_eq!(polygon.vertices.len(), 4);
+            assert!(!polygon.is_self_intersecting());
+        }
+    }
+
+    #[test]
+    fn outlines_of_nothing() {
+        assert!(union_outline(&[]).is_empty());
+        assert!(union_outline(&[Rectangle::new(3, 3, 0, 5)]).is_empty());
+    }
+
+    #[test]
+    fn areas_past_u64_do_not_overflow() {
+        let m = u32::MAX;
+        let rects = [
+            Rectangle::new(0, 0, m, m),
+            Rectangle::new(m, 0, m, m),
+            Rectangle::new(0, m, m, m),
+            Rectangle::new(m, m, m, m),
+        ];
+        let side = u128::from(m);
+        assert_eq!(union_area(&rects), 4 * side * side);
+        assert!(union_area(&rects) > u128::from(u64::MAX));
+    }
+}
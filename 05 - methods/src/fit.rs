@@ -0,0 +1,335 @@
+use crate::num::Num;
+use crate::rectangle::Rectangle;
+
+// how an item has to be turned to fit into a container
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    // as it is
+    Upright,
+    // turned by 90°, so width and height swap
+    Rotated,
+    // turned by this many degrees, measured from the x axis to the
+    // item's width edge
+    Angle(f64),
+}
+
+// which side of the container is too short
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Width,
+    Height,
+    Both,
+}
+
+// the answer `can_hold` gives, with the reason attached. The slack is the
+// room left over along each axis of the container
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fit<S> {
+    Fits {
+        orientation: Orientation,
+        slack_width: S,
+        slack_height: S,
+    },
+    DoesNotFit {
+        axis: Axis,
+    },
+}
+
+impl<S> Fit<S> {
+    pub fn fits(&self) -> bool {
+        matches!(self, Fit::Fits { .. })
+    }
+}
+
+// floating point slack for the tilted case. Sizes are compared with a
+// small relative tolerance so that an item fitting exactly is not
+// rejected because of rounding in the trigonometry
+const EPSILON: f64 = 1e-9;
+
+impl<T: Num> Rectangle<T> {
+    // like `can_hold`, but says how much room is left or which side is
+    // too short. Empty sizes count as zero
+    pub fn fit(&self, item: &Rectangle<T>) -> Fit<T> {
+        let (cw, ch) = (size(self.width), size(self.height));
+        let (iw, ih) = (size(item.width), size(item.height));
+
+        match (iw <= cw, ih <= ch) {
+            (true, true) => Fit::Fits {
+                orientation: Orientation::Upright,
+                slack_width: T::narrow(cw.widen() - iw.widen()),
+                slack_height: T::narrow(ch.widen() - ih.widen()),
+            },
+            (false, true) => Fit::DoesNotFit { axis: Axis::Width },
+            (true, false) => Fit::DoesNotFit { axis: Axis::Height },
+            (false, false) => Fit::DoesNotFit { axis: Axis::Both },
+        }
+    }
+
+    // tries the item as it is and then turned by 90°. A failure reports
+    // the axis of the upright attempt
+    pub fn fit_rotated(&self, item: &Rectangle<T>) -> Fit<T> {
+        let upright = self.fit(item);
+        if upright.fits() {
+            return upright;
+        }
+
+        let turned = Rectangle::new(item.x, item.y, item.height, item.width);
+        match self.fit(&turned) {
+            Fit::Fits {
+                slack_width,
+                slack_height,
+                ..
+            } => Fit::Fits {
+                orientation: Orientation::Rotated,
+                slack_width,
+                slack_height,
+            },
+            Fit::DoesNotFit { .. } => upright,
+        }
+    }
+
+    // also allows turning the item by any angle. A long thin item can fit
+    // diagonally into a container that is shorter than the item in both
+    // directions (Carver's condition). The slack is reported in f64 since
+    // a tilted item has no exact integer extent
+    pub fn fit_at_angle(&self, item: &Rectangle<T>) -> Fit<f64> {
+        if let Fit::Fits {
+            orientation,
+            slack_width,
+            slack_height,
+        } = self.fit_rotated(item)
+        {
+            return Fit::Fits {
+                orientation,
+                slack_width: slack_width.to_f64(),
+                slack_height: slack_height.to_f64(),
+            };
+        }
+
+        // work with the long sides of both lying along the same axis:
+        // `a` >= `b` for the container, `p` >= `q` for the item
+        let (cw, ch) = (size(self.width).to_f64(), size(self.height).to_f64());
+        let (iw, ih) = (size(item.width).to_f64(), size(item.height).to_f64());
+        let container_wide = cw >= ch;
+        let item_wide = iw >= ih;
+        let (a, b) = if container_wide { (cw, ch) } else { (ch, cw) };
+        let (p, q) = if item_wide { (iw, ih) } else { (ih, iw) };
+
+        let short_axis = if container_wide {
+            Axis::Height
+        } else {
+            Axis::Width
+        };
+
+        // the upright test failed, so either p > a or q > b. A short side
+        // that is already too long only gets worse when tilted
+        if q > b * (1.0 + EPSILON) {
+            let axis = if p > a { Axis::Both } else { short_axis };
+            return Fit::DoesNotFit { axis };
+        }
+
+        // tilted by `theta` the item is `p cos + q sin` long and
+        // `p sin + q cos` high. That is `r cos(theta - phi)` long, so the
+        // smallest angle that brings the length down to `a` is found with
+        // acos. Any steeper angle only makes the item higher
+        let r = p.hypot(q);
+        let phi = q.atan2(p);
+        let theta = phi + (a / r).clamp(-1.0, 1.0).acos();
+        if theta > std::f64::consts::FRAC_PI_2 {
+            return Fit::DoesNotFit { axis: short_axis };
+        }
+
+        let length = p * theta.cos() + q * theta.sin();
+        let height = p * theta.sin() + q * theta.cos();
+        if height > b * (1.0 + EPSILON) {
+            return Fit::DoesNotFit { axis: short_axis };
+        }
+
+        // `theta` is measured between the long sides. Turn it into the
+        // angle between the x axis and the item's width edge
+        let mut degrees = theta.to_degrees();
+        if !container_wide {
+            degrees = 90.0 - degrees;
+        }
+        if !item_wide {
+            degrees += 90.0;
+        }
+
+        let (slack_long, slack_short) = ((a - length).max(0.0), (b - height).max(0.0));
+        let (slack_width, slack_height) = if container_wide {
+            (slack_long, slack_short)
+        } else {
+            (slack_short, slack_long)
+        };
+
+        Fit::Fits {
+            orientation: Orientation::Angle(degrees % 180.0),
+            slack_width,
+            slack_height,
+        }
+    }
+}
+
+// negative and NaN sizes belong to empty rectangles, which take no room
+fn size<T: Num>(v: T) -> T {
+    if v > T::ZERO {
+        v
+    } else {
+        T::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(container: (u32, u32), item: (u32, u32)) -> Fit<f64> {
+        Rectangle::new(0, 0, container.0, container.1)
+            .fit_at_angle(&Rectangle::new(0, 0, item.0, item.1))
+    }
+
+    // Carver's condition for a p×q item in an a×b container, with p >= q
+    // and a >= b. The margin says how far the case is from the boundary
+    fn carver(a: f64, b: f64, p: f64, q: f64) -> (bool, f64) {
+        if p <= a && q <= b {
+            return (true, f64::INFINITY);
+        }
+        if q > b || p == q {
+            return (false, f64::INFINITY);
+        }
+        let lhs = ((a + b) / (p + q)).powi(2) + ((a - b) / (p - q)).powi(2);
+        (lhs >= 2.0, (lhs - 2.0).abs())
+    }
+
+    #[test]
+    fn exact_fits_leave_no_slack() {
+        let container = Rectangle::new(0, 0, 10u32, 4);
+        assert_eq!(
+            container.fit(&Rectangle::new(3, 3, 10, 4)),
+            Fit::Fits {
+                orientation: Orientation::Upright,
+                slack_width: 0,
+                slack_height: 0
+            }
+        );
+        assert_eq!(
+            container.fit(&Rectangle::new(0, 0, 11, 4)),
+            Fit::DoesNotFit { axis: Axis::Width }
+        );
+        assert_eq!(
+            container.fit(&Rectangle::new(0, 0, 10, 5)),
+            Fit::DoesNotFit { axis: Axis::Height }
+        );
+        assert_eq!(
+            container.fit(&Rectangle::new(0, 0, 11, 5)),
+            Fit::DoesNotFit { axis: Axis::Both }
+        );
+        assert_eq!(
+            container.fit_rotated(&Rectangle::new(0, 0, 4, 10)),
+            Fit::Fits {
+                orientation: Orientation::Rotated,
+                slack_width: 0,
+                slack_height: 0
+            }
+        );
+    }
+
+    #[test]
+    fn empty_sides_count_as_zero() {
+        let container = Rectangle::new(0, 0, 3, 3);
+        assert!(!container.fit(&Rectangle::new(0, 0, 0, 100)).fits());
+        assert!(container.fit(&Rectangle::new(0, 0, 0, 3)).fits());
+        let signed = Rectangle::new(0, 0, 3i32, 3);
+        assert!(signed.fit(&Rectangle::new(0, 0, -5, 2)).fits());
+    }
+
+    #[test]
+    fn the_diagonal_boundary_fits() {
+        // 19×16 holds 20×5 exactly, tilted so the item rises 3 for every 4 across
+        match fit((19, 16), (20, 5)) {
+            Fit::Fits {
+                orientation: Orientation::Angle(degrees),
+                slack_width,
+                slack_height,
+            } => {
+                assert!(
+                    (degrees - 3f64.atan2(4.0).to_degrees()).abs() < 1e-6,
+                    "{degrees}"
+                );
+                assert!(slack_width.abs() < 1e-6 && slack_height.abs() < 1e-6);
+            }
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(
+            fit((19, 16), (21, 5)),
+            Fit::DoesNotFit { axis: Axis::Height }
+        );
+        assert_eq!(
+            fit((16, 19), (21, 5)),
+            Fit::DoesNotFit { axis: Axis::Width }
+        );
+        assert_eq!(fit((19, 4), (20, 5)), Fit::DoesNotFit { axis: Axis::Both });
+    }
+
+    #[test]
+    fn fit_at_angle_agrees_with_carver() {
+        for (a, b) in [(10u32, 10), (13, 5), (19, 16), (30, 7), (8, 3)] {
+            for iw in 1..45u32 {
+                for ih in 1..20u32 {
+                    let (p, q) = (iw.max(ih) as f64, iw.min(ih) as f64);
+                    let (expected, margin) = carver(a as f64, b as f64, p, q);
+                    if margin < 1e-6 {
+                        continue;
+                    }
+                    for (cw, ch) in [(a, b), (b, a)] {
+                        let got = fit((cw, ch), (iw, ih));
+                        assert_eq!(got.fits(), expected, "{iw}×{ih} in {cw}×{ch}: {got:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_reported_angle_places_the_item_inside() {
+        for (cw, ch) in [(19u32, 16), (16, 19), (30, 7), (7, 30)] {
+            for (iw, ih) in [(20u32, 5), (5, 20), (31, 2), (2, 31), (24, 3), (3, 24)] {
+                let Fit::Fits {
+                    orientation: Orientation::Angle(degrees),
+                    slack_width,
+                    slack_height,
+                } = fit((cw, ch), (iw, ih))
+                else {
+                    continue;
+                };
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let (iw, ih) = (iw as f64, ih as f64);
+                let width = iw * cos.abs() + ih * sin.abs();
+                let height = iw * sin.abs() + ih * cos.abs();
+                assert!((cw as f64 - width - slack_width).abs() < 1e-6);
+                assert!((ch as f64 - height - slack_height).abs() < 1e-6);
+                assert!(slack_width >= 0.0 && slack_height >= 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn upright_and_rotated_fits_are_preferred() {
+        assert_eq!(
+            fit((10, 4), (3, 2)),
+            Fit::Fits {
+                orientation: Orientation::Upright,
+                slack_width: 7.0,
+                slack_height: 2.0
+            }
+        );
+        assert_eq!(
+            fit((4, 10), (9, 3)),
+            Fit::Fits {
+                orientation: Orientation::Rotated,
+                slack_width: 1.0,
+                slack_height: 1.0
+            }
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use crate::num::Num;
+use crate::rectangle::Rectangle;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// rectangles can be written as text in two ways:
+//
+// - as a size, `10x15` or `10 x 15`, optionally followed by the position
+//   as signed offsets like X11 window geometry: `10x15+3+4`, `10x15-3+4`
+// - as fields, `w=10,h=15` or `width=10, height=15, x=3, y=4`, in any
+//   order. A missing position is the origin
+//
+// `Display` writes the first form, and leaves the offsets out when the
+// rectangle sits at the origin, so that printing and parsing round-trip.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseRectangleError {
+    Empty,
+    // a size without the `x` between width and height
+    MissingSeparator,
+    // offsets that are not two signed numbers, like `10x15+3`
+    InvalidOffset(String),
+    InvalidNumber { field: &'static str, value: String },
+    MissingField(&'static str),
+    DuplicateField(&'static str),
+    UnknownField(String),
+}
+
+impl fmt::Display for ParseRectangleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseRectangleError::Empty => write!(f, "empty rectangle"),
+            ParseRectangleError::MissingSeparator => {
+                write!(f, "expected a size like `10x15`")
+            }
+            ParseRectangleError::InvalidOffset(s) => {
+                write!(f, "invalid position `{}`, expected offsets like `+3+4`", s)
+            }
+            ParseRectangleError::InvalidNumber { field, value } => {
+                write!(f, "invalid {} `{}`", field, value)
+            }
+            ParseRectangleError::MissingField(field) => write!(f, "missing {}", field),
+            ParseRectangleError::DuplicateField(field) => write!(f, "{} given twice", field),
+            ParseRectangleError::UnknownField(field) => write!(f, "unknown field `{}`", field),
+        }
+    }
+}
+
+impl Error for ParseRectangleError {}
+
+impl<T: Num + FromStr> FromStr for Rectangle<T> {
+    type Err = ParseRectangleError;
+
+    fn from_str(s: &str) -> Result<Rectangle<T>, ParseRectangleError> {
+        let s = s.trim();
+        if s.is_empty() {
+            Err(ParseRectangleError::Empty)
+        } else if s.contains('=') {
+            parse_fields(s)
+        } else {
+            parse_size(s)
+        }
+    }
+}
+
+impl<T: Num + fmt::Display> fmt::Display for Rectangle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if self.x != T::ZERO || self.y != T::ZERO {
+            write!(f, "{:+}{:+}", self.x, self.y)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_size<T: Num + FromStr>(s: &str) -> Result<Rectangle<T>, ParseRectangleError> {
+    let (width, rest) = s
+        .split_once(['x', 'X'])
+        .ok_or(ParseRectangleError::MissingSeparator)?;
+
+    let rest = rest.trim_start();
+    let (height, offsets) = match sign_position(rest, 1) {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+
+    let (x, y) = if offsets.is_empty() {
+        (T::ZERO, T::ZERO)
+    } else {
+        let invalid = || ParseRectangleError::InvalidOffset(offsets.to_string());
+        // the second offset starts at the next sign
+        let i = sign_position(offsets, 1).ok_or_else(invalid)?;
+        let (x, y) = offsets.split_at(i);
+        if sign_position(y, 1).is_some() {
+            return Err(invalid());
+        }
+        (number("x", x)?, number("y", y)?)
+    };
+
+    Ok(Rectangle::new(
+        x,
+        y,
+        number("width", width)?,
+        number("height", height)?,
+    ))
+}
+
+// the first `+` or `-` at or after `from` that starts a new number, and is
+// not the sign of an exponent as in `1.5e-3`
+fn sign_position(s: &str, from: usize) -> Option<usize> {
+    let bytes = s.as_bytes();
+    (from..bytes.len())
+        .find(|&i| (bytes[i] == b'+' || bytes[i] == b'-') && !matches!(bytes[i - 1], b'e' | b'E'))
+}
+
+fn parse_fields<T: Num + FromStr>(s: &str) -> Result<Rectangle<T>, ParseRectangleError> {
+    let mut fields: [Option<T>; 4] = [None; 4];
+    const NAMES: [&str; 4] = ["x", "y", "width", "height"];
+
+    for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| ParseRectangleError::UnknownField(pair.to_string()))?;
+        let i = match key.trim() {
+            "x" => 0,
+            "y" => 1,
+            "w" | "width" => 2,
+            "h" | "height" => 3,
+            other => return Err(ParseRectangleError::UnknownField(other.to_string())),
+        };
+        if fields[i].is_some() {
+            return Err(ParseRectangleError::DuplicateField(NAMES[i]));
+        }
+        fields[i] = Some(number(NAMES[i], value)?);
+    }
+
+    Ok(Rectangle::new(
+        fields[0].unwrap_or(T::ZERO),
+        fields[1].unwrap_or(T::ZERO),
+        fields[2].ok_or(ParseRectangleError::MissingField("width"))?,
+        fields[3].ok_or(ParseRectangleError::MissingField("height"))?,
+    ))
+}
+
+fn number<T: FromStr>(field: &'static str, value: &str) -> Result<T, ParseRectangleError> {
+    let value = value.trim();
+    value
+        .parse()
+        .map_err(|_| ParseRectangleError::InvalidNumber {
+            field,
+            value: value.to_string(),
+        })
+}
+
+// errors reading a list of rectangles. Line numbers count from 1 and
+// include the header
+#[derive(Debug)]
+pub enum ImportError {
+    MissingColumn(&'static str),
+    UnknownColumn(String),
+    WrongColumnCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    Row {
+        line: usize,
+        error: ParseRectangleError,
+    },
+    #[cfg(feature = "serde")]
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::MissingColumn(c) => write!(f, "missing column `{}`", c),
+            ImportError::UnknownColumn(c) => write!(f, "unknown column `{}`", c),
+            ImportError::WrongColumnCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} columns, found {}",
+                line, expected, found
+            ),
+            ImportError::Row { line, error } => write!(f, "line {}: {}", line, error),
+            #[cfg(feature = "serde")]
+            ImportError::Json(e) => write!(f, "invalid JSON: {}", e),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+// a header line and one rectangle per line. The x and y columns are only
+// written when at least one rectangle is not at the origin
+pub fn to_csv<T: Num + fmt::Display>(rects: &[Rectangle<T>]) -> String {
+    let positioned = rects.iter().any(|r| r.x != T::ZERO || r.y != T::ZERO);
+    let mut csv = String::from(if positioned {
+        "width,height,x,y\n"
+    } else {
+        "width,height\n"
+    });
+
+    for r in rects {
+        if positioned {
+            csv.push_str(&format!("{},{},{},{}\n", r.width, r.height, r.x, r.y));
+        } else {
+            csv.push_str(&format!("{},{}\n", r.width, r.height));
+        }
+    }
+    csv
+}
+
+// reads what `to_csv` writes. The columns may come in any order, `x` and
+// `y` may be left out, and blank lines are skipped
+pub fn from_csv<T: Num + FromStr>(text: &str) -> Result<Vec<Rectangle<T>>, ImportError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty());
+
+    let header: Vec<&str> = match lines.next() {
+        Some((_, h)) => h.split(',').map(str::trim).collect(),
+        None => return Ok(Vec::new()),
+    };
+
+    // where each field is found in a row
+    let mut columns: [Option<usize>; 4] = [None; 4];
+    for (i, name) in header.iter().enumerate() {
+        let field = match *name {
+            "x" => 0,
+            "y" => 1,
+            "w" | "width" => 2,
+            "h" | "height" => 3,
+            other => return Err(ImportError::UnknownColumn(other.to_string())),
+        };
+        columns[field] = Some(i);
+    }
+    let width = columns[2].ok_or(ImportError::MissingColumn("width"))?;
+    let height = columns[3].ok_or(ImportError::MissingColumn("height"))?;
+
+    let mut rects = Vec::new();
+    for (line, row) in lines {
+        let cells: Vec<&str> = row.split(',').collect();
+        if cells.len() != header.len() {
+            return Err(ImportError::WrongColumnCount {
+                line,
+                expected: header.len(),
+                found: cells.len(),
+            });
+        }
+
+        let cell = |column: Option<usize>, field| match column {
+            Some(c) => number(field, cells[c]),
+            None => Ok(T::ZERO),
+        };
+        let rect = (|| {
+            Ok(Rectangle::new(
+                cell(columns[0], "x")?,
+                cell(columns[1], "y")?,
+                number("width", cells[width])?,
+                number("height", cells[height])?,
+            ))
+        })()
+        .map_err(|error| ImportError::Row { line, error })?;
+        rects.push(rect);
+    }
+
+    Ok(rects)
+}
+
+// JSON goes through serde, which is only compiled in with the `serde`
+// feature. A rectangle is an object with `width` and `height`, and `x`
+// and `y` when it is not at the origin
+#[cfg(feature = "serde")]
+pub fn to_json<T: Num + serde::Serialize>(rects: &[Rectangle<T>]) -> String {
+    // serialising plain numbers cannot fail
+    serde_json::to_string_pretty(rects).unwrap()
+}
+
+#[cfg(feature = "serde")]
+pub fn from_json<T>(text: &str) -> Result<Vec<Rectangle<T>>, ImportError>
+where
+    T: Num + serde::de::DeserializeOwned,
+{
+    serde_json::from_str(text).map_err(ImportError::Json)
+}
+
+// used by the serde attributes on `Rectangle` to leave out the position
+#[cfg(feature = "serde")]
+pub(crate) fn zero<T: Num>() -> T {
+    T::ZERO
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn is_zero<T: Num>(v: &T) -> bool {
+    *v == T::ZERO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn parse(s: &str) -> Result<Rectangle<i32>, ParseRectangleError> {
+        s.parse()
+    }
+
+    fn rect() -> impl Strategy<Value = Rectangle<i32>> {
+        (any::<i32>(), any::<i32>(), any::<i32>(), any::<i32>())
+            .prop_map(|(x, y, w, h)| Rectangle::new(x, y, w, h))
+    }
+
+    #[test]
+    fn both_forms_parse() {
+        let r = Rectangle::new(3, -4, 10, 15);
+        assert_eq!(parse("10x15+3-4"), Ok(r));
+        assert_eq!(parse(" 10 X 15+3-4 "), Ok(r));
+        assert_eq!(parse("width=10, height=15, x=3, y=-4"), Ok(r));
+        assert_eq!(parse("y=-4,h=15,x=3,w=10,"), Ok(r));
+        assert_eq!(parse("10x15"), Ok(Rectangle::new(0, 0, 10, 15)));
+        assert_eq!(parse("w=10,h=15"), Ok(Rectangle::new(0, 0, 10, 15)));
+        assert_eq!(
+            "1.5e-3x2+1e+2-0.5".parse::<Rectangle<f64>>(),
+            Ok(Rectangle::new(100.0, -0.5, 0.0015, 2.0))
+        );
+    }
+
+    #[test]
+    fn errors_say_what_is_wrong() {
+        use ParseRectangleError::*;
+
+        assert_eq!(parse("  "), Err(Empty));
+        assert_eq!(parse("10 15"), Err(MissingSeparator));
+        assert_eq!(parse("10x15+3"), Err(InvalidOffset("+3".to_string())));
+        assert_eq!(
+            parse("10x15+3+4+5"),
+            Err(InvalidOffset("+3+4+5".to_string()))
+        );
+        assert_eq!(
+            parse("10xabc"),
+            Err(InvalidNumber {
+                field: "height",
+                value: "abc".to_string()
+            })
+        );
+        assert_eq!(
+            "-1x2".parse::<Rectangle<u32>>(),
+            Err(InvalidNumber {
+                field: "width",
+                value: "-1".to_string()
+            })
+        );
+        assert_eq!(parse("w=10"), Err(MissingField("height")));
+        assert_eq!(parse("w=10,width=3,h=1"), Err(DuplicateField("width")));
+        assert_eq!(
+            parse("w=10,h=1,depth=3"),
+            Err(UnknownField("depth".to_string()))
+        );
+        assert_eq!(parse("w=10,h"), Err(UnknownField("h".to_string())));
+        assert_eq!(
+            parse("w=10,h=1,x=").unwrap_err().to_string(),
+            "invalid x ``"
+        );
+    }
+
+    #[test]
+    fn csv_errors_carry_the_line() {
+        let err = from_csv::<u32>("width,height\n1,2\n\n3\n").unwrap_err();
+        assert!(matches!(
+            err,
+            ImportError::WrongColumnCount {
+                line: 4,
+                expected: 2,
+                found: 1
+            }
+        ));
+        let err = from_csv::<u32>("height,width\n1,x\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid width `x`");
+        assert!(matches!(
+            from_csv::<u32>("x,y,height\n"),
+            Err(ImportError::MissingColumn("width"))
+        ));
+        assert!(matches!(
+            from_csv::<u32>("width,height,depth\n"),
+            Err(ImportError::UnknownColumn(c)) if c == "depth"
+        ));
+        assert!(from_csv::<u32>("\n  \n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn csv_leaves_out_the_position_at_the_origin() {
+        let rects = [Rectangle::new(0u32, 0, 3, 4), Rectangle::new(0, 0, 5, 6)];
+        assert_eq!(to_csv(&rects), "width,height\n3,4\n5,6\n");
+        let moved = [rects[0], Rectangle::new(1, 2, 5, 6)];
+        assert_eq!(to_csv(&moved), "width,height,x,y\n3,4,0,0\n5,6,1,2\n");
+    }
+
+    proptest! {
+        #[test]
+        fn display_parses_back(r in rect()) {
+            prop_assert_eq!(parse(&r.to_string()), Ok(r));
+        }
+
+        #[test]
+        fn unsigned_display_parses_back(x: u32, y: u32, w: u32, h: u32) {
+            let r = Rectangle::new(x, y, w, h);
+            prop_assert_eq!(r.to_string().parse::<Rectangle<u32>>(), Ok(r));
+        }
+
+        #[test]
+        fn csv_round_trips(rects in prop::collection::vec(rect(), 0..20)) {
+            prop_assert_eq!(from_csv::<i32>(&to_csv(&rects)).unwrap(), rects);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trips_and_leaves_out_the_origin() {
+        let rects = vec![Rectangle::new(0u32, 0, 3, 4), Rectangle::new(1, 2, 5, 6)];
+        let json = to_json(&rects);
+        assert_eq!(from_json::<u32>(&json).unwrap(), rects);
+
+        let first: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(first[0], serde_json::json!({"width": 3, "height": 4}));
+        assert_eq!(
+            from_json::<u32>(r#"[{"width": 3, "height": 4, "y": 7}]"#).unwrap(),
+            vec![Rectangle::new(0, 7, 3, 4)]
+        );
+        assert!(matches!(
+            from_json::<u32>(r#"[{"width": 3}]"#),
+            Err(ImportError::Json(_))
+        ));
+        assert!(matches!(
+            from_json::<u32>(r#"[{"width": -3, "height": 1}]"#),
+            Err(ImportError::Json(_))
+        ));
+    }
+}
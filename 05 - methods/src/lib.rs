@@ -0,0 +1,17 @@
+// the geometry lives in the library crate so it can be shared by the
+// binary in main.rs and by anything else that depends on it
+pub mod coverage;
+pub mod fit;
+pub mod formats;
+pub mod num;
+pub mod packing;
+pub mod rectangle;
+pub mod render;
+pub mod shape;
+pub mod spatial;
+
+pub use crate::fit::{Axis, Fit, Orientation};
+pub use crate::formats::{ImportError, ParseRectangleError};
+pub use crate::num::Num;
+pub use crate::rectangle::Rectangle;
+pub use crate::shape::Shape;
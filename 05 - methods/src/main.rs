@@ -1,29 +1,11 @@
-#[derive(Debug)]
-struct Rectangle {
-    width: u32,
-    height: u32,
-}
-
-impl Rectangle {
-    // first parameter is always self, which represents the instance of the struct 
-    fn area(&self) -> u32 {
-        self.width * self.height
-    }
-
-    fn can_hold(&self, other: &Rectangle) -> bool {
-        self.width >= other.width && self.height >= other.height
-    }
-
-    // associated functions
-    // not methods, often used for constructors returning
-    // a new instance of a struct
-    fn square(size: u32) -> Rectangle {
-        Rectangle {
-            width: size,
-            height: size,
-        }
-    }
-}
+use methods::coverage;
+use methods::formats;
+use methods::packing::{self, Algorithm};
+use methods::render::{self, Drawing};
+use methods::shape::{Circle, Ellipse, Point, Polygon, Triangle};
+use methods::spatial::SpatialIndex;
+use methods::{Rectangle, Shape};
+use std::env;
 
 fn main() {
     let rect1 = create_rect(10, 15);
@@ -41,10 +23,182 @@ fn main() {
     // associated functions are called using `::`
     let sqr = Rectangle::square(5);
     println!("Created a square: {:#?}", sqr);
+
+    // methods can also return new instances, leaving `self` untouched
+    let moved = rect2.translate(5, 5);
+    println!("rect2 moved by (5, 5): {:?}", moved);
+    println!("rect1 and moved overlap: {:?}", rect1.intersection(&moved));
+    println!("Both fit in: {:?}", rect1.union(&moved));
+    println!("Is (12, 12) inside moved? {}", moved.contains_point(12, 12));
+    println!("rect1 shrunk by 2 on every side: {:?}", rect1.inset(2));
+    println!("rect1 contains moved? {}", rect1.contains(&moved));
+
+    // `Rectangle` is generic over its coordinate type, `u32` by default
+    let huge = Rectangle::square(u32::MAX);
+    println!(
+        "Area of the largest u32 square: checked {:?}, widened {}, perimeter {}",
+        huge.checked_area(),
+        huge.wide_area(),
+        huge.perimeter()
+    );
+    let signed = Rectangle::new(-5i64, -5, 10, 10);
+    println!("A rectangle around the origin: {:?}", signed);
+    let float = Rectangle::square(2.5f64);
+    println!("A float square with area {}", float.area());
+
+    // `can_hold` only compares sizes as they are. The fit queries can
+    // turn the item and explain the answer
+    let tall = create_rect(3, 10);
+    let wide = create_rect(10, 3);
+    println!("Can tall hold wide? {}", tall.can_hold(&wide));
+    println!("Upright: {:?}", tall.fit(&wide));
+    println!("Turned by 90°: {:?}", tall.fit_rotated(&wide));
+
+    // a plank that is too long for the box either way fits diagonally
+    let case = create_rect(100, 40);
+    let plank = create_rect(104, 4);
+    println!("At an angle: {:?}", case.fit_at_angle(&plank));
+
+    // packing places a list of rectangles inside a container
+    let sheet = create_rect(20, 20);
+    let parts = [
+        create_rect(12, 8),
+        create_rect(8, 12),
+        create_rect(10, 10),
+        create_rect(6, 6),
+        create_rect(20, 3),
+    ];
+    for algorithm in [Algorithm::Shelf, Algorithm::Guillotine, Algorithm::MaxRects] {
+        let result = packing::pack(algorithm, &parts, &sheet, true);
+        println!(
+            "{:?}: {} placed, unplaced {:?}, {:.1}% used",
+            algorithm,
+            result.placed.len(),
+            result.unplaced,
+            result.utilisation()
+        );
+    }
+
+    // printing with {:?} says little about the layout, drawing it does.
+    // Pass a path to also write the layout as an SVG file
+    let layout = packing::pack(Algorithm::MaxRects, &parts, &sheet, true);
+    let drawing = Drawing::packing(&layout);
+    print!("{}", drawing.to_ascii(render::terminal_width().min(40)));
+    if let Some(path) = env::args().nth(1) {
+        match drawing.save_svg(&path) {
+            Ok(()) => println!("Layout written to {}", path),
+            Err(e) => eprintln!("Cannot write {}: {}", path, e),
+        }
+    }
+
+    // a spatial index answers "what is here?" without comparing against
+    // every rectangle
+    let index = SpatialIndex::bulk_load(vec![
+        ("rect1", rect1),
+        ("rect2", rect2),
+        ("moved", moved),
+        ("square", sqr.translate(30, 30)),
+    ]);
+    println!("At (12, 12): {:?}", index.query_point(12, 12));
+    println!("Nearest to (40, 0): {:?}", index.nearest(40, 0, 1));
+    println!("Overlapping: {:?}", index.overlapping_pairs());
+
+    // summing the areas counts overlapping parts twice
+    let overlapping = [rect1, rect2, moved];
+    let summed: u32 = overlapping.iter().map(|r| r.area()).sum();
+    println!(
+        "Summed area {}, covered area {}, covered twice or more {}",
+        summed,
+        coverage::union_area(&overlapping),
+        coverage::area_covered_at_least(&overlapping, 2)
+    );
+    for outline in coverage::union_outline(&overlapping) {
+        println!("Outline of the union: {:?}", outline.vertices);
+    }
+
+    // `parse` works for any type implementing `FromStr`, and `Display`
+    // writes the same form back
+    for text in [
+        "10x15",
+        "10 x 15",
+        "w=10,h=15",
+        "10x15+3-4",
+        "10 by 15",
+        "w=10",
+    ] {
+        match text.parse::<Rectangle<i64>>() {
+            Ok(r) => println!("Parsed `{}` as {:?}, printed as {}", text, r, r),
+            Err(e) => println!("Cannot parse `{}`: {}", text, e),
+        }
+    }
+    let csv = formats::to_csv(&[rect1, moved]);
+    print!("As CSV:\n{}", csv);
+    match formats::from_csv::<u32>(&csv) {
+        Ok(rects) => println!("Read back: {:?}", rects),
+        Err(e) => println!("Cannot read CSV: {}", e),
+    }
+    #[cfg(feature = "serde")]
+    println!("As JSON: {}", formats::to_json(&[rect1, moved]));
+
+    // traits let different types answer the same questions. A `&dyn Shape`
+    // can be any of them
+    let shapes: Vec<(&str, Box<dyn Shape>)> = vec![
+        ("rect1", Box::new(rect1)),
+        (
+            "circle",
+            Box::new(Circle {
+                centre: Point::new(5.0, 5.0),
+                radius: 4.0,
+            }),
+        ),
+        (
+            "ellipse",
+            Box::new(Ellipse {
+                centre: Point::new(5.0, 7.5),
+                radius_x: 5.0,
+                radius_y: 7.5,
+            }),
+        ),
+        (
+            "triangle",
+            Box::new(Triangle {
+                a: Point::new(1.0, 1.0),
+                b: Point::new(9.0, 1.0),
+                c: Point::new(5.0, 8.0),
+            }),
+        ),
+        (
+            "arrow",
+            Box::new(Polygon::new(vec![
+                Point::new(0.0, 0.0),
+                Point::new(10.0, 5.0),
+                Point::new(0.0, 10.0),
+                Point::new(4.0, 5.0),
+            ])),
+        ),
+    ];
+    for (name, shape) in &shapes {
+        let c = shape.centroid();
+        println!(
+            "{}: area {:.2}, perimeter {:.2}, centroid ({:.2}, {:.2}), convex {}",
+            name,
+            shape.area(),
+            shape.perimeter(),
+            c.x,
+            c.y,
+            shape.is_convex()
+        );
+    }
+    for (outer, a) in &shapes {
+        let inside: Vec<&str> = shapes
+            .iter()
+            .filter(|(inner, b)| inner != outer && a.contains_shape(b.as_ref()))
+            .map(|(inner, _)| *inner)
+            .collect();
+        println!("{} contains {:?}", outer, inside);
+    }
 }
 
 fn create_rect(width: u32, height: u32) -> Rectangle {
-    let rect = Rectangle { width, height };
-
-    rect
+    Rectangle::new(0, 0, width, height)
 }
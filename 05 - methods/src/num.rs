@@ -0,0 +1,149 @@
+use std::fmt::Debug;
+use std::ops::{Add, Div, Mul, Sub};
+
+// the numeric operations a `Rectangle` needs from its coordinate type.
+// Integers and floats differ a lot (overflow, NaN, no negative values
+// for unsigned types), so rather than pulling in a crate like num-traits
+// the few operations needed are spelled out here and implemented for the
+// supported types by the macros below.
+pub trait Num:
+    Copy
+    + PartialOrd
+    + Debug
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    // a type large enough to hold the product of any two values and the
+    // sum of any four, so area and perimeter never overflow in it
+    type Wide: Copy
+        + PartialOrd
+        + Debug
+        + Add<Output = Self::Wide>
+        + Sub<Output = Self::Wide>
+        + Mul<Output = Self::Wide>
+        + Div<Output = Self::Wide>;
+
+    // a signed type for moving a value in either direction. For unsigned
+    // integers this is what lets a coordinate go down as well as up
+    type Delta: Copy + Debug;
+
+    const ZERO: Self;
+    const ONE: Self;
+
+    // `None` on overflow. For floats a result that is not finite counts
+    // as an overflow
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+
+    // clamps at the bounds of the type instead of overflowing
+    fn saturating_add(self, other: Self) -> Self;
+    fn saturating_mul(self, other: Self) -> Self;
+    fn saturating_offset(self, delta: Self::Delta) -> Self;
+
+    fn widen(self) -> Self::Wide;
+    // the opposite of `widen`, values out of range are clamped
+    fn narrow(wide: Self::Wide) -> Self;
+
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! impl_int {
+    ($($t:ty => $wide:ty, $delta:ty),*) => {
+        $(
+            impl Num for $t {
+                type Wide = $wide;
+                type Delta = $delta;
+
+                const ZERO: $t = 0;
+                const ONE: $t = 1;
+
+                fn checked_add(self, other: $t) -> Option<$t> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn checked_mul(self, other: $t) -> Option<$t> {
+                    <$t>::checked_mul(self, other)
+                }
+
+                fn saturating_add(self, other: $t) -> $t {
+                    <$t>::saturating_add(self, other)
+                }
+
+                fn saturating_mul(self, other: $t) -> $t {
+                    <$t>::saturating_mul(self, other)
+                }
+
+                // i128 holds every value and delta of the supported types
+                fn saturating_offset(self, delta: $delta) -> $t {
+                    (self as i128)
+                        .saturating_add(delta as i128)
+                        .clamp(<$t>::MIN as i128, <$t>::MAX as i128) as $t
+                }
+
+                fn widen(self) -> $wide {
+                    self as $wide
+                }
+
+                fn narrow(wide: $wide) -> $t {
+                    wide.clamp(<$t>::MIN as $wide, <$t>::MAX as $wide) as $t
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_float {
+    ($($t:ty => $wide:ty),*) => {
+        $(
+            impl Num for $t {
+                type Wide = $wide;
+                type Delta = $t;
+
+                const ZERO: $t = 0.0;
+                const ONE: $t = 1.0;
+
+                fn checked_add(self, other: $t) -> Option<$t> {
+                    Some(self + other).filter(|v| v.is_finite())
+                }
+
+                fn checked_mul(self, other: $t) -> Option<$t> {
+                    Some(self * other).filter(|v| v.is_finite())
+                }
+
+                // floats saturate at infinity on their own
+                fn saturating_add(self, other: $t) -> $t {
+                    self + other
+                }
+
+                fn saturating_mul(self, other: $t) -> $t {
+                    self * other
+                }
+
+                fn saturating_offset(self, delta: $t) -> $t {
+                    self + delta
+                }
+
+                fn widen(self) -> $wide {
+                    self as $wide
+                }
+
+                fn narrow(wide: $wide) -> $t {
+                    wide as $t
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(u32 => u64, i64, u64 => u128, i128, i32 => i64, i64, i64 => i128, i128);
+impl_float!(f32 => f64, f64 => f64);
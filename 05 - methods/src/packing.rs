@@ -0,0 +1,395 @@
+use crate::rectangle::Rectangle;
+
+// 2D bin packing: place as many items as possible inside a container
+// without overlaps. Finding the best packing is NP-hard, so all three
+// algorithms are heuristics trading speed for how tightly they pack:
+//
+// - Shelf: items are lined up on horizontal shelves. Very fast, wastes
+//   the space above items that are lower than their shelf.
+// - Guillotine: free space is kept as a list of disjoint rectangles that
+//   are cut in two after every placement, like a sheet being cut edge to
+//   edge. This is the only one whose layouts can be cut with a saw.
+// - MaxRects: free space is kept as the list of all maximal empty
+//   rectangles (which overlap each other). Slowest, packs the tightest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Shelf,
+    Guillotine,
+    MaxRects,
+}
+
+// where an item ended up. `rect` is the item's position and size inside
+// the container, with width and height already swapped when `rotated`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    // index of the item in the list that was packed
+    pub index: usize,
+    pub rect: Rectangle,
+    pub rotated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packing {
+    pub container: Rectangle,
+    pub placed: Vec<Placement>,
+    // indices of the items that did not fit, in input order
+    pub unplaced: Vec<usize>,
+}
+
+impl Packing {
+    // the share of the container covered by placed items, in percent
+    pub fn utilisation(&self) -> f64 {
+        let total = self.container.wide_area();
+        if total == 0 {
+            return 0.0;
+        }
+
+        let used: u64 = self.placed.iter().map(|p| p.rect.wide_area()).sum();
+        used as f64 / total as f64 * 100.0
+    }
+}
+
+// packs `items` into `container`. Only the sizes of the items matter, the
+// placements are positioned relative to the grid the container is on.
+//
+// a container can reach past the end of the u32 grid (x + width above
+// u32::MAX). Positions out there cannot be written down, so that part of
+// it is never used
+pub fn pack(
+    algorithm: Algorithm,
+    items: &[Rectangle],
+    container: &Rectangle,
+    allow_rotation: bool,
+) -> Packing {
+    // every heuristic packs better when the big items go first
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    match algorithm {
+        Algorithm::Shelf => order.sort_by_key(|&i| {
+            let r = &items[i];
+            let h = if allow_rotation {
+                r.width.min(r.height)
+            } else {
+                r.height
+            };
+            std::cmp::Reverse(h)
+        }),
+        _ => order.sort_by_key(|&i| std::cmp::Reverse(items[i].wide_area())),
+    }
+
+    let mut packer: Box<dyn Packer> = match algorithm {
+        Algorithm::Shelf => Box::new(Shelf::new(container)),
+        Algorithm::Guillotine => Box::new(Guillotine::new(container)),
+        Algorithm::MaxRects => Box::new(MaxRects::new(container)),
+    };
+
+    let mut packing = Packing {
+        container: *container,
+        placed: Vec::new(),
+        unplaced: Vec::new(),
+    };
+
+    for index in order {
+        let item = &items[index];
+        if item.is_empty() {
+            // takes no room, so it fits anywhere
+            packing.placed.push(Placement {
+                index,
+                rect: Rectangle::new(container.x, container.y, 0, 0),
+                rotated: false,
+            });
+            continue;
+        }
+
+        match packer.insert(item.width, item.height, allow_rotation) {
+            Some((rect, rotated)) => packing.placed.push(Placement {
+                index,
+                rect,
+                rotated,
+            }),
+            None => packing.unplaced.push(index),
+        }
+    }
+
+    packing.unplaced.sort_unstable();
+    packing
+}
+
+// the part that differs between algorithms: find a spot for one item and
+// update the free space. Returns the placed rectangle and whether it was
+// turned
+trait Packer {
+    fn insert(
+        &mut self,
+        width: u32,
+        height: u32,
+        allow_rotation: bool,
+    ) -> Option<(Rectangle, bool)>;
+}
+
+// the sizes to try for an item, upright first
+fn orientations(width: u32, height: u32, allow_rotation: bool) -> Vec<(u32, u32, bool)> {
+    let mut sizes = vec![(width, height, false)];
+    if allow_rotation && width != height {
+        sizes.push((height, width, true));
+    }
+    sizes
+}
+
+struct Shelf {
+    container: Rectangle,
+    // (top, height, used width) of every opened shelf
+    shelves: Vec<(u32, u32, u32)>,
+}
+
+impl Shelf {
+    fn new(container: &Rectangle) -> Shelf {
+        Shelf {
+            container: *container,
+            shelves: Vec::new(),
+        }
+    }
+}
+
+impl Packer for Shelf {
+    fn insert(
+        &mut self,
+        width: u32,
+        height: u32,
+        allow_rotation: bool,
+    ) -> Option<(Rectangle, bool)> {
+        let c = self.container;
+
+        // lying flat keeps new shelves low, so try that first
+        let mut sizes = orientations(width, height, allow_rotation);
+        sizes.sort_by_key(|&(_, h, _)| h);
+
+        // first fit on an existing shelf
+        for &(w, h, rotated) in &sizes {
+            for shelf in self.shelves.iter_mut() {
+                let (top, shelf_height, used) = *shelf;
+                let (Some(x), Some(y)) = (c.x.checked_add(used), c.y.checked_add(top)) else {
+                    continue;
+                };
+                if h <= shelf_height && w <= c.width - used {
+                    shelf.2 += w;
+                    return Some((Rectangle::new(x, y, w, h), rotated));
+                }
+            }
+        }
+
+        // otherwise open a new shelf above the last one
+        let top = self.shelves.last().map_or(0, |&(t, h, _)| t + h);
+        let y = c.y.checked_add(top)?;
+        for &(w, h, rotated) in &sizes {
+            if w <= c.width && h <= c.height - top {
+                self.shelves.push((top, h, w));
+                return Some((Rectangle::new(c.x, y, w, h), rotated));
+            }
+        }
+
+        None
+    }
+}
+
+struct Guillotine {
+    free: Vec<Rectangle>,
+}
+
+impl Guillotine {
+    fn new(container: &Rectangle) -> Guillotine {
+        Guillotine {
+            free: vec![*container],
+        }
+    }
+}
+
+impl Packer for Guillotine {
+    fn insert(
+        &mut self,
+        width: u32,
+        height: u32,
+        allow_rotation: bool,
+    ) -> Option<(Rectangle, bool)> {
+        // best area fit: the free rectangle with the least area left over
+        let mut best: Option<(u64, usize, u32, u32, bool)> = None;
+        for (i, free) in self.free.iter().enumerate() {
+            for (w, h, rotated) in orientations(width, height, allow_rotation) {
+                if free.can_hold(&Rectangle::new(0, 0, w, h)) {
+                    let waste = free.wide_area() - w as u64 * h as u64;
+                    if best.is_none_or(|b| waste < b.0) {
+                        best = Some((waste, i, w, h, rotated));
+                    }
+                }
+            }
+        }
+
+        let (_, i, w, h, rotated) = best?;
+        let free = self.free.swap_remove(i);
+
+        // one straight cut through the rest of the free rectangle. Cutting
+        // along the shorter leftover side keeps the bigger piece whole
+        let right_w = free.width - w;
+        let bottom_h = free.height - h;
+        let (right_h, bottom_w) = if right_w < bottom_h {
+            (h, free.width)
+        } else {
+            (free.height, w)
+        };
+        // a piece that starts past the end of the grid is dropped
+        let right = free
+            .x
+            .checked_add(w)
+            .map(|x| Rectangle::new(x, free.y, right_w, right_h));
+        let bottom = free
+            .y
+            .checked_add(h)
+            .map(|y| Rectangle::new(free.x, y, bottom_w, bottom_h));
+        self.free.extend(
+            [right, bottom]
+                .into_iter()
+                .flatten()
+                .filter(|r| !r.is_empty()),
+        );
+
+        Some((Rectangle::new(free.x, free.y, w, h), rotated))
+    }
+}
+
+struct MaxRects {
+    free: Vec<Rectangle>,
+}
+
+impl MaxRects {
+    fn new(container: &Rectangle) -> MaxRects {
+        MaxRects {
+            free: vec![*container],
+        }
+    }
+}
+
+impl Packer for MaxRects {
+    fn insert(
+        &mut self,
+        width: u32,
+        height: u32,
+        allow_rotation: bool,
+    ) -> Option<(Rectangle, bool)> {
+        // best short side fit: the spot where the item leaves the smallest
+        // gap along one of its sides, ties broken by the longer gap
+        let mut best: Option<((u32, u32), Rectangle, bool)> = None;
+        for free in &self.free {
+            for (w, h, rotated) in orientations(width, height, allow_rotation) {
+                if free.can_hold(&Rectangle::new(0, 0, w, h)) {
+                    let (dw, dh) = (free.width - w, free.height - h);
+                    let score = (dw.min(dh), dw.max(dh));
+                    if best.is_none_or(|b| score < b.0) {
+                        best = Some((score, Rectangle::new(free.x, free.y, w, h), rotated));
+                    }
+                }
+            }
+        }
+
+        let (_, placed, rotated) = best?;
+
+        // every free rectangle the item overlaps is replaced by the (up to
+        // four) maximal rectangles around the item
+        let mut free = Vec::with_capacity(self.free.len() + 4);
+        for r in &self.free {
+            if r.intersects(&placed) {
+                free.extend(split(r, &placed));
+            } else {
+                free.push(*r);
+            }
+        }
+
+        // drop free rectangles that are inside another one. Of two equal
+        // ones only the first is kept
+        let mut maximal: Vec<Rectangle> = Vec::with_capacity(free.len());
+        for (i, r) in free.iter().enumerate() {
+            let covered = free
+                .iter()
+                .enumerate()
+                .any(|(j, other)| i != j && other.contains(r) && (other != r || j < i));
+            if !covered {
+                maximal.push(*r);
+            }
+        }
+        self.free = maximal;
+
+        Some((placed, rotated))
+    }
+}
+
+// the parts of `free` left, right, above and below `used`
+fn split(free: &Rectangle, used: &Rectangle) -> Vec<Rectangle> {
+    let mut parts = Vec::with_capacity(4);
+
+    if used.x > free.x {
+        parts.push(Rectangle::new(free.x, free.y, used.x - free.x, free.height));
+    }
+    // the parts right of and below `used` only exist when they start on
+    // the grid
+    if let (true, Ok(x)) = (used.right() < free.right(), u32::try_from(used.right())) {
+        parts.push(Rectangle::new(
+            x,
+            free.y,
+            (free.right() - used.right()) as u32,
+            free.height,
+        ));
+    }
+    if used.y > free.y {
+        parts.push(Rectangle::new(free.x, free.y, free.width, used.y - free.y));
+    }
+    if let (true, Ok(y)) = (used.bottom() < free.bottom(), u32::try_from(used.bottom())) {
+        parts.push(Rectangle::new(
+            free.x,
+            y,
+            free.width,
+            (free.bottom() - used.bottom()) as u32,
+        ));
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [Algorithm; 3] =
+        [Algorithm::Shelf, Algorithm::Guillotine, Algorithm::MaxRects];
+
+    fn check(packing: &Packing) {
+        for (i, a) in packing.placed.iter().enumerate() {
+            assert!(packing.container.contains(&a.rect), "{:?} is outside", a);
+            for b in &packing.placed[i + 1..] {
+                assert!(!a.rect.intersects(&b.rect), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn containers_at_the_end_of_the_grid_do_not_overflow() {
+        let items: Vec<Rectangle> = (1..20)
+            .map(|i| Rectangle::new(0, 0, i * 7, 30 - i))
+            .collect();
+        let container = Rectangle::new(u32::MAX - 50, u32::MAX - 50, 1000, 1000);
+        for algorithm in ALGORITHMS {
+            let packing = pack(algorithm, &items, &container, true);
+            check(&packing);
+            assert!(!packing.placed.is_empty());
+        }
+    }
+
+    #[test]
+    fn items_that_fit_exactly_are_all_placed() {
+        let items = vec![Rectangle::new(0, 0, 5, 5); 4];
+        let container = Rectangle::new(3, 4, 10, 10);
+        for algorithm in ALGORITHMS {
+            let packing = pack(algorithm, &items, &container, false);
+            check(&packing);
+            assert!(packing.unplaced.is_empty(), "{:?}", algorithm);
+            assert_eq!(packing.utilisation(), 100.0);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use crate::num::Num;
+
+// a rectangle is placed on a grid by its top-left corner (x, y) and grows
+// to the right and down. The area it covers is half-open: a point on the
+// right or bottom edge belongs to the next rectangle, so rectangles that
+// share an edge do not overlap.
+//
+// the coordinate type is generic (see `Num`) and is used for the position
+// as well as the size. It defaults to u32, so a plain `Rectangle` cannot
+// sit left of or above (0, 0), but it can still be moved left and up:
+// `translate` takes a signed delta (`Num::Delta`) and stops at 0. Code
+// that needs negative positions uses a signed type, `Rectangle<i64>` holds
+// every u32 rectangle and `From` converts to it.
+//
+// a rectangle with zero (or negative) width or height is empty. It still
+// has a position (it is a point or a line segment) but covers no points,
+// intersects nothing and is ignored by `union`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "T: Num + serde::Serialize",
+        deserialize = "T: Num + serde::Deserialize<'de>"
+    ))
+)]
+pub struct Rectangle<T = u32> {
+    // the position is optional in serialised form
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default = "crate::formats::zero",
+            skip_serializing_if = "crate::formats::is_zero"
+        )
+    )]
+    pub x: T,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default = "crate::formats::zero",
+            skip_serializing_if = "crate::formats::is_zero"
+        )
+    )]
+    pub y: T,
+    pub width: T,
+    pub height: T,
+}
+
+impl<T: Num> Rectangle<T> {
+    // first parameter is always self, which represents the instance of the struct
+    //
+    // the product of two values can overflow `T`, so the plain area clamps
+    // at the largest value instead of panicking (debug) or wrapping
+    // (release). `checked_area` and `wide_area` report it exactly
+    pub fn area(&self) -> T {
+        if self.is_empty() {
+            return T::ZERO;
+        }
+        self.width.saturating_mul(self.height)
+    }
+
+    // `None` when the area does not fit in `T`
+    pub fn checked_area(&self) -> Option<T> {
+        if self.is_empty() {
+            return Some(T::ZERO);
+        }
+        self.width.checked_mul(self.height)
+    }
+
+    // the area in the wider type (u32 -> u64, i64 -> i128, f32 -> f64,
+    // ...) where it always fits
+    pub fn wide_area(&self) -> T::Wide {
+        if self.is_empty() {
+            return T::ZERO.widen();
+        }
+        self.width.widen() * self.height.widen()
+    }
+
+    // also computed in the wider type, so it cannot overflow either
+    pub fn perimeter(&self) -> T::Wide {
+        let (w, h) = (self.width.widen(), self.height.widen());
+        w + w + h + h
+    }
+
+    // compares sizes only, the positions are ignored
+    pub fn can_hold(&self, other: &Rectangle<T>) -> bool {
+        self.width >= other.width && self.height >= other.height
+    }
+
+    // associated functions
+    // not methods, often used for constructors returning
+    // a new instance of a struct
+    pub fn square(size: T) -> Rectangle<T> {
+        Rectangle {
+            x: T::ZERO,
+            y: T::ZERO,
+            width: size,
+            height: size,
+        }
+    }
+
+    pub fn new(x: T, y: T, width: T, height: T) -> Rectangle<T> {
+        Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // written as a negation so NaN sizes count as empty too
+    pub fn is_empty(&self) -> bool {
+        !(self.width > T::ZERO && self.height > T::ZERO)
+    }
+
+    // the far edges can lie outside the range of `T` (x + width), so
+    // edge arithmetic is done in the wider type where it cannot overflow
+    pub fn left(&self) -> T::Wide {
+        self.x.widen()
+    }
+
+    pub fn top(&self) -> T::Wide {
+        self.y.widen()
+    }
+
+    pub fn right(&self) -> T::Wide {
+        self.x.widen() + self.width.widen()
+    }
+
+    pub fn bottom(&self) -> T::Wide {
+        self.y.widen() + self.height.widen()
+    }
+
+    pub fn contains_point(&self, x: T, y: T) -> bool {
+        let (x, y) = (x.widen(), y.widen());
+        self.left() <= x && x < self.right() && self.top() <= y && y < self.bottom()
+    }
+
+    // true when every point of `other` is inside `self`. An empty
+    // rectangle has no points, so any rectangle contains it
+    pub fn contains(&self, other: &Rectangle<T>) -> bool {
+        other.is_empty()
+            || (self.left() <= other.left()
+                && other.right() <= self.right()
+                && self.top() <= other.top()
+                && other.bottom() <= self.bottom())
+    }
+
+    // true when the two rectangles share at least one point. Touching
+    // edges do not count
+    pub fn intersects(&self, other: &Rectangle<T>) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    // the overlapping part of both rectangles, `None` if there is none
+    pub fn intersection(&self, other: &Rectangle<T>) -> Option<Rectangle<T>> {
+        let left = max(self.left(), other.left());
+        let top = max(self.top(), other.top());
+        let right = min(self.right(), other.right());
+        let bottom = min(self.bottom(), other.bottom());
+
+        if left < right && top < bottom {
+            Some(from_edges(left, top, right, bottom))
+        } else {
+            None
+        }
+    }
+
+    // the smallest rectangle covering both. Empty rectangles are skipped,
+    // and the union of two empty rectangles is `self`
+    pub fn union(&self, other: &Rectangle<T>) -> Rectangle<T> {
+        if other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return *other;
+        }
+
+        from_edges(
+            min(self.left(), other.left()),
+            min(self.top(), other.top()),
+            max(self.right(), other.right()),
+            max(self.bottom(), other.bottom()),
+        )
+    }
+
+    // the bounding box of a whole collection, `None` if every rectangle
+    // in it is empty (or there are none)
+    pub fn bounding_box<'a, I>(rects: I) -> Option<Rectangle<T>>
+    where
+        I: IntoIterator<Item = &'a Rectangle<T>>,
+        T: 'a,
+    {
+        rects
+            .into_iter()
+            .filter(|r| !r.is_empty())
+            .fold(None, |acc: Option<Rectangle<T>>, r| match acc {
+                Some(b) => Some(b.union(r)),
+                None => Some(*r),
+            })
+    }
+
+    // moves the rectangle, saturating at the bounds of `T`. The delta is
+    // signed even when `T` is not
+    pub fn translate(&self, dx: T::Delta, dy: T::Delta) -> Rectangle<T> {
+        Rectangle {
+            x: self.x.saturating_offset(dx),
+            y: self.y.saturating_offset(dy),
+            ..*self
+        }
+    }
+
+    // multiplies the size, keeping the top-left corner in place
+    pub fn scale(&self, sx: T, sy: T) -> Rectangle<T> {
+        Rectangle {
+            width: self.width.saturating_mul(sx),
+            height: self.height.saturating_mul(sy),
+            ..*self
+        }
+    }
+
+    // moves every edge inwards by `amount`. With a signed type a negative
+    // amount grows the rectangle instead. When the edges would cross, the
+    // result is an empty rectangle at the centre
+    pub fn inset(&self, amount: T) -> Rectangle<T> {
+        let a = amount.widen();
+        let two = T::ONE.widen() + T::ONE.widen();
+        let (w, h) = (self.width.widen(), self.height.widen());
+
+        // comparing before subtracting keeps unsigned types from
+        // underflowing when the amount is larger than the rectangle
+        let (left, right) = if a + a < w {
+            (self.left() + a, self.right() - a)
+        } else {
+            let centre = self.left() + w / two;
+            (centre, centre)
+        };
+        let (top, bottom) = if a + a < h {
+            (self.top() + a, self.bottom() - a)
+        } else {
+            let centre = self.top() + h / two;
+            (centre, centre)
+        };
+
+        from_edges(left, top, right, bottom)
+    }
+}
+
+// `Ord::min`/`max` are not available for floats, `PartialOrd` is enough
+// here since NaN edges only come from NaN rectangles
+fn min<W: PartialOrd>(a: W, b: W) -> W {
+    if b < a {
+        b
+    } else {
+        a
+    }
+}
+
+fn max<W: PartialOrd>(a: W, b: W) -> W {
+    if b > a {
+        b
+    } else {
+        a
+    }
+}
+
+// builds a rectangle from its edges, clamping anything that falls outside
+// the range of `T`
+fn from_edges<T: Num>(
+    left: T::Wide,
+    top: T::Wide,
+    right: T::Wide,
+    bottom: T::Wide,
+) -> Rectangle<T> {
+    let x = T::narrow(left);
+    let y = T::narrow(top);
+
+    Rectangle {
+        x,
+        y,
+        width: T::narrow(max(right, x.widen()) - x.widen()),
+        height: T::narrow(max(bottom, y.widen()) - y.widen()),
+    }
+}
+
+// every u32 position and size fits in an i64, so this loses nothing
+impl From<Rectangle<u32>> for Rectangle<u64> {
+    fn from(r: Rectangle<u32>) -> Rectangle<u64> {
+        Rectangle::new(r.x as u64, r.y as u64, r.width as u64, r.height as u64)
+    }
+}
+
+impl From<Rectangle<u32>> for Rectangle<i64> {
+    fn from(r: Rectangle<u32>) -> Rectangle<i64> {
+        Rectangle::new(r.x as i64, r.y as i64, r.width as i64, r.height as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // small coordinates so that overlaps are common, and sizes that can
+    // be zero so that empty rectangles come up too
+    fn rect() -> impl Strategy<Value = Rectangle<i32>> {
+        (-20i32..20, -20i32..20, 0i32..15, 0i32..15)
+            .prop_map(|(x, y, w, h)| Rectangle::new(x, y, w, h))
+    }
+
+    proptest! {
+        #[test]
+        fn intersection_is_commutative(a in rect(), b in rect()) {
+            prop_assert_eq!(a.intersection(&b), b.intersection(&a));
+            prop_assert_eq!(a.intersects(&b), b.intersects(&a));
+        }
+
+        #[test]
+        fn intersection_is_inside_both(a in rect(), b in rect()) {
+            if let Some(i) = a.intersection(&b) {
+                prop_assert!(!i.is_empty());
+                prop_assert!(a.contains(&i) && b.contains(&i));
+            }
+        }
+
+        #[test]
+        fn union_contains_both(a in rect(), b in rect()) {
+            let u = a.union(&b);
+            prop_assert!(u.contains(&a));
+            prop_assert!(u.contains(&b));
+        }
+
+        #[test]
+        fn contains_implies_intersects(a in rect(), b in rect()) {
+            if a.contains(&b) && !b.is_empty() {
+                prop_assert!(a.intersects(&b));
+            }
+        }
+
+        #[test]
+        fn points_of_the_intersection_are_in_both(a in rect(), b in rect(), x in -25i32..40, y in -25i32..40) {
+            let in_both = a.contains_point(x, y) && b.contains_point(x, y);
+            let in_intersection = a.intersection(&b).is_some_and(|i| i.contains_point(x, y));
+            prop_assert_eq!(in_both, in_intersection);
+        }
+
+        #[test]
+        fn translate_keeps_the_size(a in rect(), dx in -50i32..50, dy in -50i32..50) {
+            let moved = a.translate(dx.into(), dy.into());
+            prop_assert_eq!((moved.width, moved.height), (a.width, a.height));
+            prop_assert_eq!(moved.contains_point(a.x + dx, a.y + dy), !a.is_empty());
+        }
+
+        #[test]
+        fn inset_stays_inside(a in rect(), amount in 0i32..10) {
+            prop_assert!(a.contains(&a.inset(amount)));
+        }
+    }
+
+    #[test]
+    fn unsigned_rectangles_move_left_and_up() {
+        let r = Rectangle::new(10u32, 20, 5, 5);
+        assert_eq!(r.translate(-4, -20), Rectangle::new(6, 0, 5, 5));
+        assert_eq!(r.translate(-11, -21), Rectangle::new(0, 0, 5, 5));
+        assert_eq!(r.translate(3, -1), Rectangle::new(13, 19, 5, 5));
+        assert_eq!(r.translate(i64::MAX, 0), Rectangle::new(u32::MAX, 20, 5, 5));
+    }
+
+    #[test]
+    fn empty_rectangles_cover_nothing() {
+        let line = Rectangle::new(2, 2, 0, 5);
+        let point = Rectangle::new(2, 2, 0, 0);
+        let big = Rectangle::new(0, 0, 10, 10);
+
+        for empty in [line, point] {
+            assert!(empty.is_empty());
+            assert_eq!(empty.area(), 0);
+            assert!(!empty.contains_point(2, 2));
+            assert!(!empty.intersects(&big));
+            assert!(!big.intersects(&empty));
+            assert!(big.contains(&empty));
+            assert_eq!(big.union(&empty), big);
+            assert_eq!(empty.union(&big), big);
+        }
+        assert_eq!(line.union(&point), line);
+        assert_eq!(Rectangle::<i32>::bounding_box(&[line, point]), None);
+    }
+
+    #[test]
+    fn touching_edges_do_not_intersect() {
+        let a = Rectangle::new(0, 0, 5, 5);
+        let b = Rectangle::new(5, 0, 5, 5);
+        assert!(!a.intersects(&b));
+        assert_eq!(a.intersection(&b), None);
+        assert_eq!(a.union(&b), Rectangle::new(0, 0, 10, 5));
+    }
+
+    #[test]
+    fn inset_past_the_middle_is_empty() {
+        let r = Rectangle::new(0u32, 0, 4, 10);
+        let inset = r.inset(3);
+        assert!(inset.is_empty());
+        assert_eq!((inset.x, inset.width), (2, 0));
+        assert_eq!((inset.y, inset.height), (3, 4));
+    }
+
+    #[test]
+    fn negative_sizes_are_empty() {
+        let r = Rectangle::new(0, 0, -3, 4);
+        assert!(r.is_empty());
+        assert_eq!(r.area(), 0);
+        assert!(!r.intersects(&Rectangle::new(-5, -5, 10, 10)));
+    }
+}
@@ -0,0 +1,319 @@
+use crate::num::Num;
+use crate::packing::Packing;
+use crate::rectangle::Rectangle;
+use crate::spatial;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// how a rectangle is drawn in SVG. Colours are any CSS colour
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    pub fill: String,
+    pub stroke: String,
+    pub stroke_width: f64,
+    pub opacity: f64,
+}
+
+// rectangles without an explicit style take their fill from this list,
+// in order, so neighbours are easy to tell apart
+const PALETTE: [&str; 8] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+];
+
+impl Style {
+    pub fn fill(colour: &str) -> Style {
+        Style {
+            fill: colour.to_string(),
+            stroke: String::from("#333333"),
+            stroke_width: 1.0,
+            opacity: 0.7,
+        }
+    }
+}
+
+struct Item {
+    rect: Rectangle,
+    label: Option<String>,
+    style: Style,
+}
+
+// a set of positioned rectangles to be drawn together, optionally inside
+// a frame (a packing container, say). Both renderers fit the picture to
+// the frame, or to the bounding box of the rectangles when there is none
+#[derive(Default)]
+pub struct Drawing {
+    items: Vec<Item>,
+    frame: Option<Rectangle>,
+}
+
+impl Drawing {
+    pub fn new() -> Drawing {
+        Drawing::default()
+    }
+
+    // every placement of a packing, labelled with the index of its item,
+    // inside the container
+    pub fn packing(packing: &Packing) -> Drawing {
+        let mut drawing = Drawing::new();
+        drawing.frame(packing.container);
+        for p in &packing.placed {
+            let label = if p.rotated {
+                format!("{}'", p.index)
+            } else {
+                p.index.to_string()
+            };
+            drawing.add(p.rect, Some(&label));
+        }
+        drawing
+    }
+
+    pub fn frame(&mut self, frame: Rectangle) -> &mut Drawing {
+        self.frame = Some(frame);
+        self
+    }
+
+    pub fn add(&mut self, rect: Rectangle, label: Option<&str>) -> &mut Drawing {
+        let style = Style::fill(PALETTE[self.items.len() % PALETTE.len()]);
+        self.add_styled(rect, label, style)
+    }
+
+    pub fn add_styled(
+        &mut self,
+        rect: Rectangle,
+        label: Option<&str>,
+        style: Style,
+    ) -> &mut Drawing {
+        self.items.push(Item {
+            rect,
+            label: label.map(String::from),
+            style,
+        });
+        self
+    }
+
+    // the part of the plane shown. Empty rectangles still mark a position,
+    // so they are included too
+    fn bounds(&self) -> Rectangle {
+        if let Some(frame) = self.frame {
+            return frame;
+        }
+
+        // the far edge can lie one past u32::MAX, which is cut off here.
+        // A picture loses nothing that can be seen by that
+        let b = spatial::enclose(self.items.iter().map(|i| i.rect.into()));
+        Rectangle::new(
+            b.x as u32,
+            b.y as u32,
+            u32::narrow(b.width),
+            u32::narrow(b.height),
+        )
+    }
+
+    pub fn to_svg(&self) -> String {
+        let b = self.bounds();
+        // a margin keeps the strokes on the edges fully visible
+        let margin = (b.width.max(b.height) as f64 * 0.02).max(1.0);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            b.x as f64 - margin,
+            b.y as f64 - margin,
+            b.width as f64 + 2.0 * margin,
+            b.height as f64 + 2.0 * margin
+        );
+
+        if let Some(frame) = self.frame {
+            svg.push_str(&format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+                 stroke=\"#999999\" stroke-dasharray=\"4 2\" vector-effect=\"non-scaling-stroke\"/>\n",
+                frame.x, frame.y, frame.width, frame.height
+            ));
+        }
+
+        for item in &self.items {
+            let (r, s) = (&item.rect, &item.style);
+            svg.push_str(&format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" \
+                 fill-opacity=\"{}\" stroke=\"{}\" stroke-width=\"{}\" \
+                 vector-effect=\"non-scaling-stroke\"/>\n",
+                r.x,
+                r.y,
+                r.width,
+                r.height,
+                escape(&s.fill),
+                s.opacity,
+                escape(&s.stroke),
+                s.stroke_width
+            ));
+
+            if let Some(label) = &item.label {
+                // the text is sized to the rectangle so it stays inside
+                let size = (r.width.min(r.height) as f64 * 0.4).max(1.0);
+                // two decimals are plenty and keep the output stable
+                let size = (size * 100.0).round() / 100.0;
+                svg.push_str(&format!(
+                    "  <text x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"sans-serif\" \
+                     text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+                    r.x as f64 + r.width as f64 / 2.0,
+                    r.y as f64 + r.height as f64 / 2.0,
+                    size,
+                    escape(label)
+                ));
+            }
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn save_svg<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_svg())
+    }
+
+    // draws the rectangles as letters on a grid at most `columns`
+    // characters wide and `columns` rows high, followed by a legend. Cells
+    // covered by more than one rectangle show `#`, uncovered cells inside
+    // the frame show `.`. Terminal characters are about twice as tall as
+    // they are wide, so rows are scaled by half
+    pub fn to_ascii(&self, columns: usize) -> String {
+        let b = self.bounds();
+        let columns = columns.max(1);
+        // the larger side decides, so that a tall picture gets no more
+        // rows than a wide one gets columns
+        let longest = (b.width as f64).max(b.height as f64 / 2.0);
+        let scale = if longest > 0.0 {
+            columns as f64 / longest
+        } else {
+            1.0
+        };
+        let cols = ((b.width as f64 * scale).round() as usize).clamp(1, columns);
+        let rows = ((b.height as f64 * scale / 2.0).round() as usize).clamp(1, columns);
+
+        let mut grid = vec![vec![b'.'; cols]; rows];
+        let mut legend = String::new();
+
+        for (i, item) in self.items.iter().enumerate() {
+            let r = &item.rect;
+            let mark = marker(i);
+            legend.push_str(&format!(
+                "{} {} at ({}, {}) {}x{}\n",
+                mark as char,
+                item.label.as_deref().unwrap_or(""),
+                r.x,
+                r.y,
+                r.width,
+                r.height
+            ));
+
+            // rectangles outside the frame are only listed in the legend
+            if r.right() < b.left()
+                || r.left() > b.right()
+                || r.bottom() < b.top()
+                || r.top() > b.bottom()
+            {
+                continue;
+            }
+
+            // every cell the rectangle touches, and at least one so that
+            // small rectangles do not disappear
+            let span = |low: u64, high: u64, origin: u64, s: f64, cells: usize| {
+                let first = ((low - origin.min(low)) as f64 * s).floor() as usize;
+                let last = ((high - origin.min(high)) as f64 * s).ceil() as usize;
+                let first = first.min(cells - 1);
+                (first, last.clamp(first + 1, cells))
+            };
+            let (c0, c1) = span(r.left(), r.right(), b.left(), scale, cols);
+            let (r0, r1) = span(r.top(), r.bottom(), b.top(), scale / 2.0, rows);
+
+            for row in &mut grid[r0..r1] {
+                for cell in &mut row[c0..c1] {
+                    *cell = if *cell == b'.' { mark } else { b'#' };
+                }
+            }
+        }
+
+        let mut out = String::new();
+        for row in grid {
+            out.push_str(&String::from_utf8_lossy(&row));
+            out.push('\n');
+        }
+        out.push_str(&legend);
+        out
+    }
+}
+
+// the number of columns of the terminal, as exported by most shells in
+// $COLUMNS, or 80 when it is unknown
+pub fn terminal_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(80)
+}
+
+// A-Z, then a-z, then 0-9, starting over after that
+fn marker(index: usize) -> u8 {
+    const MARKERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    MARKERS[index % MARKERS.len()]
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drawing() -> Drawing {
+        let mut drawing = Drawing::new();
+        drawing
+            .frame(Rectangle::new(0, 0, 20, 10))
+            .add(Rectangle::new(0, 0, 10, 10), Some("left"))
+            .add(Rectangle::new(8, 2, 6, 4), Some("a & b"));
+        drawing
+    }
+
+    #[test]
+    fn ascii_snapshot() {
+        let expected = "\
+AAAAAAAAAA..........
+AAAAAAAA##BBBB......
+AAAAAAAA##BBBB......
+AAAAAAAAAA..........
+AAAAAAAAAA..........
+A left at (0, 0) 10x10
+B a & b at (8, 2) 6x4
+";
+        assert_eq!(drawing().to_ascii(20), expected);
+    }
+
+    #[test]
+    fn svg_snapshot() {
+        let expected = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-1 -1 22 12">
+  <rect x="0" y="0" width="20" height="10" fill="none" stroke="#999999" stroke-dasharray="4 2" vector-effect="non-scaling-stroke"/>
+  <rect x="0" y="0" width="10" height="10" fill="#4e79a7" fill-opacity="0.7" stroke="#333333" stroke-width="1" vector-effect="non-scaling-stroke"/>
+  <text x="5" y="5" font-size="4" font-family="sans-serif" text-anchor="middle" dominant-baseline="central">left</text>
+  <rect x="8" y="2" width="6" height="4" fill="#f28e2b" fill-opacity="0.7" stroke="#333333" stroke-width="1" vector-effect="non-scaling-stroke"/>
+  <text x="11" y="4" font-size="1.6" font-family="sans-serif" text-anchor="middle" dominant-baseline="central">a &amp; b</text>
+</svg>
+"##;
+        assert_eq!(drawing().to_svg(), expected);
+    }
+
+    #[test]
+    fn tall_layouts_get_no_more_rows_than_columns() {
+        let mut drawing = Drawing::new();
+        drawing.add(Rectangle::new(0, 0, 0, u32::MAX), None);
+        drawing.add(Rectangle::new(0, 0, 1, 1_000_000), None);
+        let ascii = drawing.to_ascii(10);
+        // the grid, then a line of legend for each rectangle
+        assert_eq!(ascii.lines().count(), 10 + 2);
+        assert!(ascii.lines().all(|line| line.len() <= 40));
+    }
+}
@@ -0,0 +1,616 @@
+use crate::num::Num;
+use crate::rectangle::Rectangle;
+use std::f64::consts::PI;
+
+// shapes work on the continuous plane, so every coordinate is an f64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    pub fn distance(&self, other: &Point) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+// what `area` and `can_hold` on `Rectangle` become when any shape can be
+// asked the same questions. Unlike `Rectangle::contains_point`, points on
+// the boundary count as inside, since a shape has no grid cells to share
+// with its neighbours
+pub trait Shape {
+    fn area(&self) -> f64;
+    fn perimeter(&self) -> f64;
+    fn bounding_box(&self) -> Rectangle<f64>;
+    fn centroid(&self) -> Point;
+    fn contains_point(&self, p: Point) -> bool;
+    fn is_convex(&self) -> bool;
+
+    // the corners of the shape in order. Curved shapes return points
+    // along their boundary, close enough together for containment tests
+    fn outline(&self) -> Vec<Point>;
+
+    // true when `other` lies completely inside `self`. All of the outline
+    // of `other` has to be inside, and a concave `self` may not cut through
+    // any of its edges. Curved shapes are compared through their outline,
+    // so the answer for them is approximate right at the boundary
+    fn contains_shape(&self, other: &dyn Shape) -> bool {
+        let outline = other.outline();
+        if !outline.iter().all(|&p| self.contains_point(p)) {
+            return false;
+        }
+        self.is_convex() || !edges_cross(&self.outline(), &outline)
+    }
+}
+
+// how many points `outline` uses for curves
+const CURVE_POINTS: usize = 256;
+
+// tolerance for points lying on an edge
+const EPSILON: f64 = 1e-9;
+
+impl<T: Num> Shape for Rectangle<T> {
+    fn area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.width.to_f64() * self.height.to_f64()
+    }
+
+    fn perimeter(&self) -> f64 {
+        2.0 * (self.width.to_f64() + self.height.to_f64())
+    }
+
+    fn bounding_box(&self) -> Rectangle<f64> {
+        Rectangle::new(
+            self.x.to_f64(),
+            self.y.to_f64(),
+            self.width.to_f64(),
+            self.height.to_f64(),
+        )
+    }
+
+    fn centroid(&self) -> Point {
+        Point::new(
+            self.x.to_f64() + self.width.to_f64() / 2.0,
+            self.y.to_f64() + self.height.to_f64() / 2.0,
+        )
+    }
+
+    fn contains_point(&self, p: Point) -> bool {
+        let b = Shape::bounding_box(self);
+        b.x <= p.x && p.x <= b.x + b.width && b.y <= p.y && p.y <= b.y + b.height
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    fn outline(&self) -> Vec<Point> {
+        let b = Shape::bounding_box(self);
+        vec![
+            Point::new(b.x, b.y),
+            Point::new(b.x + b.width, b.y),
+            Point::new(b.x + b.width, b.y + b.height),
+            Point::new(b.x, b.y + b.height),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub centre: Point,
+    pub radius: f64,
+}
+
+impl Shape for Circle {
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    fn perimeter(&self) -> f64 {
+        2.0 * PI * self.radius
+    }
+
+    fn bounding_box(&self) -> Rectangle<f64> {
+        let r = self.radius;
+        Rectangle::new(self.centre.x - r, self.centre.y - r, 2.0 * r, 2.0 * r)
+    }
+
+    fn centroid(&self) -> Point {
+        self.centre
+    }
+
+    fn contains_point(&self, p: Point) -> bool {
+        self.centre.distance(&p) <= self.radius * (1.0 + EPSILON)
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    fn outline(&self) -> Vec<Point> {
+        ellipse_outline(self.centre, self.radius, self.radius)
+    }
+}
+
+// an ellipse with its axes along x and y
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ellipse {
+    pub centre: Point,
+    pub radius_x: f64,
+    pub radius_y: f64,
+}
+
+impl Shape for Ellipse {
+    fn area(&self) -> f64 {
+        PI * self.radius_x * self.radius_y
+    }
+
+    // the perimeter of an ellipse has no closed form. Ramanujan's second
+    // approximation is accurate to well below a millionth for most shapes
+    fn perimeter(&self) -> f64 {
+        let (a, b) = (self.radius_x, self.radius_y);
+        if a + b == 0.0 {
+            return 0.0;
+        }
+        let h = ((a - b) / (a + b)).powi(2);
+        PI * (a + b) * (1.0 + 3.0 * h / (10.0 + (4.0 - 3.0 * h).sqrt()))
+    }
+
+    fn bounding_box(&self) -> Rectangle<f64> {
+        Rectangle::new(
+            self.centre.x - self.radius_x,
+            self.centre.y - self.radius_y,
+            2.0 * self.radius_x,
+            2.0 * self.radius_y,
+        )
+    }
+
+    fn centroid(&self) -> Point {
+        self.centre
+    }
+
+    fn contains_point(&self, p: Point) -> bool {
+        let dx = p.x - self.centre.x;
+        let dy = p.y - self.centre.y;
+        // a degenerate ellipse is the segment between its ends
+        if self.radius_x == 0.0 || self.radius_y == 0.0 {
+            return dx.abs() <= self.radius_x && dy.abs() <= self.radius_y;
+        }
+        (dx / self.radius_x).powi(2) + (dy / self.radius_y).powi(2) <= 1.0 + EPSILON
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    fn outline(&self) -> Vec<Point> {
+        ellipse_outline(self.centre, self.radius_x, self.radius_y)
+    }
+}
+
+fn ellipse_outline(centre: Point, rx: f64, ry: f64) -> Vec<Point> {
+    (0..CURVE_POINTS)
+        .map(|i| {
+            let t = 2.0 * PI * i as f64 / CURVE_POINTS as f64;
+            Point::new(centre.x + rx * t.cos(), centre.y + ry * t.sin())
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Triangle {
+    pub a: Point,
+    pub b: Point,
+    pub c: Point,
+}
+
+impl Triangle {
+    fn polygon(&self) -> Polygon {
+        Polygon::new(vec![self.a, self.b, self.c])
+    }
+}
+
+// a triangle is the simplest polygon, so it borrows all of its answers
+impl Shape for Triangle {
+    fn area(&self) -> f64 {
+        self.polygon().area()
+    }
+
+    fn perimeter(&self) -> f64 {
+        self.polygon().perimeter()
+    }
+
+    fn bounding_box(&self) -> Rectangle<f64> {
+        self.polygon().bounding_box()
+    }
+
+    fn centroid(&self) -> Point {
+        Point::new(
+            (self.a.x + self.b.x + self.c.x) / 3.0,
+            (self.a.y + self.b.y + self.c.y) / 3.0,
+        )
+    }
+
+    fn contains_point(&self, p: Point) -> bool {
+        self.polygon().contains_point(p)
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    fn outline(&self) -> Vec<Point> {
+        vec![self.a, self.b, self.c]
+    }
+}
+
+// a closed polygon through `vertices` in order, the last one connects
+// back to the first. Area and containment assume the polygon is simple
+// (its edges do not cross), which `is_self_intersecting` can check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub vertices: Vec<Point>,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<Point>) -> Polygon {
+        Polygon { vertices }
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        let n = self.vertices.len();
+        (0..n).map(move |i| (self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+
+    // the shoelace formula: summing the cross products of neighbouring
+    // vertices gives twice the enclosed area. The sign tells the winding,
+    // positive when the vertices go counter-clockwise in a y-up system
+    // (clockwise on screen, where y grows downwards)
+    pub fn signed_area(&self) -> f64 {
+        self.edges()
+            .map(|(p, q)| p.x * q.y - q.x * p.y)
+            .sum::<f64>()
+            / 2.0
+    }
+
+    // true when two edges that are not neighbours touch or cross, or when
+    // two neighbouring edges fold back onto each other
+    pub fn is_self_intersecting(&self) -> bool {
+        let edges: Vec<(Point, Point)> = self.edges().collect();
+        let n = edges.len();
+        if n < 3 {
+            return false;
+        }
+
+        for i in 0..n {
+            // neighbours share a vertex, they only intersect when they
+            // overlap along a line
+            let (p, q) = edges[i];
+            let (_, r) = edges[(i + 1) % n];
+            if cross(p, q, r).abs() <= EPSILON && dot(q, p, r) > 0.0 {
+                return true;
+            }
+
+            for j in i + 2..n {
+                if i == 0 && j == n - 1 {
+                    continue;
+                }
+                if segments_touch(edges[i], edges[j]) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+impl Shape for Polygon {
+    fn area(&self) -> f64 {
+        self.signed_area().abs()
+    }
+
+    fn perimeter(&self) -> f64 {
+        self.edges().map(|(p, q)| p.distance(&q)).sum()
+    }
+
+    fn bounding_box(&self) -> Rectangle<f64> {
+        if self.vertices.is_empty() {
+            return Rectangle::new(0.0, 0.0, 0.0, 0.0);
+        }
+
+        let (mut left, mut top) = (f64::INFINITY, f64::INFINITY);
+        let (mut right, mut bottom) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for p in &self.vertices {
+            left = left.min(p.x);
+            top = top.min(p.y);
+            right = right.max(p.x);
+            bottom = bottom.max(p.y);
+        }
+        Rectangle::new(left, top, right - left, bottom - top)
+    }
+
+    // the centroid of the enclosed area. Polygons without area (a line,
+    // say) fall back to the average of their vertices
+    fn centroid(&self) -> Point {
+        let a = self.signed_area();
+        if a.abs() <= EPSILON {
+            let n = self.vertices.len().max(1) as f64;
+            let (sx, sy) = self
+                .vertices
+                .iter()
+                .fold((0.0, 0.0), |(x, y), p| (x + p.x, y + p.y));
+            return Point::new(sx / n, sy / n);
+        }
+
+        let (mut cx, mut cy) = (0.0, 0.0);
+        for (p, q) in self.edges() {
+            let f = p.x * q.y - q.x * p.y;
+            cx += (p.x + q.x) * f;
+            cy += (p.y + q.y) * f;
+        }
+        Point::new(cx / (6.0 * a), cy / (6.0 * a))
+    }
+
+    // ray casting: a ray from the point crosses the boundary an odd
+    // number of times when the point is inside
+    fn contains_point(&self, p: Point) -> bool {
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if on_segment(p, (a, b)) {
+                return true;
+            }
+            if (a.y > p.y) != (b.y > p.y) {
+                let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if p.x < x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+
+    // every turn goes the same way. Straight runs are allowed
+    fn is_convex(&self) -> bool {
+        let n = self.vertices.len();
+        if n < 3 {
+            return true;
+        }
+
+        let mut sign = 0.0;
+        for i in 0..n {
+            let turn = cross(
+                self.vertices[i],
+                self.vertices[(i + 1) % n],
+                self.vertices[(i + 2) % n],
+            );
+            if turn.abs() <= EPSILON {
+                continue;
+            }
+            if sign * turn < 0.0 {
+                return false;
+            }
+            sign = turn;
+        }
+        !self.is_self_intersecting()
+    }
+
+    fn outline(&self) -> Vec<Point> {
+        self.vertices.clone()
+    }
+}
+
+// the z component of (q - p) x (r - p): positive when p -> q -> r turns
+// counter-clockwise, zero when the three are on a line
+fn cross(p: Point, q: Point, r: Point) -> f64 {
+    (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x)
+}
+
+// (p - q) . (r - q), positive when the angle at q is sharp
+fn dot(q: Point, p: Point, r: Point) -> f64 {
+    (p.x - q.x) * (r.x - q.x) + (p.y - q.y) * (r.y - q.y)
+}
+
+fn on_segment(p: Point, (a, b): (Point, Point)) -> bool {
+    cross(a, b, p).abs() <= EPSILON * (1.0 + a.distance(&b))
+        && p.x >= a.x.min(b.x) - EPSILON
+        && p.x <= a.x.max(b.x) + EPSILON
+        && p.y >= a.y.min(b.y) - EPSILON
+        && p.y <= a.y.max(b.y) + EPSILON
+}
+
+// true when the segments share any point
+fn segments_touch(s: (Point, Point), t: (Point, Point)) -> bool {
+    segments_cross(s, t)
+        || on_segment(s.0, t)
+        || on_segment(s.1, t)
+        || on_segment(t.0, s)
+        || on_segment(t.1, s)
+}
+
+// true when the segments cross in a single point inside both of them
+fn segments_cross((a, b): (Point, Point), (c, d): (Point, Point)) -> bool {
+    let d1 = cross(a, b, c);
+    let d2 = cross(a, b, d);
+    let d3 = cross(c, d, a);
+    let d4 = cross(c, d, b);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+// true when an edge of one closed outline crosses an edge of the other
+fn edges_cross(a: &[Point], b: &[Point]) -> bool {
+    let edges = |v: &[Point]| -> Vec<(Point, Point)> {
+        (0..v.len()).map(|i| (v[i], v[(i + 1) % v.len()])).collect()
+    };
+    let (ea, eb) = (edges(a), edges(b));
+    ea.iter().any(|&s| eb.iter().any(|&t| segments_cross(s, t)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: &[(f64, f64)]) -> Polygon {
+        Polygon::new(points.iter().map(|&(x, y)| Point::new(x, y)).collect())
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * (1.0 + b.abs())
+    }
+
+    #[test]
+    fn triangle_area_and_containment() {
+        let t = Triangle {
+            a: Point::new(0.0, 0.0),
+            b: Point::new(4.0, 0.0),
+            c: Point::new(0.0, 3.0),
+        };
+        assert!(close(t.area(), 6.0));
+        assert!(close(t.perimeter(), 12.0));
+        assert_eq!(t.centroid(), Point::new(4.0 / 3.0, 1.0));
+        assert_eq!(t.bounding_box(), Rectangle::new(0.0, 0.0, 4.0, 3.0));
+
+        assert!(t.contains_point(Point::new(1.0, 1.0)));
+        // corners and edges belong to the shape
+        assert!(t.contains_point(Point::new(4.0, 0.0)));
+        assert!(t.contains_point(Point::new(2.0, 1.5)));
+        assert!(!t.contains_point(Point::new(2.1, 1.6)));
+        assert!(!t.contains_point(Point::new(-0.1, 1.0)));
+
+        // the same triangle wound the other way round
+        let flipped = Triangle {
+            b: t.c,
+            c: t.b,
+            ..t
+        };
+        assert!(close(flipped.area(), 6.0));
+        assert!(flipped.contains_point(Point::new(1.0, 1.0)));
+    }
+
+    #[test]
+    fn polygon_area_and_containment() {
+        // an L: a 4×4 square with its top right 2×2 quarter cut away
+        let l = polygon(&[
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 2.0),
+            (4.0, 2.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+        ]);
+        assert!(close(l.area(), 12.0));
+        assert!(close(l.signed_area(), 12.0));
+        assert!(close(l.perimeter(), 16.0));
+        assert!(!l.is_convex());
+        assert!(!l.is_self_intersecting());
+
+        assert!(l.contains_point(Point::new(1.0, 1.0)));
+        assert!(l.contains_point(Point::new(3.0, 3.0)));
+        assert!(l.contains_point(Point::new(3.0, 2.0)));
+        assert!(!l.contains_point(Point::new(3.0, 1.0)));
+        assert!(!l.contains_point(Point::new(5.0, 3.0)));
+
+        // the notch is outside, so a square across it is not contained
+        let across = Rectangle::new(1.0, 1.0, 2.0, 2.0);
+        let inside = Rectangle::new(0.5, 2.5, 3.0, 1.0);
+        assert!(!l.contains_shape(&across));
+        assert!(l.contains_shape(&inside));
+
+        let reversed = polygon(&[
+            (0.0, 4.0),
+            (4.0, 4.0),
+            (4.0, 2.0),
+            (2.0, 2.0),
+            (2.0, 0.0),
+            (0.0, 0.0),
+        ]);
+        assert!(close(reversed.signed_area(), -12.0));
+        assert!(close(reversed.area(), 12.0));
+    }
+
+    #[test]
+    fn polygon_centroid_and_degenerate_cases() {
+        let square = polygon(&[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]);
+        assert_eq!(square.centroid(), Point::new(1.0, 1.0));
+        assert!(square.is_convex());
+
+        let line = polygon(&[(0.0, 0.0), (3.0, 0.0), (6.0, 0.0)]);
+        assert_eq!(line.area(), 0.0);
+        assert_eq!(line.centroid(), Point::new(3.0, 0.0));
+
+        let empty = polygon(&[]);
+        assert_eq!(empty.area(), 0.0);
+        assert_eq!(empty.bounding_box(), Rectangle::new(0.0, 0.0, 0.0, 0.0));
+        assert!(!empty.contains_point(Point::new(0.0, 0.0)));
+    }
+
+    #[test]
+    fn self_intersecting_polygons_are_recognised() {
+        let bow_tie = polygon(&[(0.0, 0.0), (2.0, 2.0), (2.0, 0.0), (0.0, 2.0)]);
+        assert!(bow_tie.is_self_intersecting());
+        assert!(!bow_tie.is_convex());
+
+        // a vertex touching a non-neighbouring edge
+        let pinched = polygon(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (2.0, 0.0), (0.0, 4.0)]);
+        assert!(pinched.is_self_intersecting());
+
+        // an edge folding back onto the one before it
+        let folded = polygon(&[(0.0, 0.0), (4.0, 0.0), (2.0, 0.0), (2.0, 3.0)]);
+        assert!(folded.is_self_intersecting());
+
+        // a vertex in the middle of a straight run is fine
+        let straight = polygon(&[(0.0, 0.0), (2.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)]);
+        assert!(!straight.is_self_intersecting());
+        assert!(straight.is_convex());
+    }
+
+    #[test]
+    fn circle_and_ellipse_bounds() {
+        let circle = Circle {
+            centre: Point::new(1.0, -2.0),
+            radius: 3.0,
+        };
+        assert_eq!(circle.bounding_box(), Rectangle::new(-2.0, -5.0, 6.0, 6.0));
+        assert!(close(circle.area(), 9.0 * PI));
+        assert!(close(circle.perimeter(), 6.0 * PI));
+        assert!(circle.contains_point(Point::new(4.0, -2.0)));
+        assert!(!circle.contains_point(Point::new(3.2, 0.2)));
+        assert!(circle.bounding_box().contains_shape(&circle));
+
+        let ellipse = Ellipse {
+            centre: Point::new(0.0, 0.0),
+            radius_x: 4.0,
+            radius_y: 1.0,
+        };
+        assert_eq!(ellipse.bounding_box(), Rectangle::new(-4.0, -1.0, 8.0, 2.0));
+        assert!(close(ellipse.area(), 4.0 * PI));
+        assert!(ellipse.contains_point(Point::new(4.0, 0.0)));
+        assert!(ellipse.contains_point(Point::new(0.0, -1.0)));
+        assert!(!ellipse.contains_point(Point::new(3.0, 0.8)));
+        assert!(ellipse.bounding_box().contains_shape(&ellipse));
+
+        // a round ellipse is a circle, and Ramanujan's formula is exact there
+        let round = Ellipse {
+            centre: circle.centre,
+            radius_x: 3.0,
+            radius_y: 3.0,
+        };
+        assert!(close(round.perimeter(), circle.perimeter()));
+
+        let flat = Ellipse {
+            radius_y: 0.0,
+            ..ellipse
+        };
+        assert!(flat.contains_point(Point::new(-4.0, 0.0)));
+        assert!(!flat.contains_point(Point::new(0.0, 0.1)));
+        // and off by less than a tenth of a percent for a flat one
+        assert!((flat.perimeter() - 16.0).abs() < 0.016);
+    }
+}
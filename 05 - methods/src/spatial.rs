@@ -0,0 +1,705 @@
+use crate::num::Num;
+use crate::rectangle::Rectangle;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// an R-tree: rectangles are grouped into nodes, and every node knows the
+// bounding box of everything below it. A query only descends into nodes
+// whose bounding box can contain a match, so it looks at a small part of
+// the collection instead of comparing against every rectangle.
+//
+// every rectangle is stored with an id chosen by the caller. The same id
+// may be stored more than once, `remove` needs the rectangle to find it.
+pub struct SpatialIndex<Id> {
+    root: Node<Id>,
+    len: usize,
+}
+
+// the most entries a node holds before it is split, and the fewest it
+// keeps before its entries are handed to other nodes on removal
+const MAX_ENTRIES: usize = 16;
+const MIN_ENTRIES: usize = 4;
+
+// the bounds of a node are kept in u64: a rectangle at the far end of the
+// u32 range reaches one past u32::MAX, and a u32 box around it would have
+// to be cut short and then miss it
+struct Node<Id> {
+    bounds: Rectangle<u64>,
+    kind: Kind<Id>,
+}
+
+enum Kind<Id> {
+    Leaf(Vec<(Id, Rectangle)>),
+    Branch(Vec<Node<Id>>),
+}
+
+impl<Id: Copy + PartialEq> Default for SpatialIndex<Id> {
+    fn default() -> SpatialIndex<Id> {
+        SpatialIndex::new()
+    }
+}
+
+impl<Id: Copy + PartialEq> SpatialIndex<Id> {
+    pub fn new() -> SpatialIndex<Id> {
+        SpatialIndex {
+            root: Node::leaf(Vec::new()),
+            len: 0,
+        }
+    }
+
+    // builds the tree in one go with sort-tile-recursive (STR) packing:
+    // the rectangles are sorted into vertical slices by x, each slice is
+    // sorted by y and cut into full nodes. That is much faster than
+    // inserting one by one and gives nodes that barely overlap
+    pub fn bulk_load(items: Vec<(Id, Rectangle)>) -> SpatialIndex<Id> {
+        let len = items.len();
+        let mut level: Vec<Node<Id>> = tile(items, |&(_, r)| centre(&r.into()))
+            .into_iter()
+            .map(Node::leaf)
+            .collect();
+
+        while level.len() > 1 {
+            level = tile(level, |n| centre(&n.bounds))
+                .into_iter()
+                .map(Node::branch)
+                .collect();
+        }
+
+        SpatialIndex {
+            root: level.pop().unwrap_or_else(|| Node::leaf(Vec::new())),
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, id: Id, rect: Rectangle) {
+        self.len += 1;
+        if let Some(sibling) = self.root.insert(id, rect) {
+            // the root was split, so the tree grows by one level
+            let old = std::mem::replace(&mut self.root, Node::leaf(Vec::new()));
+            self.root = Node::branch(vec![old, sibling]);
+        }
+    }
+
+    // removes one entry with this id and rectangle, returns whether one
+    // was found
+    pub fn remove(&mut self, id: Id, rect: &Rectangle) -> bool {
+        let mut orphans = Vec::new();
+        if !self.root.remove(id, rect, &mut orphans) {
+            return false;
+        }
+        self.len -= 1;
+
+        // a root with a single child is just an extra level
+        while let Kind::Branch(children) = &mut self.root.kind {
+            match children.len() {
+                0 => self.root = Node::leaf(Vec::new()),
+                1 => self.root = children.pop().unwrap(),
+                _ => break,
+            }
+        }
+
+        // entries of nodes that became too small go back in from the top
+        for (id, rect) in orphans {
+            self.len -= 1;
+            self.insert(id, rect);
+        }
+
+        true
+    }
+
+    // ids of all rectangles sharing at least one point with `window`
+    pub fn query_window(&self, window: &Rectangle) -> Vec<Id> {
+        let wide = Rectangle::from(*window);
+        let mut found = Vec::new();
+        self.root
+            .visit(&|bounds| bounds.intersects(&wide), &mut |id, r| {
+                if r.intersects(window) {
+                    found.push(id);
+                }
+            });
+        found
+    }
+
+    // ids of all rectangles containing the point
+    pub fn query_point(&self, x: u32, y: u32) -> Vec<Id> {
+        let mut found = Vec::new();
+        self.root.visit(
+            &|bounds| bounds.contains_point(x as u64, y as u64),
+            &mut |id, r| {
+                if r.contains_point(x, y) {
+                    found.push(id);
+                }
+            },
+        );
+        found
+    }
+
+    // the `k` rectangles closest to the point with their distances,
+    // nearest first. Rectangles containing the point are at distance 0
+    pub fn nearest(&self, x: u32, y: u32, k: usize) -> Vec<(Id, f64)> {
+        // best-first search: nodes and entries share one queue ordered by
+        // their distance, so the first `k` entries taken out are the
+        // nearest ones. A node is never closer than its bounding box
+        enum Item<'a, Id> {
+            Node(&'a Node<Id>),
+            Entry(Id),
+        }
+
+        let mut nearest = Vec::new();
+        let mut queue = BinaryHeap::new();
+        let mut items = vec![Item::Node(&self.root)];
+        queue.push(Reverse((reach2(&self.root.bounds, x, y), 0)));
+
+        while let Some(Reverse((d, i))) = queue.pop() {
+            if nearest.len() == k {
+                break;
+            }
+            match items[i] {
+                Item::Entry(id) => nearest.push((id, (d as f64).sqrt())),
+                Item::Node(node) => match &node.kind {
+                    Kind::Leaf(entries) => {
+                        for &(id, r) in entries {
+                            queue.push(Reverse((distance2(&r, x, y), items.len())));
+                            items.push(Item::Entry(id));
+                        }
+                    }
+                    Kind::Branch(children) => {
+                        for child in children {
+                            queue.push(Reverse((reach2(&child.bounds, x, y), items.len())));
+                            items.push(Item::Node(child));
+                        }
+                    }
+                },
+            }
+        }
+
+        nearest
+    }
+
+    // every pair of stored rectangles that overlap, each pair once. Whole
+    // subtrees are skipped when their bounding boxes do not meet
+    pub fn overlapping_pairs(&self) -> Vec<(Id, Id)> {
+        let mut pairs = Vec::new();
+        self_join(&self.root, &mut pairs);
+        pairs
+    }
+}
+
+impl<Id: Copy + PartialEq> Node<Id> {
+    fn leaf(entries: Vec<(Id, Rectangle)>) -> Node<Id> {
+        let bounds = enclose(entries.iter().map(|&(_, r)| r.into()));
+        Node {
+            bounds,
+            kind: Kind::Leaf(entries),
+        }
+    }
+
+    fn branch(children: Vec<Node<Id>>) -> Node<Id> {
+        let bounds = enclose(children.iter().map(|c| c.bounds));
+        Node {
+            bounds,
+            kind: Kind::Branch(children),
+        }
+    }
+
+    fn refresh_bounds(&mut self) {
+        self.bounds = match &self.kind {
+            Kind::Leaf(entries) => enclose(entries.iter().map(|&(_, r)| r.into())),
+            Kind::Branch(children) => enclose(children.iter().map(|c| c.bounds)),
+        };
+    }
+
+    // returns a new sibling when this node had to be split
+    fn insert(&mut self, id: Id, rect: Rectangle) -> Option<Node<Id>> {
+        // an empty node has no bounds worth keeping
+        self.bounds = if self.size() == 0 {
+            rect.into()
+        } else {
+            enclose([self.bounds, rect.into()])
+        };
+
+        match &mut self.kind {
+            Kind::Leaf(entries) => {
+                entries.push((id, rect));
+                if entries.len() <= MAX_ENTRIES {
+                    return None;
+                }
+                let (keep, moved) = split(std::mem::take(entries), |&(_, r)| r.into());
+                *entries = keep;
+                self.refresh_bounds();
+                Some(Node::leaf(moved))
+            }
+            Kind::Branch(children) => {
+                // descend into the child that grows the least
+                let best = (0..children.len())
+                    .min_by_key(|&i| {
+                        let b = children[i].bounds;
+                        let grown = enclose([b, rect.into()]).wide_area() - b.wide_area();
+                        (grown, b.wide_area())
+                    })
+                    .unwrap();
+
+                let sibling = children[best].insert(id, rect)?;
+                children.push(sibling);
+                if children.len() <= MAX_ENTRIES {
+                    return None;
+                }
+                let (keep, moved) = split(std::mem::take(children), |c| c.bounds);
+                *children = keep;
+                self.refresh_bounds();
+                Some(Node::branch(moved))
+            }
+        }
+    }
+
+    // removes the entry and collects the entries of nodes that became too
+    // small into `orphans`
+    fn remove(&mut self, id: Id, rect: &Rectangle, orphans: &mut Vec<(Id, Rectangle)>) -> bool {
+        if !covers(&self.bounds, rect) {
+            return false;
+        }
+
+        match &mut self.kind {
+            Kind::Leaf(entries) => {
+                let found = entries.iter().position(|(i, r)| *i == id && r == rect);
+                match found {
+                    Some(i) => {
+                        entries.swap_remove(i);
+                    }
+                    None => return false,
+                }
+            }
+            Kind::Branch(children) => {
+                let found = children
+                    .iter_mut()
+                    .position(|c| c.remove(id, rect, orphans));
+                let i = match found {
+                    Some(i) => i,
+                    None => return false,
+                };
+                if children[i].size() < MIN_ENTRIES {
+                    children.swap_remove(i).drain_into(orphans);
+                }
+            }
+        }
+
+        self.refresh_bounds();
+        true
+    }
+
+    fn size(&self) -> usize {
+        match &self.kind {
+            Kind::Leaf(entries) => entries.len(),
+            Kind::Branch(children) => children.len(),
+        }
+    }
+
+    fn drain_into(self, out: &mut Vec<(Id, Rectangle)>) {
+        match self.kind {
+            Kind::Leaf(entries) => out.extend(entries),
+            Kind::Branch(children) => {
+                for child in children {
+                    child.drain_into(out);
+                }
+            }
+        }
+    }
+
+    // calls `found` for every entry in the nodes `descend` accepts
+    fn visit(
+        &self,
+        descend: &dyn Fn(&Rectangle<u64>) -> bool,
+        found: &mut dyn FnMut(Id, &Rectangle),
+    ) {
+        if !descend(&self.bounds) {
+            return;
+        }
+
+        match &self.kind {
+            Kind::Leaf(entries) => {
+                for (id, r) in entries {
+                    found(*id, r);
+                }
+            }
+            Kind::Branch(children) => {
+                for child in children {
+                    child.visit(descend, found);
+                }
+            }
+        }
+    }
+}
+
+// overlapping pairs within one node
+fn self_join<Id: Copy + PartialEq>(node: &Node<Id>, pairs: &mut Vec<(Id, Id)>) {
+    match &node.kind {
+        Kind::Leaf(entries) => {
+            for (i, (a, ra)) in entries.iter().enumerate() {
+                for (b, rb) in &entries[i + 1..] {
+                    if ra.intersects(rb) {
+                        pairs.push((*a, *b));
+                    }
+                }
+            }
+        }
+        Kind::Branch(children) => {
+            for (i, a) in children.iter().enumerate() {
+                self_join(a, pairs);
+                for b in &children[i + 1..] {
+                    join(a, b, pairs);
+                }
+            }
+        }
+    }
+}
+
+// overlapping pairs with one rectangle from each node
+fn join<Id: Copy + PartialEq>(a: &Node<Id>, b: &Node<Id>, pairs: &mut Vec<(Id, Id)>) {
+    if !a.bounds.intersects(&b.bounds) {
+        return;
+    }
+
+    match (&a.kind, &b.kind) {
+        (Kind::Branch(children), _) => {
+            for child in children {
+                join(child, b, pairs);
+            }
+        }
+        (_, Kind::Branch(children)) => {
+            for child in children {
+                join(a, child, pairs);
+            }
+        }
+        (Kind::Leaf(left), Kind::Leaf(right)) => {
+            for (x, rx) in left {
+                for (y, ry) in right {
+                    if rx.intersects(ry) {
+                        pairs.push((*x, *y));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the box around all the given rectangles. Unlike `Rectangle::union` this
+// keeps empty rectangles too, so that the bounds of a node always cover
+// the position of everything stored in it
+pub(crate) fn enclose<I: IntoIterator<Item = Rectangle<u64>>>(rects: I) -> Rectangle<u64> {
+    let mut edges: Option<(u128, u128, u128, u128)> = None;
+    for r in rects {
+        let (l, t, rt, b) = (r.left(), r.top(), r.right(), r.bottom());
+        edges = Some(match edges {
+            Some((l0, t0, r0, b0)) => (l0.min(l), t0.min(t), r0.max(rt), b0.max(b)),
+            None => (l, t, rt, b),
+        });
+    }
+
+    match edges {
+        Some((l, t, r, b)) => Rectangle::new(
+            Num::narrow(l),
+            Num::narrow(t),
+            Num::narrow(r - l),
+            Num::narrow(b - t),
+        ),
+        None => Rectangle::new(0, 0, 0, 0),
+    }
+}
+
+// like `Rectangle::contains`, but also true for empty rectangles that lie
+// on the edge of `outer`, since those are stored too
+fn covers(outer: &Rectangle<u64>, inner: &Rectangle) -> bool {
+    let inner = Rectangle::<u64>::from(*inner);
+    outer.left() <= inner.left()
+        && inner.right() <= outer.right()
+        && outer.top() <= inner.top()
+        && inner.bottom() <= outer.bottom()
+}
+
+// twice the centre, which keeps it an integer
+fn centre(r: &Rectangle<u64>) -> (u128, u128) {
+    (r.left() + r.right(), r.top() + r.bottom())
+}
+
+// how far `p` lies outside the range `low..=high`
+fn gap(p: u32, low: u128, high: u128) -> u128 {
+    let p = p as u128;
+    low.saturating_sub(p) + p.saturating_sub(high)
+}
+
+// the squared distance from a point to the nearest point of a rectangle.
+// Like `contains_point` the right and bottom edges are not part of it, so
+// the last column and row covered are one before them. An empty rectangle
+// counts as the point or line at its position
+fn distance2(r: &Rectangle, x: u32, y: u32) -> u128 {
+    let r = Rectangle::<u64>::from(*r);
+    let last = |low: u128, high: u128| if high > low { high - 1 } else { low };
+    let dx = gap(x, r.left(), last(r.left(), r.right()));
+    let dy = gap(y, r.top(), last(r.top(), r.bottom()));
+    dx * dx + dy * dy
+}
+
+// a lower bound of `distance2` for everything inside a node. The far edges
+// are included here, since an empty rectangle may lie right on them
+fn reach2(bounds: &Rectangle<u64>, x: u32, y: u32) -> u128 {
+    let dx = gap(x, bounds.left(), bounds.right());
+    let dy = gap(y, bounds.top(), bounds.bottom());
+    dx * dx + dy * dy
+}
+
+// the STR step shared by leaves and branches: cut `items` into groups of
+// at most `MAX_ENTRIES`, close together in both directions
+fn tile<T, F>(mut items: Vec<T>, key: F) -> Vec<Vec<T>>
+where
+    F: Fn(&T) -> (u128, u128),
+{
+    if items.is_empty() {
+        return Vec::new();
+    }
+
+    let nodes = items.len().div_ceil(MAX_ENTRIES);
+    let slices = (nodes as f64).sqrt().ceil() as usize;
+    let per_slice = slices * MAX_ENTRIES;
+
+    items.sort_by_key(|item| key(item).0);
+    let mut groups = Vec::with_capacity(nodes);
+    let mut items = items.into_iter().peekable();
+    while items.peek().is_some() {
+        let mut slice: Vec<T> = items.by_ref().take(per_slice).collect();
+        slice.sort_by_key(|item| key(item).1);
+
+        let mut slice = slice.into_iter().peekable();
+        while slice.peek().is_some() {
+            groups.push(slice.by_ref().take(MAX_ENTRIES).collect());
+        }
+    }
+
+    groups
+}
+
+// Guttman's quadratic split: the two entries that would waste the most
+// space together start the two groups, and every other entry joins the
+// group it enlarges the least
+fn split<T, F>(mut items: Vec<T>, bounds: F) -> (Vec<T>, Vec<T>)
+where
+    F: Fn(&T) -> Rectangle<u64>,
+{
+    let mut seeds = (0, 1);
+    let mut worst = 0;
+    for i in 0..items.len() {
+        for j in i + 1..items.len() {
+            let (a, b) = (bounds(&items[i]), bounds(&items[j]));
+            let waste = enclose([a, b])
+                .wide_area()
+                .saturating_sub(a.wide_area() + b.wide_area());
+            if waste >= worst {
+                worst = waste;
+                seeds = (i, j);
+            }
+        }
+    }
+
+    // remove the later index first so the earlier one stays valid
+    let second = items.swap_remove(seeds.1);
+    let first = items.swap_remove(seeds.0);
+    let (mut box_a, mut box_b) = (bounds(&first), bounds(&second));
+    let (mut a, mut b) = (vec![first], vec![second]);
+
+    while let Some(item) = items.pop() {
+        // make sure both groups end up with enough entries
+        let left = items.len() + 1;
+        if a.len() + left <= MIN_ENTRIES {
+            box_a = enclose([box_a, bounds(&item)]);
+            a.push(item);
+            continue;
+        }
+        if b.len() + left <= MIN_ENTRIES {
+            box_b = enclose([box_b, bounds(&item)]);
+            b.push(item);
+            continue;
+        }
+
+        let r = bounds(&item);
+        let grown_a = enclose([box_a, r]);
+        let grown_b = enclose([box_b, r]);
+        let cost_a = grown_a.wide_area() - box_a.wide_area();
+        let cost_b = grown_b.wide_area() - box_b.wide_area();
+        if (cost_a, a.len()) <= (cost_b, b.len()) {
+            box_a = grown_a;
+            a.push(item);
+        } else {
+            box_b = grown_b;
+            b.push(item);
+        }
+    }
+
+    (a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // rectangles near the origin and near the far end of the u32 range,
+    // some of them empty
+    fn coordinate() -> impl Strategy<Value = u32> {
+        prop_oneof![0u32..120, (u32::MAX - 120)..=u32::MAX]
+    }
+
+    fn rect() -> impl Strategy<Value = Rectangle> {
+        (coordinate(), coordinate(), 0u32..40, 0u32..40)
+            .prop_map(|(x, y, w, h)| Rectangle::new(x, y, w, h))
+    }
+
+    fn rects() -> impl Strategy<Value = Vec<Rectangle>> {
+        prop::collection::vec(rect(), 0..80)
+    }
+
+    // the distance a plain scan finds: to the nearest covered point, or
+    // to the position of an empty rectangle
+    fn brute_distance(r: &Rectangle, x: u32, y: u32) -> f64 {
+        let axis = |p: u32, low: u32, size: u32| {
+            let high = (low as u64 + size as u64).saturating_sub(1).max(low as u64);
+            (p as u64).clamp(low as u64, high).abs_diff(p as u64) as u128
+        };
+        let (dx, dy) = (axis(x, r.x, r.width), axis(y, r.y, r.height));
+        ((dx * dx + dy * dy) as f64).sqrt()
+    }
+
+    struct Both {
+        index: SpatialIndex<usize>,
+        all: Vec<(usize, Rectangle)>,
+    }
+
+    impl Both {
+        fn check(&self, probes: &[Rectangle]) {
+            assert_eq!(self.index.len(), self.all.len());
+
+            let mut pairs: Vec<(usize, usize)> = self
+                .index
+                .overlapping_pairs()
+                .into_iter()
+                .map(|(a, b)| (a.min(b), a.max(b)))
+                .collect();
+            pairs.sort();
+            let mut expected = Vec::new();
+            for (i, (a, ra)) in self.all.iter().enumerate() {
+                for (b, rb) in &self.all[i + 1..] {
+                    if ra.intersects(rb) {
+                        expected.push((*a.min(b), *a.max(b)));
+                    }
+                }
+            }
+            expected.sort();
+            assert_eq!(pairs, expected);
+
+            for probe in probes {
+                let mut found = self.index.query_window(probe);
+                found.sort();
+                let mut expected: Vec<usize> = self
+                    .all
+                    .iter()
+                    .filter(|(_, r)| r.intersects(probe))
+                    .map(|&(id, _)| id)
+                    .collect();
+                expected.sort();
+                assert_eq!(found, expected, "window {probe:?}");
+
+                for (x, y) in [(probe.x, probe.y), (u32::MAX, probe.y), (probe.x, u32::MAX)] {
+                    let mut found = self.index.query_point(x, y);
+                    found.sort();
+                    let mut expected: Vec<usize> = self
+                        .all
+                        .iter()
+                        .filter(|(_, r)| r.contains_point(x, y))
+                        .map(|&(id, _)| id)
+                        .collect();
+                    expected.sort();
+                    assert_eq!(found, expected, "point ({x}, {y})");
+
+                    let k = 5;
+                    let nearest: Vec<f64> = self
+                        .index
+                        .nearest(x, y, k)
+                        .iter()
+                        .map(|&(_, d)| d)
+                        .collect();
+                    let mut expected: Vec<f64> = self
+                        .all
+                        .iter()
+                        .map(|(_, r)| brute_distance(r, x, y))
+                        .collect();
+                    expected.sort_by(f64::total_cmp);
+                    expected.truncate(k);
+                    assert_eq!(nearest, expected, "nearest to ({x}, {y})");
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn matches_a_plain_scan(
+            loaded in rects(),
+            inserted in rects(),
+            removed in prop::collection::vec(any::<prop::sample::Index>(), 0..60),
+            probes in prop::collection::vec(rect(), 1..6),
+        ) {
+            let all: Vec<(usize, Rectangle)> = loaded.into_iter().enumerate().collect();
+            let mut both = Both { index: SpatialIndex::bulk_load(all.clone()), all };
+            both.check(&probes);
+
+            for r in inserted {
+                let id = both.all.len() + 1000;
+                both.index.insert(id, r);
+                both.all.push((id, r));
+            }
+            both.check(&probes);
+
+            let mut taken = Vec::new();
+            for i in removed {
+                if both.all.is_empty() {
+                    break;
+                }
+                let (id, r) = both.all.swap_remove(i.index(both.all.len()));
+                prop_assert!(both.index.remove(id, &r));
+                prop_assert!(!both.index.remove(id, &r));
+                taken.push((id, r));
+            }
+            both.check(&probes);
+
+            for (id, r) in taken {
+                both.index.insert(id, r);
+                both.all.push((id, r));
+            }
+            both.check(&probes);
+        }
+    }
+
+    #[test]
+    fn rectangles_at_the_far_end_are_found() {
+        let far = Rectangle::new(u32::MAX - 1, 0, u32::MAX, 10);
+        let mut index = SpatialIndex::bulk_load(vec![(1, Rectangle::new(0, 0, 10, 10)), (2, far)]);
+        assert_eq!(index.query_point(u32::MAX, 5), vec![2]);
+        assert_eq!(index.nearest(u32::MAX, 5, 1), vec![(2, 0.0)]);
+        assert!(index.remove(2, &far));
+        assert_eq!(index.query_point(u32::MAX, 5), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn the_far_edges_are_not_part_of_a_rectangle() {
+        let index = SpatialIndex::bulk_load(vec![(1, Rectangle::new(0, 0, 10, 10))]);
+        assert_eq!(index.nearest(10, 5, 1), vec![(1, 1.0)]);
+        assert_eq!(index.nearest(9, 9, 1), vec![(1, 0.0)]);
+        assert_eq!(index.nearest(12, 13, 1), vec![(1, 5.0)]);
+    }
+}
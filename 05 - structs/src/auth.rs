@@ -0,0 +1,436 @@
+use crate::store::{Backend, StoreError, UserId, UserStore};
+use crate::user::{Email, Username};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+// passwords are never stored, only an Argon2id hash of them with a random
+// salt, written as a PHC string (`$argon2id$v=19$m=19456,t=2,p=1$...`).
+// Argon2 is memory-hard: every guess costs about 19 MiB of memory as well
+// as time, which makes guessing on graphics cards or custom chips much
+// more expensive than with a plain hash. The parameters are part of the
+// string, so hashes made with older settings keep verifying.
+//
+// `verify_password` compares the derived hash with the stored one in
+// constant time, so the time it takes does not tell how many leading
+// bytes of a guess were right.
+
+pub const PASSWORD_MIN: usize = 8;
+
+#[derive(Debug)]
+pub enum AuthError {
+    // the login does not exist, has no password, or the password is
+    // wrong. These are one error on purpose, so that a caller cannot find
+    // out which logins exist
+    InvalidCredentials,
+    Locked { until: SystemTime },
+    Inactive,
+    WeakPassword,
+    Hash(argon2::password_hash::Error),
+    Store(StoreError),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::InvalidCredentials => write!(f, "invalid login or password"),
+            AuthError::Locked { until } => {
+                let left = until
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO);
+                write!(
+                    f,
+                    "too many failed sign-ins, try again in {}s",
+                    left.as_secs() + 1
+                )
+            }
+            AuthError::Inactive => write!(f, "the account is deactivated"),
+            AuthError::WeakPassword => {
+                write!(f, "passwords need at least {} characters", PASSWORD_MIN)
+            }
+            AuthError::Hash(e) => write!(f, "password hashing failed: {}", e),
+            AuthError::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+impl From<StoreError> for AuthError {
+    fn from(e: StoreError) -> AuthError {
+        AuthError::Store(e)
+    }
+}
+
+impl From<argon2::password_hash::Error> for AuthError {
+    fn from(e: argon2::password_hash::Error) -> AuthError {
+        AuthError::Hash(e)
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    if password.chars().count() < PASSWORD_MIN {
+        return Err(AuthError::WeakPassword);
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+// false for a wrong password and for a hash that cannot be read
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+// after `max_failures` wrong passwords in a row an account is locked for
+// `base_delay`, and every further failure doubles that, up to `max_delay`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    pub max_failures: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> LockoutPolicy {
+        LockoutPolicy {
+            max_failures: 5,
+            base_delay: Duration::from_secs(30),
+            max_delay: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl LockoutPolicy {
+    // how long to lock after `failures` failures in a row, if at all
+    pub fn delay(&self, failures: u32) -> Option<Duration> {
+        let extra = failures.checked_sub(self.max_failures)?;
+        let factor = 2u32.checked_pow(extra).unwrap_or(u32::MAX);
+        Some(
+            self.base_delay
+                .checked_mul(factor)
+                .unwrap_or(self.max_delay)
+                .min(self.max_delay),
+        )
+    }
+}
+
+// what the authenticator knows about an account besides the `User`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    pub password_hash: Option<String>,
+    pub failed_attempts: u32,
+    pub locked_until: Option<SystemTime>,
+    pub last_sign_in: Option<SystemTime>,
+}
+
+// signs users of a `UserStore` in. The store is passed to each call
+// instead of being held, so the same store can be used elsewhere between
+// sign-ins.
+//
+// the accounts are not part of the users, so the store's log does not
+// keep them. An authenticator made with `open` writes them to a file of
+// its own after every change, which is best kept next to the log, e.g.
+// at `store.backend().path().with_extension("accounts")`
+#[derive(Debug, Default)]
+pub struct Authenticator {
+    policy: LockoutPolicy,
+    accounts: HashMap<UserId, Account>,
+    path: Option<PathBuf>,
+}
+
+impl Authenticator {
+    // an authenticator that keeps its accounts in memory only
+    pub fn new(policy: LockoutPolicy) -> Authenticator {
+        Authenticator {
+            policy,
+            accounts: HashMap::new(),
+            path: None,
+        }
+    }
+
+    // reads the accounts saved at `path`, if there are any yet
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        policy: LockoutPolicy,
+    ) -> Result<Authenticator, AuthError> {
+        let path = path.as_ref().to_path_buf();
+        let accounts = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| StoreError::Corrupt {
+                line: e.line(),
+                message: e.to_string(),
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(StoreError::Io(e).into()),
+        };
+        Ok(Authenticator {
+            policy,
+            accounts,
+            path: Some(path),
+        })
+    }
+
+    pub fn account(&self, id: UserId) -> Option<&Account> {
+        self.accounts.get(&id)
+    }
+
+    pub fn set_password<B: Backend>(
+        &mut self,
+        store: &UserStore<B>,
+        id: UserId,
+        password: &str,
+    ) -> Result<(), AuthError> {
+        if store.get(id).is_none() {
+            return Err(StoreError::NotFound(id).into());
+        }
+        let hash = hash_password(password)?;
+        self.accounts.entry(id).or_default().password_hash = Some(hash);
+        self.save()
+    }
+
+    pub fn sign_in<B: Backend>(
+        &mut self,
+        store: &mut UserStore<B>,
+        login: &str,
+        password: &str,
+    ) -> Result<UserId, AuthError> {
+        self.sign_in_at(store, login, password, SystemTime::now())
+    }
+
+    // `sign_in` at a given time. The login is a username or an email
+    pub fn sign_in_at<B: Backend>(
+        &mut self,
+        store: &mut UserStore<B>,
+        login: &str,
+        password: &str,
+        now: SystemTime,
+    ) -> Result<UserId, AuthError> {
+        let id = find(store, login);
+        let account = id.and_then(|id| self.accounts.get_mut(&id).map(|a| (id, a)));
+        let (id, account) = match account {
+            Some((id, account)) if account.password_hash.is_some() => (id, account),
+            _ => {
+                // hash anyway, so an unknown login takes as long to reject
+                // as a wrong password
+                verify_password(password, dummy_hash());
+                return Err(AuthError::InvalidCredentials);
+            }
+        };
+
+        if let Some(until) = account.locked_until {
+            if now < until {
+                // the same work as for a wrong password, so how long this
+                // takes does not tell a locked account from others
+                verify_password(password, dummy_hash());
+                return Err(AuthError::Locked { until });
+            }
+        }
+
+        let hash = account.password_hash.as_deref().unwrap_or_default();
+        if !verify_password(password, hash) {
+            account.failed_attempts += 1;
+            account.locked_until = self.policy.delay(account.failed_attempts).map(|d| now + d);
+            self.save()?;
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        // checked after the password, so that a wrong one does not reveal
+        // that the account is deactivated
+        let mut user = store.get(id).cloned().ok_or(StoreError::NotFound(id))?;
+        if !user.active {
+            return Err(AuthError::Inactive);
+        }
+
+        user.sign_in_count += 1;
+        store.update(id, user)?;
+        account.failed_attempts = 0;
+        account.locked_until = None;
+        account.last_sign_in = Some(now);
+        self.save()?;
+        Ok(id)
+    }
+
+    // deactivated users keep their password, but cannot sign in until an
+    // update makes them active again
+    pub fn deactivate<B: Backend>(
+        &mut self,
+        store: &mut UserStore<B>,
+        id: UserId,
+    ) -> Result<(), AuthError> {
+        store.deactivate(id)?;
+        if let Some(account) = self.accounts.get_mut(&id) {
+            account.locked_until = None;
+            account.failed_attempts = 0;
+        }
+        self.save()
+    }
+
+    // rewrites the accounts file, if there is one. Like `compact` on the
+    // store's log, it is written next to the old one and renamed over it
+    fn save(&self) -> Result<(), AuthError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let write = || -> io::Result<()> {
+            let tmp = path.with_extension("tmp");
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            serde_json::to_writer(&mut out, &self.accounts)?;
+            out.write_all(b"\n")?;
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            fs::rename(&tmp, path)
+        };
+        write().map_err(|e| StoreError::Io(e).into())
+    }
+}
+
+fn find<B: Backend>(store: &UserStore<B>, login: &str) -> Option<UserId> {
+    if login.contains('@') {
+        let email = Email::parse(login).ok()?;
+        store.find_by_email(&email).map(|(id, _)| id)
+    } else {
+        let username = Username::parse(login).ok()?;
+        store.find_by_username(&username).map(|(id, _)| id)
+    }
+}
+
+// a hash of a password nobody knows, made once
+fn dummy_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| hash_password("not a real password").unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::User;
+    use std::env;
+    use std::process;
+    use std::time::Instant;
+
+    const PASSWORD: &str = "correct horse battery";
+
+    fn store_with_ada() -> (UserStore, UserId) {
+        let mut store = UserStore::in_memory();
+        let ada = User::builder()
+            .email("ada@example.com")
+            .username("ada")
+            .build()
+            .expect("valid user");
+        let id = store.create(ada).expect("unique");
+        (store, id)
+    }
+
+    fn policy() -> LockoutPolicy {
+        LockoutPolicy {
+            max_failures: 1,
+            ..LockoutPolicy::default()
+        }
+    }
+
+    #[test]
+    fn only_the_right_password_verifies() {
+        let hash = hash_password(PASSWORD).expect("long enough");
+        assert!(verify_password(PASSWORD, &hash));
+        assert!(!verify_password("correct horse batterz", &hash));
+        assert!(!verify_password("xorrect horse battery", &hash));
+        assert!(!verify_password(PASSWORD, "not a hash"));
+        assert!(matches!(
+            hash_password("short"),
+            Err(AuthError::WeakPassword)
+        ));
+    }
+
+    #[test]
+    fn hashes_are_salted() {
+        let a = hash_password(PASSWORD).expect("long enough");
+        let b = hash_password(PASSWORD).expect("long enough");
+        assert_ne!(a, b);
+        assert!(!a.contains(PASSWORD));
+    }
+
+    // every way of being turned away runs one Argon2 verification, so an
+    // unknown login or a locked account is not answered noticeably faster
+    // than a wrong password. The bound is loose, to keep this from failing
+    // on a busy machine
+    #[test]
+    fn rejections_take_as_long_as_a_wrong_password() {
+        let (mut store, id) = store_with_ada();
+        let mut auth = Authenticator::new(policy());
+        auth.set_password(&store, id, PASSWORD).expect("ada exists");
+        dummy_hash();
+
+        let time = |auth: &mut Authenticator, store: &mut UserStore, login: &str| {
+            let start = Instant::now();
+            let result = auth.sign_in(store, login, "wrong password");
+            (start.elapsed(), result)
+        };
+
+        let (unknown, result) = time(&mut auth, &mut store, "nobody");
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+        let (wrong, result) = time(&mut auth, &mut store, "ada");
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+        let (locked, result) = time(&mut auth, &mut store, "ada");
+        assert!(matches!(result, Err(AuthError::Locked { .. })));
+
+        assert!(unknown * 4 > wrong, "{:?} vs {:?}", unknown, wrong);
+        assert!(locked * 4 > wrong, "{:?} vs {:?}", locked, wrong);
+    }
+
+    #[test]
+    fn lockout_ends_and_success_resets_it() {
+        let (mut store, id) = store_with_ada();
+        let mut auth = Authenticator::new(policy());
+        auth.set_password(&store, id, PASSWORD).expect("ada exists");
+        let start = SystemTime::now();
+
+        let result = auth.sign_in_at(&mut store, "ada", "wrong password", start);
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+        let result = auth.sign_in_at(&mut store, "ada", PASSWORD, start);
+        assert!(matches!(result, Err(AuthError::Locked { .. })));
+
+        let later = start + policy().base_delay;
+        let result = auth.sign_in_at(&mut store, "ada@example.com", PASSWORD, later);
+        assert_eq!(result.expect("lock is over"), id);
+        let account = auth.account(id).expect("has a password");
+        assert_eq!(account.failed_attempts, 0);
+        assert_eq!(account.locked_until, None);
+        assert_eq!(account.last_sign_in, Some(later));
+        assert_eq!(store.get(id).expect("ada exists").sign_in_count, 1);
+    }
+
+    #[test]
+    fn accounts_survive_reopening() {
+        let path = env::temp_dir().join(format!("structs-accounts-{}.json", process::id()));
+        let _ = fs::remove_file(&path);
+        let (mut store, id) = store_with_ada();
+        let now = SystemTime::now();
+        {
+            let mut auth = Authenticator::open(&path, policy()).expect("opens");
+            auth.set_password(&store, id, PASSWORD).expect("ada exists");
+            let result = auth.sign_in_at(&mut store, "ada", "wrong password", now);
+            assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+        }
+
+        let mut auth = Authenticator::open(&path, policy()).expect("opens");
+        let account = auth.account(id).expect("was saved").clone();
+        assert_eq!(account.failed_attempts, 1);
+        assert!(account.locked_until.is_some());
+        let result = auth.sign_in_at(&mut store, "ada", PASSWORD, now);
+        assert!(matches!(result, Err(AuthError::Locked { .. })));
+        let _ = fs::remove_file(&path);
+    }
+}
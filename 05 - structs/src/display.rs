@@ -0,0 +1,167 @@
+use crate::user::User;
+use std::fmt;
+
+// `{}` on a `User` prints every field, which is right for the owner of the
+// account and wrong for most other places. `user.display(mode)` wraps the
+// user in a value that prints only what the mode allows:
+//
+//     println!("{}", user.display(DisplayMode::Public));
+//
+// `Debug` always hides the email (see `Email`), whatever the mode.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    // the same as `{}`
+    Full,
+    // the email is shortened to `b****@example.com`
+    Redacted,
+    // only the username, as other users would see it
+    Public,
+    // `key=value` pairs separated by spaces, for logs and scripts. Values
+    // with spaces, quotes, `=` or control characters are quoted
+    Machine,
+}
+
+pub struct UserDisplay<'a> {
+    user: &'a User,
+    mode: DisplayMode,
+}
+
+impl User {
+    pub fn display(&self, mode: DisplayMode) -> UserDisplay<'_> {
+        UserDisplay { user: self, mode }
+    }
+}
+
+impl fmt::Display for UserDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let user = self.user;
+        match self.mode {
+            DisplayMode::Full => write!(f, "{}", user),
+            DisplayMode::Redacted => write!(
+                f,
+                "is_active: {}, username: {}, email: {}, sign_in_count: {}",
+                user.active,
+                user.username,
+                user.email.redacted(),
+                user.sign_in_count
+            ),
+            DisplayMode::Public => write!(f, "{}", user.username),
+            DisplayMode::Machine => {
+                let pairs = [
+                    ("active", user.active.to_string()),
+                    ("username", user.username.to_string()),
+                    ("email", user.email.to_string()),
+                    ("sign_in_count", user.sign_in_count.to_string()),
+                ];
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{}=", key)?;
+                    write_value(f, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// writes `value` as it is when it cannot be misread, and quoted with
+// backslash escapes otherwise
+fn write_value(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    let plain = !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '"' | '=' | '\\'));
+    if plain {
+        return f.write_str(value);
+    }
+
+    f.write_str("\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bob() -> User {
+        User::builder()
+            .email("bob.jones@Example.com")
+            .username("Bob")
+            .sign_in_count(3)
+            .build()
+            .expect("valid user")
+    }
+
+    // `write_value` on its own, for values no user field can hold
+    struct Value<'a>(&'a str);
+
+    impl fmt::Display for Value<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write_value(f, self.0)
+        }
+    }
+
+    #[test]
+    fn each_mode_shows_what_it_allows() {
+        let bob = bob();
+        assert_eq!(
+            bob.display(DisplayMode::Full).to_string(),
+            "is_active: true, username: bob, email: bob.jones@example.com, sign_in_count: 3"
+        );
+        assert_eq!(bob.display(DisplayMode::Full).to_string(), bob.to_string());
+        assert_eq!(
+            bob.display(DisplayMode::Redacted).to_string(),
+            "is_active: true, username: bob, email: b****@example.com, sign_in_count: 3"
+        );
+        assert_eq!(bob.display(DisplayMode::Public).to_string(), "bob");
+        assert_eq!(
+            bob.display(DisplayMode::Machine).to_string(),
+            "active=true username=bob email=bob.jones@example.com sign_in_count=3"
+        );
+    }
+
+    #[test]
+    fn debug_hides_the_email() {
+        let debug = format!("{:?}", bob());
+        assert!(debug.contains("b****@example.com"), "{debug}");
+        assert!(!debug.contains("bob.jones"), "{debug}");
+        let pretty = format!("{:#?}", bob());
+        assert!(!pretty.contains("bob.jones"), "{pretty}");
+    }
+
+    #[test]
+    fn machine_values_are_quoted_when_needed() {
+        let user = User::builder()
+            .email("a=b@example.com")
+            .username("ann")
+            .build()
+            .expect("valid user");
+        assert_eq!(
+            user.display(DisplayMode::Machine).to_string(),
+            "active=true username=ann email=\"a=b@example.com\" sign_in_count=0"
+        );
+
+        assert_eq!(Value("plain").to_string(), "plain");
+        assert_eq!(Value("").to_string(), "\"\"");
+        assert_eq!(Value("two words").to_string(), "\"two words\"");
+        assert_eq!(Value("say \"hi\"").to_string(), r#""say \"hi\"""#);
+        assert_eq!(Value(r"back\slash").to_string(), r#""back\\slash""#);
+        assert_eq!(Value("a\nb\r\tc").to_string(), r#""a\nb\r\tc""#);
+        assert_eq!(Value("bell\u{7}").to_string(), r#""bell\u{7}""#);
+        assert_eq!(Value("größe").to_string(), "größe");
+    }
+}
@@ -0,0 +1,730 @@
+use crate::user::User;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// a small language for picking users, for example
+//
+//     active = true and sign_in_count > 10 and email ends_with "@example.org"
+//
+// a filter is a list of comparisons `field operator value` joined with
+// `and`, `or` and `not`, and grouped with parentheses. `not` binds
+// tightest and `or` loosest, as in most languages.
+//
+//     field     active, username, email, sign_in_count
+//     operator  = != < <= > >= contains starts_with ends_with
+//     value     true, false, a whole number, or a "string" in which \" and
+//               \\ stand for a quote and a backslash
+//
+// the fields have types, and a filter is checked against them when it is
+// parsed: `active > 3` is an error, not a filter that matches nothing.
+// Errors carry the column (from 1, in characters) where they were found.
+//
+// usernames are stored in lowercase, so strings compared with `username`
+// are lowercased too, and `username = "Alice"` finds `alice`. Emails are
+// compared without regard to ASCII case, since the domain is stored in
+// lowercase and the local part as it was written.
+//
+// parentheses and `not` can be nested at most `MAX_DEPTH` deep. A chain
+// of `and` or `or` does not nest at all: its operands are kept side by
+// side in one list. Together that keeps the recursion of the parser, of
+// `matches` and of dropping a filter shallow, however long the filter.
+
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Bool,
+    Number,
+    Text,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Type::Bool => "a boolean",
+            Type::Number => "a number",
+            Type::Text => "a string",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    NumberTooLarge,
+    // what was expected, and what was found instead
+    Expected(&'static str, String),
+    UnknownField(String),
+    TypeMismatch {
+        field: &'static str,
+        expected: Type,
+        found: Type,
+    },
+    // an operator the field's type does not have, like `<` on a string
+    InvalidOperator {
+        operator: &'static str,
+        field: &'static str,
+    },
+    TooDeep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    pub column: usize,
+    pub kind: FilterErrorKind,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: ", self.column)?;
+        match &self.kind {
+            FilterErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected `{}`", c),
+            FilterErrorKind::UnterminatedString => write!(f, "string is not closed"),
+            FilterErrorKind::NumberTooLarge => write!(f, "number is too large"),
+            FilterErrorKind::Expected(what, found) => {
+                write!(f, "expected {}, found {}", what, found)
+            }
+            FilterErrorKind::UnknownField(name) => write!(f, "unknown field `{}`", name),
+            FilterErrorKind::TypeMismatch {
+                field,
+                expected,
+                found,
+            } => write!(f, "`{}` is {}, not {}", field, expected, found),
+            FilterErrorKind::InvalidOperator { operator, field } => {
+                write!(f, "`{}` cannot be used with `{}`", operator, field)
+            }
+            FilterErrorKind::TooDeep => {
+                write!(f, "nested more than {} levels deep", MAX_DEPTH)
+            }
+        }
+    }
+}
+
+impl Error for FilterError {}
+
+impl FilterError {
+    // the filter with a caret under the column, for showing on a terminal
+    pub fn show(&self, source: &str) -> String {
+        format!(
+            "{}\n{}^\n{}",
+            source,
+            " ".repeat(self.column.saturating_sub(1)),
+            self
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Active,
+    Username,
+    Email,
+    SignInCount,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name {
+            "active" => Some(Field::Active),
+            "username" => Some(Field::Username),
+            "email" => Some(Field::Email),
+            "sign_in_count" => Some(Field::SignInCount),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Active => "active",
+            Field::Username => "username",
+            Field::Email => "email",
+            Field::SignInCount => "sign_in_count",
+        }
+    }
+
+    pub fn kind(self) -> Type {
+        match self {
+            Field::Active => Type::Bool,
+            Field::Username | Field::Email => Type::Text,
+            Field::SignInCount => Type::Number,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+impl Operator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operator::Eq => "=",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Contains => "contains",
+            Operator::StartsWith => "starts_with",
+            Operator::EndsWith => "ends_with",
+        }
+    }
+
+    fn applies_to(self, kind: Type) -> bool {
+        match self {
+            Operator::Eq | Operator::Ne => true,
+            Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge => kind == Type::Number,
+            Operator::Contains | Operator::StartsWith | Operator::EndsWith => kind == Type::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Number(u64),
+    Text(String),
+}
+
+impl Value {
+    pub fn kind(&self) -> Type {
+        match self {
+            Value::Bool(_) => Type::Bool,
+            Value::Number(_) => Type::Number,
+            Value::Text(_) => Type::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Compare {
+        field: Field,
+        operator: Operator,
+        value: Value,
+    },
+    Not(Box<Expr>),
+    // two or more operands
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    pub fn matches(&self, user: &User) -> bool {
+        match self {
+            Expr::Compare {
+                field,
+                operator,
+                value,
+            } => compare(user, *field, *operator, value),
+            Expr::Not(e) => !e.matches(user),
+            Expr::And(all) => all.iter().all(|e| e.matches(user)),
+            Expr::Or(any) => any.iter().any(|e| e.matches(user)),
+        }
+    }
+}
+
+// the checks in the parser make sure the value has the field's type, so
+// the mixed cases cannot happen
+fn compare(user: &User, field: Field, operator: Operator, value: &Value) -> bool {
+    match (field, value) {
+        (Field::Active, Value::Bool(b)) => match operator {
+            Operator::Ne => user.active != *b,
+            _ => user.active == *b,
+        },
+        (Field::SignInCount, Value::Number(n)) => {
+            let count = user.sign_in_count;
+            match operator {
+                Operator::Eq => count == *n,
+                Operator::Ne => count != *n,
+                Operator::Lt => count < *n,
+                Operator::Le => count <= *n,
+                Operator::Gt => count > *n,
+                Operator::Ge => count >= *n,
+                _ => false,
+            }
+        }
+        (Field::Username | Field::Email, Value::Text(s)) => {
+            // the email is lowercased here, the value was when it was parsed
+            let email;
+            let text = match field {
+                Field::Username => user.username.as_str(),
+                _ => {
+                    email = user.email.as_str().to_ascii_lowercase();
+                    email.as_str()
+                }
+            };
+            match operator {
+                Operator::Eq => text == s,
+                Operator::Ne => text != s,
+                Operator::Contains => text.contains(s.as_str()),
+                Operator::StartsWith => text.starts_with(s.as_str()),
+                Operator::EndsWith => text.ends_with(s.as_str()),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// a parsed and checked filter, and the text it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, FilterError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            (Token::End, _) => Ok(Filter {
+                source: source.to_string(),
+                expr,
+            }),
+            (token, column) => Err(FilterError {
+                column,
+                kind: FilterErrorKind::Expected("`and`, `or` or the end", token.describe()),
+            }),
+        }
+    }
+
+    pub fn matches(&self, user: &User) -> bool {
+        self.expr.matches(user)
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Filter, FilterError> {
+        Filter::parse(s)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Number(u64),
+    Text(String),
+    Symbol(&'static str),
+    Open,
+    Close,
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(w) => format!("`{}`", w),
+            Token::Number(n) => format!("`{}`", n),
+            Token::Text(s) => format!("\"{}\"", s),
+            Token::Symbol(s) => format!("`{}`", s),
+            Token::Open => "`(`".to_string(),
+            Token::Close => "`)`".to_string(),
+            Token::End => "the end".to_string(),
+        }
+    }
+}
+
+// the tokens, each with the column it starts at
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, FilterError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Token::Word(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            let n = digits.parse().map_err(|_| FilterError {
+                column,
+                kind: FilterErrorKind::NumberTooLarge,
+            })?;
+            Token::Number(n)
+        } else if c == '"' {
+            i += 1;
+            let mut text = String::new();
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(FilterError {
+                            column,
+                            kind: FilterErrorKind::UnterminatedString,
+                        })
+                    }
+                    Some('"') => break,
+                    Some('\\') if matches!(chars.get(i + 1), Some('"' | '\\')) => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&c) => {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            Token::Text(text)
+        } else {
+            let next = chars.get(i + 1).copied();
+            let (symbol, length) = match (c, next) {
+                ('!', Some('=')) => ("!=", 2),
+                ('<', Some('=')) => ("<=", 2),
+                ('>', Some('=')) => (">=", 2),
+                ('=', Some('=')) => ("=", 2),
+                ('=', _) => ("=", 1),
+                ('<', _) => ("<", 1),
+                ('>', _) => (">", 1),
+                ('(', _) => ("(", 1),
+                (')', _) => (")", 1),
+                _ => {
+                    return Err(FilterError {
+                        column,
+                        kind: FilterErrorKind::UnexpectedCharacter(c),
+                    })
+                }
+            };
+            i += length;
+            match symbol {
+                "(" => Token::Open,
+                ")" => Token::Close,
+                s => Token::Symbol(s),
+            }
+        };
+        tokens.push((token, column));
+    }
+
+    tokens.push((Token::End, chars.len() + 1));
+    Ok(tokens)
+}
+
+// a single operand stands for itself
+fn join(mut operands: Vec<Expr>, make: fn(Vec<Expr>) -> Expr) -> Expr {
+    if operands.len() == 1 {
+        operands.pop().unwrap()
+    } else {
+        make(operands)
+    }
+}
+
+// a recursive descent parser, one method per level of precedence
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    // how many `(` and `not` the parser is inside of
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> (Token, usize) {
+        self.tokens[self.next].clone()
+    }
+
+    fn advance(&mut self) -> (Token, usize) {
+        let token = self.peek();
+        if token.0 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        if matches!(&self.tokens[self.next].0, Token::Word(w) if w == word) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // goes one level deeper for `f`, if that is allowed
+    fn nested<F>(&mut self, column: usize, f: F) -> Result<Expr, FilterError>
+    where
+        F: FnOnce(&mut Parser) -> Result<Expr, FilterError>,
+    {
+        if self.depth == MAX_DEPTH {
+            return Err(FilterError {
+                column,
+                kind: FilterErrorKind::TooDeep,
+            });
+        }
+        self.depth += 1;
+        let expr = f(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut operands = vec![self.and()?];
+        while self.keyword("or") {
+            operands.push(self.and()?);
+        }
+        Ok(join(operands, Expr::Or))
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut operands = vec![self.not()?];
+        while self.keyword("and") {
+            operands.push(self.not()?);
+        }
+        Ok(join(operands, Expr::And))
+    }
+
+    fn not(&mut self) -> Result<Expr, FilterError> {
+        let column = self.peek().1;
+        if self.keyword("not") {
+            let expr = self.nested(column, Parser::not)?;
+            Ok(Expr::Not(Box::new(expr)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, FilterError> {
+        match self.advance() {
+            (Token::Open, column) => {
+                let expr = self.nested(column, Parser::or)?;
+                match self.advance() {
+                    (Token::Close, _) => Ok(expr),
+                    (token, column) => Err(FilterError {
+                        column,
+                        kind: FilterErrorKind::Expected("`)`", token.describe()),
+                    }),
+                }
+            }
+            (Token::Word(name), column) => self.comparison(&name, column),
+            (token, column) => Err(FilterError {
+                column,
+                kind: FilterErrorKind::Expected("a field or `(`", token.describe()),
+            }),
+        }
+    }
+
+    fn comparison(&mut self, name: &str, field_column: usize) -> Result<Expr, FilterError> {
+        let field = Field::from_name(name).ok_or_else(|| FilterError {
+            column: field_column,
+            kind: FilterErrorKind::UnknownField(name.to_string()),
+        })?;
+
+        let (token, column) = self.advance();
+        let operator = match &token {
+            Token::Symbol("=") => Operator::Eq,
+            Token::Symbol("!=") => Operator::Ne,
+            Token::Symbol("<") => Operator::Lt,
+            Token::Symbol("<=") => Operator::Le,
+            Token::Symbol(">") => Operator::Gt,
+            Token::Symbol(">=") => Operator::Ge,
+            Token::Word(w) if w == "contains" => Operator::Contains,
+            Token::Word(w) if w == "starts_with" => Operator::StartsWith,
+            Token::Word(w) if w == "ends_with" => Operator::EndsWith,
+            _ => {
+                return Err(FilterError {
+                    column,
+                    kind: FilterErrorKind::Expected("an operator", token.describe()),
+                })
+            }
+        };
+        if !operator.applies_to(field.kind()) {
+            return Err(FilterError {
+                column,
+                kind: FilterErrorKind::InvalidOperator {
+                    operator: operator.symbol(),
+                    field: field.name(),
+                },
+            });
+        }
+
+        let (token, column) = self.advance();
+        let value = match token {
+            Token::Word(w) if w == "true" => Value::Bool(true),
+            Token::Word(w) if w == "false" => Value::Bool(false),
+            Token::Number(n) => Value::Number(n),
+            Token::Text(s) => Value::Text(s.to_ascii_lowercase()),
+            token => {
+                return Err(FilterError {
+                    column,
+                    kind: FilterErrorKind::Expected("a value", token.describe()),
+                })
+            }
+        };
+        if value.kind() != field.kind() {
+            return Err(FilterError {
+                column,
+                kind: FilterErrorKind::TypeMismatch {
+                    field: field.name(),
+                    expected: field.kind(),
+                    found: value.kind(),
+                },
+            });
+        }
+
+        Ok(Expr::Compare {
+            field,
+            operator,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alice() -> User {
+        User::builder()
+            .email("Alice.Smith@Example.org")
+            .username("Alice")
+            .sign_in_count(12)
+            .build()
+            .expect("valid user")
+    }
+
+    fn matches(source: &str) -> bool {
+        Filter::parse(source)
+            .expect("valid filter")
+            .matches(&alice())
+    }
+
+    #[test]
+    fn strings_are_compared_without_case() {
+        assert!(matches(r#"username = "Alice""#));
+        assert!(matches(r#"username starts_with "AL""#));
+        assert!(matches(r#"email = "alice.smith@example.org""#));
+        assert!(matches(r#"email ends_with "@EXAMPLE.ORG""#));
+        assert!(matches(r#"email contains "Smith""#));
+        assert!(!matches(r#"username != "ALICE""#));
+    }
+
+    #[test]
+    fn precedence_and_grouping() {
+        assert!(matches(
+            "active = false or sign_in_count > 10 and not active = false"
+        ));
+        assert!(!matches(
+            "(active = false or sign_in_count > 10) and active = false"
+        ));
+        assert!(matches("not not active = true"));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let ok = format!(
+            "{}active = true{}",
+            "(".repeat(MAX_DEPTH),
+            ")".repeat(MAX_DEPTH)
+        );
+        assert!(Filter::parse(&ok).is_ok());
+
+        let deep = format!(
+            "{}active = true{}",
+            "(".repeat(MAX_DEPTH + 1),
+            ")".repeat(MAX_DEPTH + 1)
+        );
+        let error = Filter::parse(&deep).expect_err("too deep");
+        assert_eq!(error.kind, FilterErrorKind::TooDeep);
+        assert_eq!(error.column, MAX_DEPTH + 1);
+
+        let nots = format!("{}active = true", "not ".repeat(100_000));
+        let error = Filter::parse(&nots).expect_err("too deep");
+        assert_eq!(error.kind, FilterErrorKind::TooDeep);
+        let parens = "(".repeat(100_000);
+        assert_eq!(
+            Filter::parse(&parens).expect_err("too deep").kind,
+            FilterErrorKind::TooDeep
+        );
+    }
+
+    #[test]
+    fn long_chains_do_not_nest() {
+        // a test thread has a small stack, which a nested chain of this
+        // length used to overflow in `matches` and when it was dropped
+        let terms = 50_000;
+        let ands = vec!["active = true"; terms].join(" and ");
+        let filter = Filter::parse(&ands).expect("valid filter");
+        match filter.expr() {
+            Expr::And(operands) => assert_eq!(operands.len(), terms),
+            other => panic!("expected one `and`, got {:?}", other),
+        }
+        assert!(filter.matches(&alice()));
+        drop(filter);
+
+        let ors = vec!["active = false"; terms].join(" or ") + " or sign_in_count = 12";
+        assert!(matches(&ors));
+        let mixed = vec!["active = false and active = true"; terms].join(" or ");
+        assert!(!matches(&mixed));
+    }
+
+    #[test]
+    fn chains_keep_their_precedence() {
+        let filter = Filter::parse("active = true or active = false and sign_in_count = 1")
+            .expect("valid filter");
+        match filter.expr() {
+            Expr::Or(operands) => {
+                assert_eq!(operands.len(), 2);
+                assert!(matches!(operands[0], Expr::Compare { .. }));
+                assert!(matches!(&operands[1], Expr::And(all) if all.len() == 2));
+            }
+            other => panic!("expected an `or`, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let error = Filter::parse("active > 3").expect_err("invalid operator");
+        assert_eq!(error.column, 8);
+        let error = Filter::parse(r#"sign_in_count = "a""#).expect_err("type mismatch");
+        assert_eq!(error.column, 17);
+        assert_eq!(
+            error.to_string(),
+            "column 17: `sign_in_count` is a number, not a string"
+        );
+    }
+}
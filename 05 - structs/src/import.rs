@@ -0,0 +1,418 @@
+use crate::store::{Backend, StoreError, UserId, UserStore};
+use crate::user::{BuildError, Email, User, UserBuilder, Username};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// users are exported as CSV with the header
+//
+//     username,email,active,sign_in_count
+//
+// or as a JSON array of objects with the same keys. Neither usernames nor
+// emails can contain a comma or a quote, so the CSV needs no quoting.
+//
+// importing reads such a file row by row. A row that cannot be read or is
+// not a valid user is rejected with the reason, and does not stop the
+// rest; only a file that is not CSV or JSON at all is an `ImportError`.
+// Emails are normalised before they are compared, and a row whose email
+// is already taken, earlier in the file or in the store, is merged with
+// the user that has it as `ImportOptions::on_duplicate` says.
+
+#[derive(Debug)]
+pub enum ImportError {
+    MissingColumn(&'static str),
+    UnknownColumn(String),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::MissingColumn(c) => write!(f, "missing column `{}`", c),
+            ImportError::UnknownColumn(c) => write!(f, "unknown column `{}`", c),
+            ImportError::Json(e) => write!(f, "invalid JSON: {}", e),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+// one user as read from a file, not validated yet. Rows count from 1; in
+// CSV they are line numbers, including the header
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub number: usize,
+    pub user: Result<UserBuilder, String>,
+}
+
+pub fn to_csv<'a, I: IntoIterator<Item = &'a User>>(users: I) -> String {
+    let mut csv = String::from("username,email,active,sign_in_count\n");
+    for u in users {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            u.username, u.email, u.active, u.sign_in_count
+        ));
+    }
+    csv
+}
+
+pub fn to_json<'a, I: IntoIterator<Item = &'a User>>(users: I) -> String {
+    let users: Vec<&User> = users.into_iter().collect();
+    // users are strings, booleans and numbers, which always serialise
+    serde_json::to_string_pretty(&users).unwrap()
+}
+
+// the columns may come in any order; `active` and `sign_in_count` may be
+// left out, and default to a new user's. Blank lines are skipped
+pub fn read_csv(text: &str) -> Result<Vec<Row>, ImportError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty());
+
+    let header: Vec<&str> = match lines.next() {
+        Some((_, h)) => h.split(',').map(str::trim).collect(),
+        None => return Ok(Vec::new()),
+    };
+
+    // where each field is found in a row
+    let mut columns: [Option<usize>; 4] = [None; 4];
+    for (i, name) in header.iter().enumerate() {
+        let field = match *name {
+            "username" => 0,
+            "email" => 1,
+            "active" => 2,
+            "sign_in_count" => 3,
+            other => return Err(ImportError::UnknownColumn(other.to_string())),
+        };
+        columns[field] = Some(i);
+    }
+    let username = columns[0].ok_or(ImportError::MissingColumn("username"))?;
+    let email = columns[1].ok_or(ImportError::MissingColumn("email"))?;
+
+    let rows = lines
+        .map(|(number, line)| {
+            let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+            let user = if cells.len() != header.len() {
+                Err(format!(
+                    "expected {} columns, found {}",
+                    header.len(),
+                    cells.len()
+                ))
+            } else {
+                (|| {
+                    let mut user = User::builder()
+                        .username(cells[username])
+                        .email(cells[email]);
+                    if let Some(c) = columns[2] {
+                        let active = cells[c]
+                            .parse()
+                            .map_err(|_| format!("invalid active `{}`", cells[c]))?;
+                        user = user.active(active);
+                    }
+                    if let Some(c) = columns[3] {
+                        let count = cells[c]
+                            .parse()
+                            .map_err(|_| format!("invalid sign_in_count `{}`", cells[c]))?;
+                        user = user.sign_in_count(count);
+                    }
+                    Ok(user)
+                })()
+            };
+            Row { number, user }
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+// the shape of one user in JSON, before validation
+#[derive(Deserialize)]
+struct JsonUser {
+    username: String,
+    email: String,
+    #[serde(default = "yes")]
+    active: bool,
+    #[serde(default)]
+    sign_in_count: u64,
+}
+
+fn yes() -> bool {
+    true
+}
+
+// an array of users. Rows are the positions in the array
+pub fn read_json(text: &str) -> Result<Vec<Row>, ImportError> {
+    let values: Vec<serde_json::Value> = serde_json::from_str(text).map_err(ImportError::Json)?;
+
+    let rows = values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let user = serde_json::from_value::<JsonUser>(value)
+                .map(|u| {
+                    User::builder()
+                        .username(&u.username)
+                        .email(&u.email)
+                        .active(u.active)
+                        .sign_in_count(u.sign_in_count)
+                })
+                .map_err(|e| e.to_string());
+            Row {
+                number: i + 1,
+                user,
+            }
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+// what to do with a row whose email belongs to a user already
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    Reject,
+    KeepExisting,
+    Replace,
+    // whichever of the two has signed in more often, the existing user on
+    // a tie. The one that is kept is kept whole: when the row wins, its
+    // username and `active` replace the stored ones too, not only the
+    // count
+    KeepHigherSignInCount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportOptions {
+    // `Bob@example.com` and `bob@example.com` are the same address for
+    // nearly every mail server, though not by the standard
+    pub lowercase_local_part: bool,
+    // drop a `+tag` from the local part, so `bob+news@example.com` is
+    // `bob@example.com`
+    pub strip_plus_tag: bool,
+    pub on_duplicate: OnDuplicate,
+}
+
+impl Default for ImportOptions {
+    fn default() -> ImportOptions {
+        ImportOptions {
+            lowercase_local_part: true,
+            strip_plus_tag: false,
+            on_duplicate: OnDuplicate::KeepHigherSignInCount,
+        }
+    }
+}
+
+pub fn normalise(email: &Email, options: &ImportOptions) -> Email {
+    let mut local = email.local_part().to_string();
+    if options.strip_plus_tag {
+        if let Some((base, _)) = local.split_once('+') {
+            if !base.is_empty() {
+                local = base.to_string();
+            }
+        }
+    }
+    if options.lowercase_local_part {
+        local = local.to_lowercase();
+    }
+    // a shorter or lowercased valid local part is still valid
+    Email::parse(&format!("{}@{}", local, email.domain())).unwrap_or_else(|_| email.clone())
+}
+
+#[derive(Debug)]
+pub enum Rejection {
+    Unreadable(String),
+    Invalid(BuildError),
+    // with the row or user that has the email
+    DuplicateInFile { first_row: usize },
+    DuplicateInStore(UserId),
+    UsernameTaken(Username),
+    Store(StoreError),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::Unreadable(reason) => write!(f, "{}", reason),
+            Rejection::Invalid(e) => write!(f, "{}", e),
+            Rejection::DuplicateInFile { first_row } => {
+                write!(f, "same email as row {}", first_row)
+            }
+            Rejection::DuplicateInStore(id) => write!(f, "same email as user {}", id),
+            Rejection::UsernameTaken(u) => write!(f, "username {} is already taken", u),
+            Rejection::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Accepted(UserId),
+    // the row matched this user; `updated` says whether the row replaced
+    // what was stored
+    Merged { id: UserId, updated: bool },
+    Rejected(Rejection),
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub outcomes: Vec<(usize, Outcome)>,
+}
+
+impl Report {
+    pub fn accepted(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Accepted(_)))
+    }
+
+    pub fn merged(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Merged { .. }))
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Rejected(_)))
+    }
+
+    fn count<F: Fn(&Outcome) -> bool>(&self, f: F) -> usize {
+        self.outcomes.iter().filter(|(_, o)| f(o)).count()
+    }
+}
+
+// a summary line, then one line per merged or rejected row
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} accepted, {} merged, {} rejected",
+            self.accepted(),
+            self.merged(),
+            self.rejected()
+        )?;
+        for (row, outcome) in &self.outcomes {
+            match outcome {
+                Outcome::Accepted(_) => {}
+                Outcome::Merged { id, updated } => write!(
+                    f,
+                    "\nrow {}: merged with user {}{}",
+                    row,
+                    id,
+                    if *updated { ", updated" } else { "" }
+                )?,
+                Outcome::Rejected(reason) => write!(f, "\nrow {}: {}", row, reason)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn import<B: Backend>(
+    store: &mut UserStore<B>,
+    rows: Vec<Row>,
+    options: &ImportOptions,
+) -> Report {
+    // the stored users and the rows accepted so far, by normalised email
+    let mut by_email: HashMap<Email, UserId> = store
+        .iter()
+        .map(|(id, u)| (normalise(&u.email, options), id))
+        .collect();
+    let mut seen: HashMap<Email, usize> = HashMap::new();
+
+    let mut report = Report::default();
+    for row in rows {
+        let outcome = (|| {
+            let user = row
+                .user
+                .map_err(Rejection::Unreadable)?
+                .build()
+                .map_err(Rejection::Invalid)?;
+            let user = User {
+                email: normalise(&user.email, options),
+                ..user
+            };
+
+            let id = match by_email.get(&user.email) {
+                Some(&id) => id,
+                None => {
+                    let id = store.create(user.clone()).map_err(store_rejection)?;
+                    seen.insert(user.email.clone(), row.number);
+                    by_email.insert(user.email, id);
+                    return Ok(Outcome::Accepted(id));
+                }
+            };
+
+            let stored = store
+                .get(id)
+                .ok_or(Rejection::Store(StoreError::NotFound(id)))?;
+            let replace = match options.on_duplicate {
+                OnDuplicate::Reject => {
+                    return Err(match seen.get(&user.email) {
+                        Some(&first_row) => Rejection::DuplicateInFile { first_row },
+                        None => Rejection::DuplicateInStore(id),
+                    });
+                }
+                OnDuplicate::KeepExisting => false,
+                OnDuplicate::Replace => *stored != user,
+                OnDuplicate::KeepHigherSignInCount => user.sign_in_count > stored.sign_in_count,
+            };
+            if replace {
+                store.update(id, user).map_err(store_rejection)?;
+            }
+            Ok(Outcome::Merged {
+                id,
+                updated: replace,
+            })
+        })()
+        .unwrap_or_else(Outcome::Rejected);
+
+        report.outcomes.push((row.number, outcome));
+    }
+
+    report
+}
+
+fn store_rejection(e: StoreError) -> Rejection {
+    match e {
+        StoreError::DuplicateUsername(u) => Rejection::UsernameTaken(u),
+        e => Rejection::Store(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_row_with_more_sign_ins_replaces_the_whole_user() {
+        let mut store = UserStore::in_memory();
+        let rows = read_csv(
+            "username,email,active,sign_in_count
+            ada,ada@example.com,true,3
+            ada.l,ada@example.com,false,9",
+        )
+        .expect("valid CSV");
+        let report = import(&mut store, rows, &ImportOptions::default());
+        assert_eq!((report.accepted(), report.merged()), (1, 1));
+
+        let (_, ada) = store.iter().next().expect("one user");
+        assert_eq!(ada.username.to_string(), "ada.l");
+        assert!(!ada.active);
+        assert_eq!(ada.sign_in_count, 9);
+    }
+
+    #[test]
+    fn json_export_imports_again() {
+        let mut store = UserStore::in_memory();
+        let rows = read_csv("username,email\nada,ada@example.com\ngrace,grace@example.com")
+            .expect("valid CSV");
+        import(&mut store, rows, &ImportOptions::default());
+        let json = to_json(store.iter().map(|(_, u)| u));
+
+        let mut copy = UserStore::in_memory();
+        let rows = read_json(&json).expect("valid JSON");
+        assert_eq!(
+            import(&mut copy, rows, &ImportOptions::default()).accepted(),
+            2
+        );
+        assert!(store.iter().map(|(_, u)| u).eq(copy.iter().map(|(_, u)| u)));
+    }
+}
@@ -0,0 +1,19 @@
+// the user types live in the library crate so that main.rs and the
+// modules added later can share them
+pub mod auth;
+pub mod display;
+pub mod filter;
+pub mod import;
+pub mod patch;
+pub mod point;
+pub mod store;
+pub mod user;
+
+pub use crate::auth::{AuthError, Authenticator, LockoutPolicy};
+pub use crate::display::DisplayMode;
+pub use crate::filter::{Filter, FilterError};
+pub use crate::import::{ImportOptions, OnDuplicate};
+pub use crate::patch::{AuditLog, UserPatch};
+pub use crate::point::{Aabb, Point, PointF};
+pub use crate::store::{Order, StoreError, UserId, UserStore};
+pub use crate::user::{BuildError, Email, User, UserBuilder, Username, ValidationError};
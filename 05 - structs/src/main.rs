@@ -1,65 +1,263 @@
-#[derive(Debug)]
-struct User {
-    active: bool,
-    username: String,
-    email: String,
-    sign_in_count: u64,
-}
-
-impl std::fmt::Display for User {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "is_active: {}, username: {}, email: {}, sign_in_count: {}",
-            self.active, self.username, self.email, self.sign_in_count
-        )
-    }
-}
-
-// tuple structs
-struct Point(i32, i32, i32);
+use std::{env, fs, process};
+use structs::store::FileBackend;
+use structs::{
+    Aabb, AuditLog, Authenticator, DisplayMode, Email, Filter, ImportOptions, LockoutPolicy, Order,
+    Point, PointF, User, UserPatch, UserStore, Username,
+};
 
 // unit-like structs
 struct AlwaysEqual;
 
 fn main() {
+    // `email` and `username` only accept validated values, which are made
+    // by parsing a string
     let mut user1 = User {
-        email: String::from("besher@example.com"),
-        username: String::from("beshr"),
+        email: Email::parse("besher@example.com").expect("valid email"),
+        username: Username::parse("beshr").expect("valid username"),
         sign_in_count: 1,
         active: false,
     };
 
     // since the struct is mutable, its fields can be modified
     // the entire instance is either mutalbe or immutable
-    user1.username = String::from("besher");
+    user1.username = Username::parse("besher").expect("valid username");
 
-    let user2 = create_user("besher@example.com".to_string(), "besher".to_string());
+    let user2 = create_user("besher@example.com", "besher").expect("valid user");
 
     // other fields not explicitly set should have the same values
     // as in user1
     let user3 = User {
-        email: String::from("besher@example.org"),
+        email: Email::parse("besher@example.org").expect("valid email"),
         ..user1
     };
 
     println!("{}", user3);
+    // `Debug` hides most of the email, so these do not leak it
     println!("{:#?}", user2);
-    dbg!(user2); // prints to stderr
+    dbg!(&user2); // prints to stderr
+
+    // the other ways of printing a user
+    for mode in [
+        DisplayMode::Redacted,
+        DisplayMode::Public,
+        DisplayMode::Machine,
+    ] {
+        println!("{}", user2.display(mode));
+    }
 
     // The following would fail! Since user1 is partially moved
     // to user3 not copied.
     // println!("{}", user1);
 
-    let _p = Point(0, 0, 0);
+    // the builder reports every invalid field at once
+    match User::builder().email("not an email").username("1x").build() {
+        Ok(user) => println!("{}", user),
+        Err(e) => println!("{}", e),
+    }
+
+    // a store hands out ids and refuses a second user with the same email
+    let mut store = UserStore::in_memory();
+    for (email, username, count) in [
+        ("ada@example.com", "ada", 12),
+        ("grace@example.com", "grace", 40),
+        ("linus@example.com", "linus", 7),
+    ] {
+        let user = User::builder()
+            .email(email)
+            .username(username)
+            .sign_in_count(count)
+            .build()
+            .expect("valid user");
+        store.create(user).expect("unique user");
+    }
+    if let Err(e) = store.create(create_user("ada@EXAMPLE.com", "ada2").expect("valid user")) {
+        println!("{}", e);
+    }
+    store.deactivate(3).expect("user 3 exists");
+    for (id, user) in store
+        .query()
+        .active(true)
+        .by_sign_in_count(Order::Descending)
+        .run()
+    {
+        println!(
+            "{}: {} ({} sign-ins)",
+            id, user.username, user.sign_in_count
+        );
+    }
+
+    // passwords are kept as salted Argon2 hashes, and an account is locked
+    // for a while after too many wrong ones
+    let mut auth = Authenticator::new(LockoutPolicy {
+        max_failures: 2,
+        ..LockoutPolicy::default()
+    });
+    auth.set_password(&store, 1, "correct horse battery")
+        .expect("user 1 exists");
+    for password in [
+        "correct horse battery",
+        "wrong",
+        "wrong",
+        "correct horse battery",
+    ] {
+        match auth.sign_in(&mut store, "ada", password) {
+            Ok(id) => println!(
+                "signed in as {}",
+                store.get(id).expect("signed in").username
+            ),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    // patches change single fields. Each one names the version it was made
+    // against, and a second patch against the same version is refused
+    let mut audit = AuditLog::new();
+    let version = store.version(2).expect("user 2 exists");
+    let rename = UserPatch {
+        username: Some(Username::parse("grace.h").expect("valid username")),
+        ..UserPatch::default()
+    };
+    let deactivate = UserPatch {
+        active: Some(false),
+        ..UserPatch::default()
+    };
+    store
+        .patch(2, version, &rename, "admin", &mut audit)
+        .expect("no conflict");
+    if let Err(e) = store.patch(2, version, &deactivate, "support", &mut audit) {
+        println!("{}", e);
+    }
+    for entry in audit.entries() {
+        println!("{}", entry);
+    }
+
+    // a bulk import reports, row by row, what happened to each user. Emails
+    // are compared after normalising, so `Ada+news@example.com` is ada's
+    let csv = "username,email,sign_in_count
+        ada.l,Ada+news@example.com,30
+        hopper,hopper@example.com,3
+        hopper2,HOPPER@example.com,1
+        x,broken,0";
+    let options = ImportOptions {
+        strip_plus_tag: true,
+        ..ImportOptions::default()
+    };
+    match structs::import::read_csv(csv) {
+        Ok(rows) => println!("{}", structs::import::import(&mut store, rows, &options)),
+        Err(e) => println!("{}", e),
+    }
+    print!("{}", structs::import::to_csv(store.iter().map(|(_, u)| u)));
+
+    // tuple structs: `Point` (in point.rs) has fields without names,
+    // `p.0`, `p.1` and `p.2`, and works as a 3D vector
+    let p = Point(1, 2, 3);
+    let q = Point(4, -5, 6);
+    println!("{} + {} = {}", p, q, p + q);
+    println!("{} x {} = {}, dot {}", p, q, p.cross(q), p.dot(q));
+    println!(
+        "manhattan {}, chebyshev {}, euclidean {:.3}",
+        p.manhattan_distance(q),
+        p.chebyshev_distance(q),
+        p.euclidean_distance(q)
+    );
+    println!("{:?}", Point(i32::MAX, 0, 0).checked_add(p));
+    if let Some(unit) = PointF::from(q).normalised() {
+        println!("unit {:.3}", unit);
+    }
+    if let Some(aabb) = Aabb::from_points([p, q, Point::ORIGIN]) {
+        println!("box {} to {}, {} points", aabb.min, aabb.max, aabb.volume());
+    }
+
     let _u = AlwaysEqual; // will be used to define traits (in later chapters)
+
+    // usage: cargo run -- filter <expression> [users.csv]
+    //        cargo run -- import <store.jsonl> <users.csv>
+    //        cargo run -- export <store.jsonl> <users.csv>
+    // without a file, the users of the store above are filtered. Import
+    // adds the users in the file to the store kept in `store.jsonl`
+    // (created if needed), and export writes the users of that store
+    // out. Files ending in `.json` are read and written as JSON
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = run(&args, store) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
 }
 
-fn create_user(email: String, username: String) -> User {
-    User {
-        email,
-        username,
-        active: true,
-        sign_in_count: 1,
+fn run(args: &[String], mut store: UserStore) -> Result<(), String> {
+    let (source, path) = match args {
+        [command, source] if command == "filter" => (source, None),
+        [command, source, path] if command == "filter" => (source, Some(path)),
+        [command, store_path, path] if command == "import" => {
+            let rows = read_users(path)?;
+            let mut store = open_store(store_path)?;
+            let report = structs::import::import(&mut store, rows, &ImportOptions::default());
+            println!("{}", report);
+            for (id, user) in store.iter() {
+                println!("{} {}", id, user.display(DisplayMode::Machine));
+            }
+            return Ok(());
+        }
+        [command, store_path, path] if command == "export" => {
+            let store = open_store(store_path)?;
+            let users = store.iter().map(|(_, u)| u);
+            let text = if is_json(path) {
+                structs::import::to_json(users)
+            } else {
+                structs::import::to_csv(users)
+            };
+            fs::write(path, text).map_err(|e| format!("cannot write {}: {}", path, e))?;
+            println!("{} users written to {}", store.len(), path);
+            return Ok(());
+        }
+        _ => {
+            return Err(String::from(
+                "usage: structs filter <expression> [users.csv]
+       structs import <store.jsonl> <users.csv>
+       structs export <store.jsonl> <users.csv>",
+            ))
+        }
+    };
+    let filter = Filter::parse(source).map_err(|e| e.show(source))?;
+
+    if let Some(path) = path {
+        let rows = read_users(path)?;
+        store = UserStore::in_memory();
+        let report = structs::import::import(&mut store, rows, &ImportOptions::default());
+        if report.rejected() > 0 {
+            eprintln!("{}", report);
+        }
     }
+
+    let found = store.query().filter(&filter).run();
+    for (id, user) in &found {
+        println!("{} {}", id, user.display(DisplayMode::Machine));
+    }
+    println!("{} of {} users matched", found.len(), store.len());
+    Ok(())
+}
+
+fn open_store(path: &str) -> Result<UserStore<FileBackend>, String> {
+    UserStore::open(path).map_err(|e| format!("cannot open {}: {}", path, e))
+}
+
+fn is_json(path: &str) -> bool {
+    path.to_lowercase().ends_with(".json")
+}
+
+// the rows of a CSV or JSON file of users
+fn read_users(path: &str) -> Result<Vec<structs::import::Row>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let rows = if is_json(path) {
+        structs::import::read_json(&text)
+    } else {
+        structs::import::read_csv(&text)
+    };
+    rows.map_err(|e| format!("{}: {}", path, e))
+}
+
+fn create_user(email: &str, username: &str) -> Result<User, structs::BuildError> {
+    User::builder().email(email).username(username).build()
 }
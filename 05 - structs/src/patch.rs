@@ -0,0 +1,451 @@
+use crate::store::{Backend, StoreError, UserId, UserStore};
+use crate::user::{Email, User, Username};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// a `UserPatch` says what to change about a user and leaves everything
+// else as it is: a field that is `None` is not touched. Patches are what
+// `UserStore::patch` writes, and what `diff` finds between two users.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<Username>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<Email>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sign_in_count: Option<u64>,
+}
+
+// the fields a patch can change, by name
+const FIELDS: [&str; 4] = ["active", "username", "email", "sign_in_count"];
+
+// one field as it was before a change and after it, both as text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.before, self.after)
+    }
+}
+
+impl UserPatch {
+    // the patch that turns `before` into `after`, with only the fields
+    // that differ
+    pub fn diff(before: &User, after: &User) -> UserPatch {
+        fn changed<T: PartialEq + Clone>(a: &T, b: &T) -> Option<T> {
+            (a != b).then(|| b.clone())
+        }
+
+        UserPatch {
+            active: changed(&before.active, &after.active),
+            username: changed(&before.username, &after.username),
+            email: changed(&before.email, &after.email),
+            sign_in_count: changed(&before.sign_in_count, &after.sign_in_count),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == UserPatch::default()
+    }
+
+    pub fn apply_to(&self, user: &User) -> User {
+        User {
+            active: self.active.unwrap_or(user.active),
+            username: self.username.clone().unwrap_or(user.username.clone()),
+            email: self.email.clone().unwrap_or(user.email.clone()),
+            sign_in_count: self.sign_in_count.unwrap_or(user.sign_in_count),
+        }
+    }
+
+    // what applying the patch to `user` changes. Fields the patch sets to
+    // the value they already have are left out
+    pub fn changes(&self, user: &User) -> Vec<FieldChange> {
+        let after = self.apply_to(user);
+        let mut changes = Vec::new();
+        let mut add = |field, before: String, after: String| {
+            if before != after {
+                changes.push(FieldChange {
+                    field,
+                    before,
+                    after,
+                });
+            }
+        };
+
+        add("active", user.active.to_string(), after.active.to_string());
+        add(
+            "username",
+            user.username.to_string(),
+            after.username.to_string(),
+        );
+        add("email", user.email.to_string(), after.email.to_string());
+        add(
+            "sign_in_count",
+            user.sign_in_count.to_string(),
+            after.sign_in_count.to_string(),
+        );
+        changes
+    }
+}
+
+// written by hand, since a derived one could only borrow the name of the
+// field from the input, and it has to be one of `FIELDS` instead
+impl<'de> Deserialize<'de> for FieldChange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FieldChange, D::Error> {
+        #[derive(Deserialize)]
+        struct Saved {
+            field: String,
+            before: String,
+            after: String,
+        }
+
+        let saved = Saved::deserialize(deserializer)?;
+        let field = FIELDS
+            .into_iter()
+            .find(|&field| field == saved.field)
+            .ok_or_else(|| serde::de::Error::unknown_variant(&saved.field, &FIELDS))?;
+        Ok(FieldChange {
+            field,
+            before: saved.before,
+            after: saved.after,
+        })
+    }
+}
+
+// who changed which user when, and how. `version` is the version of the
+// user the change produced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: UserId,
+    pub version: u64,
+    pub actor: String,
+    pub at: SystemTime,
+    pub changes: Vec<FieldChange>,
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes: Vec<String> = self.changes.iter().map(|c| c.to_string()).collect();
+        write!(
+            f,
+            "user {} v{} by {}: {}",
+            self.id,
+            self.version,
+            self.actor,
+            changes.join(", ")
+        )
+    }
+}
+
+// the entries are kept in memory, so an `AuditLog::new()` is gone when
+// the program ends. One made with `open` also appends every entry to a
+// file, as a line of JSON, and reads the earlier ones back when opened
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+    path: Option<PathBuf>,
+}
+
+impl AuditLog {
+    pub fn new() -> AuditLog {
+        AuditLog::default()
+    }
+
+    // opens the log at `path`, creating it if needed. As with the store's
+    // log, a last line that was cut off while being written is dropped
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AuditLog, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+
+        let mut entries = Vec::new();
+        let mut complete = text.is_empty() || text.ends_with('\n');
+        let lines: Vec<&str> = text.lines().collect();
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) if i + 1 == lines.len() && !complete => {
+                    file.set_len((text.len() - line.len()) as u64)?;
+                    complete = true;
+                }
+                Err(e) => {
+                    return Err(StoreError::Corrupt {
+                        line: i + 1,
+                        message: e.to_string(),
+                    })
+                }
+            }
+        }
+        // a whole last entry that only lacks its newline
+        if !complete {
+            file.write_all(b"\n")?;
+        }
+
+        Ok(AuditLog {
+            entries,
+            path: Some(path),
+        })
+    }
+
+    // oldest first
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    pub fn for_user(&self, id: UserId) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter().filter(move |e| e.id == id)
+    }
+
+    // an entry that cannot be written to the file is not kept in memory
+    // either
+    pub fn record(&mut self, entry: AuditEntry) -> Result<(), StoreError> {
+        if let Some(path) = &self.path {
+            let mut line = serde_json::to_string(&entry).map_err(io::Error::from)?;
+            line.push('\n');
+            let mut file = OpenOptions::new().append(true).open(path)?;
+            file.write_all(line.as_bytes())?;
+            file.flush()?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+impl<B: Backend> UserStore<B> {
+    // applies `patch` to the user, if they are still at `expected_version`,
+    // and notes what changed in `audit`. Returns the version the user is
+    // at afterwards. A patch that changes nothing writes nothing.
+    //
+    // the audit entry is written first, once the change is known to be
+    // allowed, so no change goes unrecorded. If the store then fails to
+    // write it, the audit names a version the user never reached
+    pub fn patch(
+        &mut self,
+        id: UserId,
+        expected_version: u64,
+        patch: &UserPatch,
+        actor: &str,
+        audit: &mut AuditLog,
+    ) -> Result<u64, StoreError> {
+        let user = self.get(id).ok_or(StoreError::NotFound(id))?;
+        let found = self.version(id).unwrap_or(0);
+        if found != expected_version {
+            return Err(StoreError::Conflict {
+                id,
+                expected: expected_version,
+                found,
+            });
+        }
+
+        let changes = patch.changes(user);
+        if changes.is_empty() {
+            return Ok(found);
+        }
+
+        let after = patch.apply_to(user);
+        self.check_unique(id, &after)?;
+        audit.record(AuditEntry {
+            id,
+            version: found + 1,
+            actor: actor.to_string(),
+            at: SystemTime::now(),
+            changes,
+        })?;
+        self.update_if(id, expected_version, after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn audit_entries_survive_reopening() {
+        let path = env::temp_dir().join(format!("structs-audit-{}.jsonl", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut store = UserStore::in_memory();
+        let ada = User::builder()
+            .email("ada@example.com")
+            .username("ada")
+            .build()
+            .expect("valid user");
+        let id = store.create(ada).expect("unique");
+        let patch = UserPatch {
+            sign_in_count: Some(3),
+            ..UserPatch::default()
+        };
+        {
+            let mut audit = AuditLog::open(&path).expect("opens");
+            store
+                .patch(id, 1, &patch, "admin", &mut audit)
+                .expect("no conflict");
+        }
+
+        let audit = AuditLog::open(&path).expect("opens");
+        let _ = fs::remove_file(&path);
+        assert_eq!(audit.entries().len(), 1);
+        let entry = &audit.entries()[0];
+        assert_eq!(
+            (entry.id, entry.version, entry.actor.as_str()),
+            (id, 2, "admin")
+        );
+        assert_eq!(entry.changes[0].to_string(), "sign_in_count: 0 -> 3");
+    }
+
+    fn user(name: &str) -> User {
+        User::builder()
+            .email(&format!("{}@example.com", name))
+            .username(name)
+            .build()
+            .expect("valid user")
+    }
+
+    #[test]
+    fn diff_has_only_the_changed_fields() {
+        let before = user("ada");
+        assert!(UserPatch::diff(&before, &before).is_empty());
+
+        let after = User {
+            active: false,
+            sign_in_count: 4,
+            ..before.clone()
+        };
+        let patch = UserPatch::diff(&before, &after);
+        assert_eq!(
+            patch,
+            UserPatch {
+                active: Some(false),
+                sign_in_count: Some(4),
+                ..UserPatch::default()
+            }
+        );
+        assert_eq!(patch.apply_to(&before), after);
+        let changes: Vec<String> = patch
+            .changes(&before)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(changes, ["active: true -> false", "sign_in_count: 0 -> 4"]);
+
+        // setting a field to the value it has is not a change
+        let same = UserPatch {
+            username: Some(before.username.clone()),
+            ..UserPatch::default()
+        };
+        assert!(same.changes(&before).is_empty());
+    }
+
+    #[test]
+    fn stale_versions_conflict_and_leave_no_trace() {
+        let mut store = UserStore::in_memory();
+        let id = store.create(user("ada")).expect("unique");
+        let mut audit = AuditLog::new();
+        let first = UserPatch {
+            sign_in_count: Some(1),
+            ..UserPatch::default()
+        };
+        assert_eq!(
+            store
+                .patch(id, 1, &first, "a", &mut audit)
+                .expect("current"),
+            2
+        );
+
+        // a second writer who also read version 1
+        let second = UserPatch {
+            active: Some(false),
+            ..UserPatch::default()
+        };
+        match store.patch(id, 1, &second, "b", &mut audit) {
+            Err(StoreError::Conflict {
+                id: conflict,
+                expected: 1,
+                found: 2,
+            }) => assert_eq!(conflict, id),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        // even a patch that would change nothing needs the right version
+        assert!(matches!(
+            store.patch(id, 1, &first, "b", &mut audit),
+            Err(StoreError::Conflict { .. })
+        ));
+
+        assert!(store.get(id).expect("stored").active);
+        assert_eq!(store.version(id), Some(2));
+        assert_eq!(audit.entries().len(), 1);
+        assert_eq!(audit.for_user(id).count(), 1);
+    }
+
+    #[test]
+    fn refused_patches_are_not_audited() {
+        let mut store = UserStore::in_memory();
+        let ada = store.create(user("ada")).expect("unique");
+        store.create(user("grace")).expect("unique");
+        let mut audit = AuditLog::new();
+        let taken = UserPatch {
+            username: Some(user("grace").username),
+            ..UserPatch::default()
+        };
+        assert!(matches!(
+            store.patch(ada, 1, &taken, "admin", &mut audit),
+            Err(StoreError::DuplicateUsername(_))
+        ));
+        assert!(audit.entries().is_empty());
+        assert_eq!(store.version(ada), Some(1));
+    }
+
+    #[test]
+    fn a_failed_audit_leaves_the_user_unchanged() {
+        let dir = env::temp_dir().join(format!("structs-audit-gone-{}", process::id()));
+        fs::create_dir_all(&dir).expect("created");
+        let mut audit = AuditLog::open(dir.join("audit.jsonl")).expect("opens");
+        fs::remove_dir_all(&dir).expect("removed");
+
+        let mut store = UserStore::in_memory();
+        let id = store.create(user("ada")).expect("unique");
+        let patch = UserPatch {
+            active: Some(false),
+            ..UserPatch::default()
+        };
+        assert!(matches!(
+            store.patch(id, 1, &patch, "admin", &mut audit),
+            Err(StoreError::Io(_))
+        ));
+        assert!(store.get(id).expect("stored").active);
+        assert_eq!(store.version(id), Some(1));
+    }
+
+    #[test]
+    fn a_cut_off_entry_is_dropped() {
+        let path = env::temp_dir().join(format!("structs-audit-cut-{}.jsonl", process::id()));
+        fs::write(&path, "{\"id\":1,\"vers").expect("written");
+        let audit = AuditLog::open(&path).expect("opens");
+        let left = fs::read_to_string(&path).expect("readable");
+        let _ = fs::remove_file(&path);
+        assert!(audit.entries().is_empty());
+        assert_eq!(left, "");
+    }
+}
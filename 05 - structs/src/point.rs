@@ -0,0 +1,503 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+// a tuple struct used as a 3D vector. The fields have no names, they are
+// `p.0`, `p.1` and `p.2` for x, y and z.
+//
+// the operators behave like the ones on `i32`: they panic on overflow in
+// debug builds and wrap in release builds. The `checked_` methods return
+// `None` instead. Products (`dot`, `cross`) are taken in `i64`, so only
+// the results can overflow, not the steps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Point(pub i32, pub i32, pub i32);
+
+// the same with `f64` coordinates, for results like lengths and unit
+// vectors that are not whole numbers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PointF(pub f64, pub f64, pub f64);
+
+impl Point {
+    pub const ORIGIN: Point = Point(0, 0, 0);
+
+    pub fn dot(self, other: Point) -> i64 {
+        self.wide()
+            .iter()
+            .zip(other.wide())
+            .map(|(a, b)| a * b)
+            .sum()
+    }
+
+    pub fn cross(self, other: Point) -> Point {
+        debug_assert!(
+            self.checked_cross(other).is_some(),
+            "cross product overflows i32"
+        );
+        // `as` keeps the low 32 bits, which is what wrapping gives
+        let [x, y, z] = cross(self.wide(), other.wide());
+        Point(x as i32, y as i32, z as i32)
+    }
+
+    pub fn checked_add(self, other: Point) -> Option<Point> {
+        Some(Point(
+            self.0.checked_add(other.0)?,
+            self.1.checked_add(other.1)?,
+            self.2.checked_add(other.2)?,
+        ))
+    }
+
+    pub fn checked_sub(self, other: Point) -> Option<Point> {
+        Some(Point(
+            self.0.checked_sub(other.0)?,
+            self.1.checked_sub(other.1)?,
+            self.2.checked_sub(other.2)?,
+        ))
+    }
+
+    pub fn checked_neg(self) -> Option<Point> {
+        Some(Point(
+            self.0.checked_neg()?,
+            self.1.checked_neg()?,
+            self.2.checked_neg()?,
+        ))
+    }
+
+    pub fn checked_mul(self, k: i32) -> Option<Point> {
+        Some(Point(
+            self.0.checked_mul(k)?,
+            self.1.checked_mul(k)?,
+            self.2.checked_mul(k)?,
+        ))
+    }
+
+    pub fn checked_dot(self, other: Point) -> Option<i64> {
+        self.wide()
+            .iter()
+            .zip(other.wide())
+            .try_fold(0i64, |sum, (a, b)| sum.checked_add(a * b))
+    }
+
+    pub fn checked_cross(self, other: Point) -> Option<Point> {
+        let [x, y, z] = cross(self.wide(), other.wide());
+        Some(Point(
+            x.try_into().ok()?,
+            y.try_into().ok()?,
+            z.try_into().ok()?,
+        ))
+    }
+
+    // the distances never overflow: a coordinate difference fits in a u32,
+    // and three of them in a u64
+
+    // the number of unit steps along the axes between the two points
+    pub fn manhattan_distance(self, other: Point) -> u64 {
+        self.differences(other).iter().sum()
+    }
+
+    // the largest difference along any axis, the number of king moves in
+    // a 3D chess
+    pub fn chebyshev_distance(self, other: Point) -> u64 {
+        self.differences(other).into_iter().max().unwrap_or(0)
+    }
+
+    pub fn euclidean_distance(self, other: Point) -> f64 {
+        PointF::from(self).distance(PointF::from(other))
+    }
+
+    fn wide(self) -> [i64; 3] {
+        [self.0 as i64, self.1 as i64, self.2 as i64]
+    }
+
+    fn differences(self, other: Point) -> [u64; 3] {
+        [
+            self.0.abs_diff(other.0) as u64,
+            self.1.abs_diff(other.1) as u64,
+            self.2.abs_diff(other.2) as u64,
+        ]
+    }
+}
+
+fn cross(a: [i64; 3], b: [i64; 3]) -> [i64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point(self.0 - other.0, self.1 - other.1, self.2 - other.2)
+    }
+}
+
+impl Neg for Point {
+    type Output = Point;
+
+    fn neg(self) -> Point {
+        Point(-self.0, -self.1, -self.2)
+    }
+}
+
+impl Mul<i32> for Point {
+    type Output = Point;
+
+    fn mul(self, k: i32) -> Point {
+        Point(self.0 * k, self.1 * k, self.2 * k)
+    }
+}
+
+impl Mul<Point> for i32 {
+    type Output = Point;
+
+    fn mul(self, p: Point) -> Point {
+        p * self
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.0, self.1, self.2)
+    }
+}
+
+impl PointF {
+    pub const ORIGIN: PointF = PointF(0.0, 0.0, 0.0);
+
+    pub fn dot(self, other: PointF) -> f64 {
+        self.0 * other.0 + self.1 * other.1 + self.2 * other.2
+    }
+
+    pub fn cross(self, other: PointF) -> PointF {
+        PointF(
+            self.1 * other.2 - self.2 * other.1,
+            self.2 * other.0 - self.0 * other.2,
+            self.0 * other.1 - self.1 * other.0,
+        )
+    }
+
+    pub fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    // the vector of length 1 in the same direction, or `None` for the
+    // zero vector, which has no direction
+    pub fn normalised(self) -> Option<PointF> {
+        let length = self.length();
+        (length > 0.0 && length.is_finite()).then(|| self * (1.0 / length))
+    }
+
+    pub fn manhattan_distance(self, other: PointF) -> f64 {
+        let d = self - other;
+        d.0.abs() + d.1.abs() + d.2.abs()
+    }
+
+    pub fn chebyshev_distance(self, other: PointF) -> f64 {
+        let d = self - other;
+        d.0.abs().max(d.1.abs()).max(d.2.abs())
+    }
+
+    pub fn distance(self, other: PointF) -> f64 {
+        (self - other).length()
+    }
+
+    // the nearest `Point`, with halves rounded away from zero
+    pub fn round(self) -> Result<Point, OutOfRange> {
+        Ok(Point(round(self.0)?, round(self.1)?, round(self.2)?))
+    }
+}
+
+fn round(v: f64) -> Result<i32, OutOfRange> {
+    let v = v.round();
+    if v >= i32::MIN as f64 && v <= i32::MAX as f64 {
+        Ok(v as i32)
+    } else {
+        Err(OutOfRange)
+    }
+}
+
+impl Add for PointF {
+    type Output = PointF;
+
+    fn add(self, other: PointF) -> PointF {
+        PointF(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+impl Sub for PointF {
+    type Output = PointF;
+
+    fn sub(self, other: PointF) -> PointF {
+        PointF(self.0 - other.0, self.1 - other.1, self.2 - other.2)
+    }
+}
+
+impl Neg for PointF {
+    type Output = PointF;
+
+    fn neg(self) -> PointF {
+        PointF(-self.0, -self.1, -self.2)
+    }
+}
+
+impl Mul<f64> for PointF {
+    type Output = PointF;
+
+    fn mul(self, k: f64) -> PointF {
+        PointF(self.0 * k, self.1 * k, self.2 * k)
+    }
+}
+
+impl Mul<PointF> for f64 {
+    type Output = PointF;
+
+    fn mul(self, p: PointF) -> PointF {
+        p * self
+    }
+}
+
+impl fmt::Display for PointF {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match f.precision() {
+            Some(p) => write!(f, "({:.*}, {:.*}, {:.*})", p, self.0, p, self.1, p, self.2),
+            None => write!(f, "({}, {}, {})", self.0, self.1, self.2),
+        }
+    }
+}
+
+// every i32 is exactly an f64, so this loses nothing
+impl From<Point> for PointF {
+    fn from(p: Point) -> PointF {
+        PointF(p.0 as f64, p.1 as f64, p.2 as f64)
+    }
+}
+
+// the other way only works for whole numbers in the range of i32
+impl TryFrom<PointF> for Point {
+    type Error = OutOfRange;
+
+    fn try_from(p: PointF) -> Result<Point, OutOfRange> {
+        let exact = |v: f64| {
+            if v.fract() == 0.0 {
+                round(v)
+            } else {
+                Err(OutOfRange)
+            }
+        };
+        Ok(Point(exact(p.0)?, exact(p.1)?, exact(p.2)?))
+    }
+}
+
+// a coordinate that is not a whole number in the range of i32, or that is
+// infinite or NaN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "coordinate is not an i32")
+    }
+}
+
+impl Error for OutOfRange {}
+
+// an axis-aligned bounding box: the smallest box with faces parallel to
+// the axes that holds all the points. `min` and `max` are included, so a
+// box around a single point has size (1, 1, 1) in grid cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    // `None` for no points, since there is nothing to bound
+    pub fn from_points<I: IntoIterator<Item = Point>>(points: I) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut aabb = Aabb {
+            min: first,
+            max: first,
+        };
+        for p in points {
+            aabb.include(p);
+        }
+        Some(aabb)
+    }
+
+    // grows the box to hold `p`
+    pub fn include(&mut self, p: Point) {
+        self.min = Point(
+            self.min.0.min(p.0),
+            self.min.1.min(p.1),
+            self.min.2.min(p.2),
+        );
+        self.max = Point(
+            self.max.0.max(p.0),
+            self.max.1.max(p.1),
+            self.max.2.max(p.2),
+        );
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        (self.min.0..=self.max.0).contains(&p.0)
+            && (self.min.1..=self.max.1).contains(&p.1)
+            && (self.min.2..=self.max.2).contains(&p.2)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut aabb = *self;
+        aabb.include(other.min);
+        aabb.include(other.max);
+        aabb
+    }
+
+    // the number of grid points along each axis
+    pub fn size(&self) -> [u64; 3] {
+        self.max.differences(self.min).map(|d| d + 1)
+    }
+
+    // the number of grid points inside, up to 2^96
+    pub fn volume(&self) -> u128 {
+        self.size().iter().map(|&s| s as u128).product()
+    }
+
+    pub fn centre(&self) -> PointF {
+        (PointF::from(self.min) + PointF::from(self.max)) * 0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: Point = Point(i32::MAX, i32::MAX, i32::MAX);
+    const MIN: Point = Point(i32::MIN, i32::MIN, i32::MIN);
+
+    #[test]
+    fn checked_ops_refuse_to_overflow() {
+        let one = Point(1, 0, 0);
+        assert_eq!(MAX.checked_add(one), None);
+        assert_eq!(MIN.checked_sub(one), None);
+        assert_eq!(MIN.checked_neg(), None);
+        assert_eq!(MAX.checked_mul(2), None);
+        assert_eq!(MIN.checked_mul(-1), None);
+        assert_eq!(MAX.checked_cross(Point(0, i32::MIN, 0)), None);
+
+        // within range they agree with the operators
+        let (p, q) = (Point(1, -2, 3), Point(4, 5, -6));
+        assert_eq!(p.checked_add(q), Some(p + q));
+        assert_eq!(p.checked_sub(q), Some(p - q));
+        assert_eq!(p.checked_neg(), Some(-p));
+        assert_eq!(p.checked_mul(-3), Some(p * -3));
+        assert_eq!(p.checked_mul(-3), Some(-3 * p));
+        assert_eq!(p.checked_dot(q), Some(p.dot(q)));
+        assert_eq!(p.checked_cross(q), Some(p.cross(q)));
+        assert_eq!(p.cross(q), Point(-3, 18, 13));
+    }
+
+    #[test]
+    fn products_are_taken_in_i64() {
+        // every step of these overflows an i32, but not the results
+        assert_eq!(MAX.dot(Point(1, 1, 1)), 3 * i32::MAX as i64);
+        let square = (i32::MAX as i64).pow(2);
+        assert_eq!(
+            MAX.checked_dot(Point(i32::MAX, i32::MAX, 0)),
+            Some(2 * square)
+        );
+        // three squares do not fit in an i64 either
+        assert_eq!(MAX.checked_dot(MAX), None);
+        assert_eq!(MAX.checked_cross(MAX), Some(Point::ORIGIN));
+    }
+
+    #[test]
+    fn distances_do_not_overflow() {
+        assert_eq!(MIN.manhattan_distance(MAX), 3 * u32::MAX as u64);
+        assert_eq!(MIN.chebyshev_distance(MAX), u32::MAX as u64);
+        let p = Point(1, 2, 3);
+        assert_eq!(p.manhattan_distance(Point(4, -2, 3)), 7);
+        assert_eq!(p.chebyshev_distance(Point(4, -2, 3)), 4);
+        assert_eq!(p.euclidean_distance(Point(4, -2, 3)), 5.0);
+    }
+
+    #[test]
+    fn float_conversion() {
+        assert_eq!(
+            PointF::from(MIN),
+            PointF(-2147483648.0, -2147483648.0, -2147483648.0)
+        );
+        assert_eq!(Point::try_from(PointF::from(MAX)), Ok(MAX));
+        assert_eq!(Point::try_from(PointF(1.0, -2.0, 0.0)), Ok(Point(1, -2, 0)));
+        assert_eq!(Point::try_from(PointF(1.5, 0.0, 0.0)), Err(OutOfRange));
+        assert_eq!(
+            Point::try_from(PointF(0.0, 2147483648.0, 0.0)),
+            Err(OutOfRange)
+        );
+        assert_eq!(Point::try_from(PointF(0.0, 0.0, f64::NAN)), Err(OutOfRange));
+        assert_eq!(
+            Point::try_from(PointF(f64::INFINITY, 0.0, 0.0)),
+            Err(OutOfRange)
+        );
+
+        assert_eq!(PointF(0.5, -0.5, 2.49).round(), Ok(Point(1, -1, 2)));
+        assert_eq!(
+            PointF(2147483647.4, 0.0, 0.0).round(),
+            Ok(Point(i32::MAX, 0, 0))
+        );
+        assert_eq!(PointF(2147483647.5, 0.0, 0.0).round(), Err(OutOfRange));
+        assert_eq!(PointF(f64::NAN, 0.0, 0.0).round(), Err(OutOfRange));
+
+        let unit = PointF(3.0, 0.0, 4.0).normalised().expect("has a direction");
+        assert!(unit.distance(PointF(0.6, 0.0, 0.8)) < 1e-12);
+        assert!((unit.length() - 1.0).abs() < 1e-12);
+        assert_eq!(PointF::ORIGIN.normalised(), None);
+        assert_eq!(PointF(f64::INFINITY, 0.0, 0.0).normalised(), None);
+        assert_eq!(format!("{:.1}", unit), "(0.6, 0.0, 0.8)");
+    }
+
+    #[test]
+    fn the_box_of_nothing_is_none() {
+        assert_eq!(Aabb::from_points([]), None);
+        let single = Aabb::from_points([Point(1, 2, 3)]).expect("one point");
+        assert_eq!(single.size(), [1, 1, 1]);
+        assert_eq!(single.volume(), 1);
+        assert!(single.contains(Point(1, 2, 3)));
+        assert!(!single.contains(Point(1, 2, 4)));
+    }
+
+    #[test]
+    fn boxes_contain_their_corners_and_unite() {
+        let a = Aabb::from_points([Point(0, 0, 0), Point(2, -1, 5)]).expect("points");
+        assert_eq!(a.min, Point(0, -1, 0));
+        assert_eq!(a.max, Point(2, 0, 5));
+        assert_eq!(a.size(), [3, 2, 6]);
+        assert_eq!(a.volume(), 36);
+        assert_eq!(a.centre(), PointF(1.0, -0.5, 2.5));
+        assert!(a.contains(a.min) && a.contains(a.max));
+        assert!(!a.contains(Point(3, 0, 0)));
+
+        let b = Aabb::from_points([Point(-4, 3, 1)]).expect("point");
+        let u = a.union(&b);
+        assert_eq!(u, b.union(&a));
+        assert_eq!(u.min, Point(-4, -1, 0));
+        assert_eq!(u.max, Point(2, 3, 5));
+        for p in [a.min, a.max, b.min] {
+            assert!(u.contains(p));
+        }
+        assert_eq!(a.union(&a), a);
+
+        let whole = Aabb { min: MIN, max: MAX };
+        assert_eq!(whole.volume(), (1u128 << 32).pow(3));
+        assert_eq!(whole.centre(), PointF(-0.5, -0.5, -0.5));
+    }
+}
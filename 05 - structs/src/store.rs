@@ -0,0 +1,590 @@
+use crate::filter::Filter;
+use crate::user::{Email, User, Username};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// a `UserStore` keeps users under ids it hands out, and makes sure no two
+// of them share an email or a username. The users themselves are always
+// held in memory; a `Backend` decides what happens to each change besides
+// that: `MemoryBackend` forgets it, `FileBackend` appends it to a log so
+// the store can be opened again later.
+//
+// ids are never handed out twice, not even after the user holding the
+// highest one was deleted and the log compacted: the id the next user
+// gets is kept in a header on the first line of the log.
+
+pub type UserId = u64;
+
+#[derive(Debug)]
+pub enum StoreError {
+    NotFound(UserId),
+    DuplicateEmail(Email),
+    DuplicateUsername(Username),
+    // the user was changed since the caller read them
+    Conflict {
+        id: UserId,
+        expected: u64,
+        found: u64,
+    },
+    Io(io::Error),
+    // a line of the log that could not be read back. Lines count from 1
+    Corrupt {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::NotFound(id) => write!(f, "no user with id {}", id),
+            StoreError::DuplicateEmail(e) => write!(f, "email {} is already taken", e),
+            StoreError::DuplicateUsername(u) => write!(f, "username {} is already taken", u),
+            StoreError::Conflict {
+                id,
+                expected,
+                found,
+            } => write!(
+                f,
+                "user {} is at version {}, not {}: it was changed in the meantime",
+                id, found, expected
+            ),
+            StoreError::Io(e) => write!(f, "{}", e),
+            StoreError::Corrupt { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> StoreError {
+        StoreError::Io(e)
+    }
+}
+
+// one change, as it is written to the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Change {
+    // a new user, or the new state of an existing one. Every put makes a
+    // new version of the user, starting at 1
+    Put {
+        id: UserId,
+        version: u64,
+        user: User,
+    },
+    Delete {
+        id: UserId,
+    },
+}
+
+// the first line of a log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogHeader {
+    pub next_id: UserId,
+}
+
+pub trait Backend {
+    // called before the change is applied in memory, so a change that
+    // could not be recorded is not applied either
+    fn record(&mut self, change: &Change) -> Result<(), StoreError>;
+
+    // replaces whatever has been recorded with the current state, given
+    // as one put per user, and the header to keep with it
+    fn compact(&mut self, header: &LogHeader, state: &[Change]) -> Result<(), StoreError>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryBackend;
+
+impl Backend for MemoryBackend {
+    fn record(&mut self, _change: &Change) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn compact(&mut self, _header: &LogHeader, _state: &[Change]) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
+
+// an append-only log with a `LogHeader` and then one JSON change per line.
+// Opening the store replays the log; `compact` rewrites it with a new
+// header and a single `put` per user
+#[derive(Debug)]
+pub struct FileBackend {
+    path: PathBuf,
+    file: File,
+    // changes in the log, to tell how much compacting would save
+    lines: usize,
+}
+
+impl FileBackend {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+}
+
+impl Backend for FileBackend {
+    fn record(&mut self, change: &Change) -> Result<(), StoreError> {
+        let mut line = serde_json::to_string(change).map_err(io::Error::from)?;
+        line.push('\n');
+        // a single write, so a crash leaves at most a cut-off last line
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    fn compact(&mut self, header: &LogHeader, state: &[Change]) -> Result<(), StoreError> {
+        // written next to the log and renamed over it, so a crash leaves
+        // either the old log or the new one
+        let tmp = self.path.with_extension("compact");
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            serde_json::to_writer(&mut out, header).map_err(io::Error::from)?;
+            out.write_all(b"\n")?;
+            for change in state {
+                serde_json::to_writer(&mut out, change).map_err(io::Error::from)?;
+                out.write_all(b"\n")?;
+            }
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.lines = state.len();
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct UserStore<B: Backend = MemoryBackend> {
+    backend: B,
+    users: BTreeMap<UserId, User>,
+    by_email: HashMap<Email, UserId>,
+    by_username: HashMap<Username, UserId>,
+    versions: HashMap<UserId, u64>,
+    next_id: UserId,
+}
+
+impl UserStore<MemoryBackend> {
+    pub fn in_memory() -> UserStore<MemoryBackend> {
+        UserStore::with_backend(MemoryBackend)
+    }
+}
+
+impl Default for UserStore<MemoryBackend> {
+    fn default() -> UserStore<MemoryBackend> {
+        UserStore::in_memory()
+    }
+}
+
+impl UserStore<FileBackend> {
+    // opens the log at `path`, creating it if needed, and replays it
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<UserStore<FileBackend>, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+
+        let mut header = None;
+        let mut changes = Vec::new();
+        let mut complete = text.is_empty() || text.ends_with('\n');
+        let lines: Vec<&str> = text.lines().collect();
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = if header.is_none() {
+                serde_json::from_str::<LogHeader>(line).map(|h| header = Some(h))
+            } else {
+                serde_json::from_str::<Change>(line).map(|c| changes.push((i + 1, c)))
+            };
+            match parsed {
+                Ok(()) => {}
+                // a last line without its newline was cut off while being
+                // written, and the change it held never happened
+                Err(_) if i + 1 == lines.len() && !complete => {
+                    file.set_len((text.len() - line.len()) as u64)?;
+                    complete = true;
+                }
+                Err(e) if header.is_none() => {
+                    return Err(StoreError::Corrupt {
+                        line: i + 1,
+                        message: format!("expected the log header: {}", e),
+                    })
+                }
+                Err(e) => {
+                    return Err(StoreError::Corrupt {
+                        line: i + 1,
+                        message: e.to_string(),
+                    })
+                }
+            }
+        }
+
+        // a whole last change that only lacks its newline
+        if !complete {
+            file.write_all(b"\n")?;
+        }
+
+        // a new log gets its header right away, and so does one whose
+        // header was cut off
+        if header.is_none() {
+            let mut line =
+                serde_json::to_string(&LogHeader { next_id: 1 }).map_err(io::Error::from)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+        }
+
+        let backend = FileBackend {
+            path,
+            file,
+            lines: changes.len(),
+        };
+        let mut store = UserStore::with_backend(backend);
+        store.next_id = header.map_or(1, |h| h.next_id);
+        for (line, change) in changes {
+            store.apply(change).map_err(|e| StoreError::Corrupt {
+                line,
+                message: e.to_string(),
+            })?;
+        }
+        Ok(store)
+    }
+}
+
+impl<B: Backend> UserStore<B> {
+    pub fn with_backend(backend: B) -> UserStore<B> {
+        UserStore {
+            backend,
+            users: BTreeMap::new(),
+            by_email: HashMap::new(),
+            by_username: HashMap::new(),
+            versions: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    // adds a user and returns the id it was given
+    pub fn create(&mut self, user: User) -> Result<UserId, StoreError> {
+        let id = self.next_id;
+        self.check_unique(id, &user)?;
+        self.put(id, user)?;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: UserId) -> Option<&User> {
+        self.users.get(&id)
+    }
+
+    // how many times the user has been written, starting at 1
+    pub fn version(&self, id: UserId) -> Option<u64> {
+        self.versions.get(&id).copied()
+    }
+
+    pub fn find_by_email(&self, email: &Email) -> Option<(UserId, &User)> {
+        let id = *self.by_email.get(email)?;
+        Some((id, &self.users[&id]))
+    }
+
+    pub fn find_by_username(&self, username: &Username) -> Option<(UserId, &User)> {
+        let id = *self.by_username.get(username)?;
+        Some((id, &self.users[&id]))
+    }
+
+    // replaces the user stored under `id`
+    pub fn update(&mut self, id: UserId, user: User) -> Result<(), StoreError> {
+        if !self.users.contains_key(&id) {
+            return Err(StoreError::NotFound(id));
+        }
+        self.check_unique(id, &user)?;
+        self.put(id, user).map(|_| ())
+    }
+
+    // `update`, but only if the user is still at `expected_version`, so
+    // that two writers who read the same version cannot both write back
+    // their copy and lose one of the changes. Returns the new version
+    pub fn update_if(
+        &mut self,
+        id: UserId,
+        expected_version: u64,
+        user: User,
+    ) -> Result<u64, StoreError> {
+        let found = self.version(id).ok_or(StoreError::NotFound(id))?;
+        if found != expected_version {
+            return Err(StoreError::Conflict {
+                id,
+                expected: expected_version,
+                found,
+            });
+        }
+        self.check_unique(id, &user)?;
+        self.put(id, user)
+    }
+
+    // keeps the user, but marks them as no longer active
+    pub fn deactivate(&mut self, id: UserId) -> Result<(), StoreError> {
+        let user = self.users.get(&id).ok_or(StoreError::NotFound(id))?;
+        if !user.active {
+            return Ok(());
+        }
+        let user = User {
+            active: false,
+            ..user.clone()
+        };
+        self.put(id, user).map(|_| ())
+    }
+
+    // removes the user and returns what was stored
+    pub fn delete(&mut self, id: UserId) -> Result<User, StoreError> {
+        let user = self.users.get(&id).ok_or(StoreError::NotFound(id))?.clone();
+        self.commit(Change::Delete { id })?;
+        Ok(user)
+    }
+
+    // every user in the order of their ids
+    pub fn iter(&self) -> impl Iterator<Item = (UserId, &User)> {
+        self.users.iter().map(|(&id, user)| (id, user))
+    }
+
+    pub fn query(&self) -> Query<'_, B> {
+        Query {
+            store: self,
+            active: None,
+            filter: None,
+            order: None,
+        }
+    }
+
+    pub fn compact(&mut self) -> Result<(), StoreError> {
+        let state: Vec<Change> = self
+            .users
+            .iter()
+            .map(|(&id, user)| Change::Put {
+                id,
+                version: self.versions[&id],
+                user: user.clone(),
+            })
+            .collect();
+        let header = LogHeader {
+            next_id: self.next_id,
+        };
+        self.backend.compact(&header, &state)
+    }
+
+    pub(crate) fn check_unique(&self, id: UserId, user: &User) -> Result<(), StoreError> {
+        if self
+            .by_email
+            .get(&user.email)
+            .is_some_and(|&other| other != id)
+        {
+            return Err(StoreError::DuplicateEmail(user.email.clone()));
+        }
+        if self
+            .by_username
+            .get(&user.username)
+            .is_some_and(|&other| other != id)
+        {
+            return Err(StoreError::DuplicateUsername(user.username.clone()));
+        }
+        Ok(())
+    }
+
+    fn put(&mut self, id: UserId, user: User) -> Result<u64, StoreError> {
+        let version = self.version(id).unwrap_or(0) + 1;
+        self.commit(Change::Put { id, version, user })?;
+        Ok(version)
+    }
+
+    fn commit(&mut self, change: Change) -> Result<(), StoreError> {
+        self.backend.record(&change)?;
+        self.apply(change)
+    }
+
+    // updates the maps, without recording anything
+    fn apply(&mut self, change: Change) -> Result<(), StoreError> {
+        match change {
+            Change::Put { id, version, user } => {
+                self.check_unique(id, &user)?;
+                if let Some(old) = self.users.remove(&id) {
+                    self.by_email.remove(&old.email);
+                    self.by_username.remove(&old.username);
+                }
+                self.by_email.insert(user.email.clone(), id);
+                self.by_username.insert(user.username.clone(), id);
+                self.users.insert(id, user);
+                self.versions.insert(id, version);
+                // ids only grow, so a deleted id is not handed out again.
+                // After compacting, the header remembers the highest ones
+                self.next_id = self.next_id.max(id + 1);
+            }
+            Change::Delete { id } => {
+                let old = self.users.remove(&id).ok_or(StoreError::NotFound(id))?;
+                self.by_email.remove(&old.email);
+                self.by_username.remove(&old.username);
+                self.versions.remove(&id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+// a query is built with chained calls and run with `run`:
+//
+//     store.query().active(true).by_sign_in_count(Order::Descending).run()
+//
+// without an order the users come in the order of their ids
+pub struct Query<'a, B: Backend> {
+    store: &'a UserStore<B>,
+    active: Option<bool>,
+    filter: Option<&'a Filter>,
+    order: Option<Order>,
+}
+
+impl<'a, B: Backend> Query<'a, B> {
+    pub fn active(mut self, active: bool) -> Query<'a, B> {
+        self.active = Some(active);
+        self
+    }
+
+    // only the users the filter matches, see filter.rs
+    pub fn filter(mut self, filter: &'a Filter) -> Query<'a, B> {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn by_sign_in_count(mut self, order: Order) -> Query<'a, B> {
+        self.order = Some(order);
+        self
+    }
+
+    pub fn run(self) -> Vec<(UserId, &'a User)> {
+        let mut found: Vec<(UserId, &User)> = self
+            .store
+            .iter()
+            .filter(|(_, u)| self.active.is_none_or(|active| u.active == active))
+            .filter(|(_, u)| self.filter.is_none_or(|f| f.matches(u)))
+            .collect();
+
+        // the sort is stable, so equal counts stay in the order of their ids
+        match self.order {
+            Some(Order::Ascending) => found.sort_by_key(|(_, u)| u.sign_in_count),
+            Some(Order::Descending) => {
+                found.sort_by_key(|(_, u)| std::cmp::Reverse(u.sign_in_count))
+            }
+            None => {}
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn user(name: &str) -> User {
+        User::builder()
+            .email(&format!("{}@example.com", name))
+            .username(name)
+            .build()
+            .expect("valid user")
+    }
+
+    // a log in the temp directory that is removed when dropped
+    struct TempLog(PathBuf);
+
+    impl TempLog {
+        fn new(name: &str) -> TempLog {
+            let path = env::temp_dir().join(format!("structs-{}-{}.jsonl", name, process::id()));
+            let _ = fs::remove_file(&path);
+            TempLog(path)
+        }
+    }
+
+    impl Drop for TempLog {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn ids_are_not_reused_after_compacting() {
+        let log = TempLog::new("compact-ids");
+        {
+            let mut store = UserStore::open(&log.0).expect("log opens");
+            store.create(user("ada")).expect("unique");
+            let grace = store.create(user("grace")).expect("unique");
+            store.delete(grace).expect("grace exists");
+            store.compact().expect("log compacts");
+        }
+
+        let mut store = UserStore::open(&log.0).expect("log opens");
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.create(user("linus")).expect("unique"), 3);
+    }
+
+    #[test]
+    fn logs_without_a_header_are_corrupt() {
+        let log = TempLog::new("no-header");
+        let line = serde_json::to_string(&Change::Put {
+            id: 4,
+            version: 1,
+            user: user("ada"),
+        })
+        .expect("serialises");
+        fs::write(&log.0, line + "\n").expect("log written");
+
+        match UserStore::open(&log.0) {
+            Err(StoreError::Corrupt { line: 1, .. }) => {}
+            other => panic!("expected a corrupt log, got {:?}", other.map(|s| s.len())),
+        }
+    }
+
+    #[test]
+    fn a_cut_off_header_starts_the_log_again() {
+        let log = TempLog::new("cut-header");
+        fs::write(&log.0, "{\"next_i").expect("log written");
+
+        let mut store = UserStore::open(&log.0).expect("log opens");
+        assert_eq!(store.create(user("ada")).expect("unique"), 1);
+        drop(store);
+
+        let store = UserStore::open(&log.0).expect("log opens again");
+        assert_eq!(store.len(), 1);
+    }
+}
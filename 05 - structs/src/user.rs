@@ -0,0 +1,547 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// newtypes wrap a single value in a tuple struct of its own. An `Email`
+// can only be made by parsing, so every `Email` in the program has been
+// validated, and a function taking one does not need to check it again.
+// That includes values read back from a file: serde goes through the same
+// parsing (`try_from`).
+//
+// `Debug` for `Email` is written by hand and hides most of the address, so
+// that `dbg!` or `{:?}` on anything holding one does not leak it to logs
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Email(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Username(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailError {
+    MissingAt,
+    EmptyLocalPart,
+    EmptyDomain,
+    TooLong,
+    LocalPartTooLong,
+    // a character outside of what the local part allows
+    InvalidLocalCharacter(char),
+    // a dot at the start or end, or two in a row
+    MisplacedDot,
+    InvalidDomain(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsernameError {
+    TooShort,
+    TooLong,
+    MustStartWithLetter,
+    InvalidCharacter(char),
+}
+
+// RFC 5321 limits: 64 characters before the @, 254 for the whole address
+// (the 256 of a path minus the angle brackets), 63 for a domain label
+const MAX_LOCAL: usize = 64;
+const MAX_EMAIL: usize = 254;
+const MAX_LABEL: usize = 63;
+
+pub const USERNAME_MIN: usize = 3;
+pub const USERNAME_MAX: usize = 32;
+
+impl Email {
+    // accepts the dot-atom form of RFC 5322: `local@domain` with no quoted
+    // strings, comments or IP literals. Surrounding whitespace is dropped
+    // and the domain is lowercased, since domains are case-insensitive.
+    // The local part is kept as written, the mail server decides about it
+    pub fn parse(s: &str) -> Result<Email, EmailError> {
+        let s = s.trim();
+        if s.len() > MAX_EMAIL {
+            return Err(EmailError::TooLong);
+        }
+
+        // the domain cannot contain an @, so the last one separates them
+        let (local, domain) = s.rsplit_once('@').ok_or(EmailError::MissingAt)?;
+        if local.is_empty() {
+            return Err(EmailError::EmptyLocalPart);
+        }
+        if domain.is_empty() {
+            return Err(EmailError::EmptyDomain);
+        }
+        if local.len() > MAX_LOCAL {
+            return Err(EmailError::LocalPartTooLong);
+        }
+
+        if let Some(c) = local.chars().find(|&c| !is_atext(c) && c != '.') {
+            return Err(EmailError::InvalidLocalCharacter(c));
+        }
+        if local.starts_with('.') || local.ends_with('.') || local.contains("..") {
+            return Err(EmailError::MisplacedDot);
+        }
+
+        let domain = domain.to_ascii_lowercase();
+        if !is_domain(&domain) {
+            return Err(EmailError::InvalidDomain(domain));
+        }
+
+        Ok(Email(format!("{}@{}", local, domain)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(l, _)| l)
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, d)| d)
+    }
+
+    // the first character of the local part and the domain, as in
+    // `b****@example.com`. The number of stars is fixed, so it does not
+    // give away the length either
+    pub fn redacted(&self) -> String {
+        let first = self.local_part().chars().next().unwrap_or('*');
+        format!("{}****@{}", first, self.domain())
+    }
+}
+
+impl fmt::Debug for Email {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Email").field(&self.redacted()).finish()
+    }
+}
+
+impl Username {
+    // 3 to 32 ASCII letters, digits, `_`, `-` and `.`, starting with a
+    // letter. Usernames are compared without case, so they are stored in
+    // lowercase
+    pub fn parse(s: &str) -> Result<Username, UsernameError> {
+        let s = s.trim();
+        if let Some(c) = s
+            .chars()
+            .find(|&c| !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        {
+            return Err(UsernameError::InvalidCharacter(c));
+        }
+        if s.len() < USERNAME_MIN {
+            return Err(UsernameError::TooShort);
+        }
+        if s.len() > USERNAME_MAX {
+            return Err(UsernameError::TooLong);
+        }
+        if !s.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(UsernameError::MustStartWithLetter);
+        }
+
+        Ok(Username(s.to_ascii_lowercase()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// the characters RFC 5322 allows in an atom
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+/=?^_`{|}~-".contains(c)
+}
+
+// a hostname with at least two labels, as in `example.com`. Labels are
+// letters, digits and hyphens, and do not start or end with a hyphen
+fn is_domain(domain: &str) -> bool {
+    let labels: Vec<&str> = domain.split('.').collect();
+    labels.len() >= 2
+        && domain.len() <= MAX_EMAIL
+        && labels.iter().all(|l| {
+            !l.is_empty()
+                && l.len() <= MAX_LABEL
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+impl FromStr for Email {
+    type Err = EmailError;
+
+    fn from_str(s: &str) -> Result<Email, EmailError> {
+        Email::parse(s)
+    }
+}
+
+impl FromStr for Username {
+    type Err = UsernameError;
+
+    fn from_str(s: &str) -> Result<Username, UsernameError> {
+        Username::parse(s)
+    }
+}
+
+impl TryFrom<String> for Email {
+    type Error = EmailError;
+
+    fn try_from(s: String) -> Result<Email, EmailError> {
+        Email::parse(&s)
+    }
+}
+
+impl TryFrom<String> for Username {
+    type Error = UsernameError;
+
+    fn try_from(s: String) -> Result<Username, UsernameError> {
+        Username::parse(&s)
+    }
+}
+
+impl From<Email> for String {
+    fn from(email: Email) -> String {
+        email.0
+    }
+}
+
+impl From<Username> for String {
+    fn from(username: Username) -> String {
+        username.0
+    }
+}
+
+impl fmt::Display for Email {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Username {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for EmailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmailError::MissingAt => write!(f, "missing @"),
+            EmailError::EmptyLocalPart => write!(f, "nothing before the @"),
+            EmailError::EmptyDomain => write!(f, "nothing after the @"),
+            EmailError::TooLong => write!(f, "longer than {} characters", MAX_EMAIL),
+            EmailError::LocalPartTooLong => {
+                write!(f, "more than {} characters before the @", MAX_LOCAL)
+            }
+            EmailError::InvalidLocalCharacter(c) => write!(f, "`{}` is not allowed", c),
+            EmailError::MisplacedDot => {
+                write!(f, "dots cannot start, end or repeat before the @")
+            }
+            EmailError::InvalidDomain(d) => write!(f, "`{}` is not a valid domain", d),
+        }
+    }
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UsernameError::TooShort => write!(f, "shorter than {} characters", USERNAME_MIN),
+            UsernameError::TooLong => write!(f, "longer than {} characters", USERNAME_MAX),
+            UsernameError::MustStartWithLetter => write!(f, "must start with a letter"),
+            UsernameError::InvalidCharacter(c) => write!(f, "`{}` is not allowed", c),
+        }
+    }
+}
+
+impl Error for EmailError {}
+impl Error for UsernameError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub active: bool,
+    pub username: Username,
+    pub email: Email,
+    pub sign_in_count: u64,
+}
+
+impl User {
+    pub fn builder() -> UserBuilder {
+        UserBuilder::default()
+    }
+}
+
+impl fmt::Display for User {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "is_active: {}, username: {}, email: {}, sign_in_count: {}",
+            self.active, self.username, self.email, self.sign_in_count
+        )
+    }
+}
+
+// collects the fields of a `User` as plain strings and validates them all
+// at once in `build`, so a form can show every problem and not only the
+// first one. A new user is active and has not signed in yet
+#[derive(Debug, Clone)]
+pub struct UserBuilder {
+    email: Option<String>,
+    username: Option<String>,
+    active: bool,
+    sign_in_count: u64,
+}
+
+impl Default for UserBuilder {
+    fn default() -> UserBuilder {
+        UserBuilder {
+            email: None,
+            username: None,
+            active: true,
+            sign_in_count: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    Missing(&'static str),
+    Email(EmailError),
+    Username(UsernameError),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::Missing(field) => write!(f, "{} is required", field),
+            ValidationError::Email(e) => write!(f, "email: {}", e),
+            ValidationError::Username(e) => write!(f, "username: {}", e),
+        }
+    }
+}
+
+// every problem found by `UserBuilder::build`, never empty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildError(pub Vec<ValidationError>);
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let messages: Vec<String> = self.0.iter().map(|e| e.to_string()).collect();
+        write!(f, "invalid user: {}", messages.join("; "))
+    }
+}
+
+impl Error for BuildError {}
+
+// the methods take and return `self` so calls can be chained
+impl UserBuilder {
+    pub fn email(mut self, email: &str) -> UserBuilder {
+        self.email = Some(email.to_string());
+        self
+    }
+
+    pub fn username(mut self, username: &str) -> UserBuilder {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    pub fn active(mut self, active: bool) -> UserBuilder {
+        self.active = active;
+        self
+    }
+
+    pub fn sign_in_count(mut self, count: u64) -> UserBuilder {
+        self.sign_in_count = count;
+        self
+    }
+
+    pub fn build(self) -> Result<User, BuildError> {
+        let mut errors = Vec::new();
+
+        let email = match self.email.as_deref().map(Email::parse) {
+            Some(Ok(email)) => Some(email),
+            Some(Err(e)) => {
+                errors.push(ValidationError::Email(e));
+                None
+            }
+            None => {
+                errors.push(ValidationError::Missing("email"));
+                None
+            }
+        };
+        let username = match self.username.as_deref().map(Username::parse) {
+            Some(Ok(username)) => Some(username),
+            Some(Err(e)) => {
+                errors.push(ValidationError::Username(e));
+                None
+            }
+            None => {
+                errors.push(ValidationError::Missing("username"));
+                None
+            }
+        };
+
+        match (email, username) {
+            (Some(email), Some(username)) => Ok(User {
+                active: self.active,
+                username,
+                email,
+                sign_in_count: self.sign_in_count,
+            }),
+            _ => Err(BuildError(errors)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_builder_reports_every_problem() {
+        let error = User::builder().build().expect_err("nothing given");
+        assert_eq!(
+            error.0,
+            vec![
+                ValidationError::Missing("email"),
+                ValidationError::Missing("username"),
+            ]
+        );
+
+        let error = User::builder()
+            .email("no-at-sign")
+            .username("1st")
+            .build()
+            .expect_err("both invalid");
+        assert_eq!(
+            error.0,
+            vec![
+                ValidationError::Email(EmailError::MissingAt),
+                ValidationError::Username(UsernameError::MustStartWithLetter),
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid user: email: missing @; username: must start with a letter"
+        );
+
+        let error = User::builder()
+            .email("bob@example.com")
+            .build()
+            .expect_err("no username");
+        assert_eq!(error.0, vec![ValidationError::Missing("username")]);
+    }
+
+    #[test]
+    fn the_builder_keeps_the_defaults_unless_told_otherwise() {
+        let user = User::builder()
+            .email("bob@example.com")
+            .username("bob")
+            .build()
+            .expect("valid user");
+        assert!(user.active);
+        assert_eq!(user.sign_in_count, 0);
+
+        let user = User::builder()
+            .email("bob@example.com")
+            .username("bob")
+            .active(false)
+            .sign_in_count(7)
+            .build()
+            .expect("valid user");
+        assert!(!user.active);
+        assert_eq!(user.sign_in_count, 7);
+    }
+
+    #[test]
+    fn emails_lowercase_only_the_domain() {
+        let email = Email::parse("  Bob.Smith+news@Mail.Example.COM ").expect("valid email");
+        assert_eq!(email.as_str(), "Bob.Smith+news@mail.example.com");
+        assert_eq!(email.local_part(), "Bob.Smith+news");
+        assert_eq!(email.domain(), "mail.example.com");
+        assert_eq!(email.redacted(), "B****@mail.example.com");
+        // the last @ separates the parts, and an @ is not allowed before it
+        assert_eq!(
+            Email::parse("a@b@example.com"),
+            Err(EmailError::InvalidLocalCharacter('@'))
+        );
+    }
+
+    #[test]
+    fn invalid_emails() {
+        let long_local = format!("{}@example.com", "a".repeat(65));
+        let long = format!("a@{}.com", "b".repeat(250));
+        let cases = [
+            ("example.com", EmailError::MissingAt),
+            ("@example.com", EmailError::EmptyLocalPart),
+            ("bob@", EmailError::EmptyDomain),
+            (long_local.as_str(), EmailError::LocalPartTooLong),
+            (long.as_str(), EmailError::TooLong),
+            (
+                "bob smith@example.com",
+                EmailError::InvalidLocalCharacter(' '),
+            ),
+            (".bob@example.com", EmailError::MisplacedDot),
+            ("bob.@example.com", EmailError::MisplacedDot),
+            ("bo..b@example.com", EmailError::MisplacedDot),
+            (
+                "bob@localhost",
+                EmailError::InvalidDomain("localhost".to_string()),
+            ),
+            (
+                "bob@-x.com",
+                EmailError::InvalidDomain("-x.com".to_string()),
+            ),
+            (
+                "bob@Ex..com",
+                EmailError::InvalidDomain("ex..com".to_string()),
+            ),
+        ];
+        for (input, error) in cases {
+            assert_eq!(Email::parse(input), Err(error), "{input}");
+        }
+    }
+
+    #[test]
+    fn usernames_are_lowercased_and_checked() {
+        assert_eq!(
+            Username::parse(" Alice_B-2.x ").map(|u| u.to_string()),
+            Ok("alice_b-2.x".to_string())
+        );
+        assert_eq!(Username::parse("Al"), Err(UsernameError::TooShort));
+        assert_eq!(
+            Username::parse(&"a".repeat(USERNAME_MAX + 1)),
+            Err(UsernameError::TooLong)
+        );
+        assert!(Username::parse(&"a".repeat(USERNAME_MAX)).is_ok());
+        assert_eq!(
+            Username::parse("_bob"),
+            Err(UsernameError::MustStartWithLetter)
+        );
+        assert_eq!(
+            Username::parse("bö"),
+            Err(UsernameError::InvalidCharacter('ö'))
+        );
+        assert_eq!(Username::parse("ALICE"), Username::parse("alice"));
+    }
+
+    #[test]
+    fn serde_validates_and_normalises() {
+        let user: User = serde_json::from_str(
+            r#"{"active":true,"username":"Bob","email":"bob@EXAMPLE.com","sign_in_count":1}"#,
+        )
+        .expect("valid user");
+        assert_eq!(user.username.as_str(), "bob");
+        assert_eq!(user.email.as_str(), "bob@example.com");
+
+        let error = serde_json::from_str::<User>(
+            r#"{"active":true,"username":"Bob","email":"bob","sign_in_count":1}"#,
+        );
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn debug_hides_the_email() {
+        let email = Email::parse("secret.name@example.com").expect("valid email");
+        let debug = format!("{:?}", email);
+        assert_eq!(debug, r#"Email("s****@example.com")"#);
+        assert!(!debug.contains("secret"));
+    }
+}
@@ -0,0 +1,83 @@
+use enums::acl::Rule;
+use enums::{Acl, Action, Cidr, IpAddr};
+use std::time::{Duration, Instant};
+
+// the same generator as in the benchmarks of 05 - methods
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn range(&mut self, low: u32, high: u32) -> u32 {
+        low + (self.next() % (high - low) as u64) as u32
+    }
+
+    // an address in 10.0.0.0/8, so that the rules below overlap a lot
+    fn address(&mut self) -> IpAddr {
+        let [_, b, c, d] = (self.next() as u32).to_be_bytes();
+        IpAddr::V4([10, b, c, d])
+    }
+}
+
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let start = Instant::now();
+    let result = f();
+    (start.elapsed(), result)
+}
+
+// what `Acl::check` does, by looking at every rule: the longest prefix
+// that matches, the first rule of those with that prefix
+fn linear<'a>(rules: &'a [Rule], address: &IpAddr) -> Option<&'a Rule> {
+    rules.iter().filter(|r| r.cidr.contains(address)).fold(
+        None,
+        |best: Option<&Rule>, r| match best {
+            Some(b) if b.cidr.prefix() >= r.cidr.prefix() => Some(b),
+            _ => Some(r),
+        },
+    )
+}
+
+fn main() {
+    for count in [100, 1_000, 10_000] {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let mut acl = Acl::new(Action::Deny);
+        for _ in 0..count {
+            let action = if rng.next() & 1 == 0 {
+                Action::Allow
+            } else {
+                Action::Deny
+            };
+            let cidr = Cidr::new(&rng.address(), rng.range(8, 29) as u8).expect("valid prefix");
+            acl.push(action, cidr);
+        }
+        let addresses: Vec<IpAddr> = (0..10_000).map(|_| rng.address()).collect();
+
+        // the lines of the deciding rules, which both must agree on
+        let (scan, expected) = time(|| {
+            addresses
+                .iter()
+                .map(|a| linear(acl.rules(), a).map_or(0, |r| r.line))
+                .sum()
+        });
+        let (trie, found) = time(|| {
+            addresses
+                .iter()
+                .map(|a| acl.check(a).rule.map_or(0, |r| r.line))
+                .sum()
+        });
+        assert_eq!(expected, found, "the trie disagrees with the scan");
+
+        println!(
+            "{:>6} rules: scan {:>10.2?}, trie {:>10.2?}, {:>7.1}x",
+            count,
+            scan,
+            trie,
+            scan.as_secs_f64() / trie.as_secs_f64()
+        );
+    }
+}
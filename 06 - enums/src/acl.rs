@@ -0,0 +1,484 @@
+use crate::cidr::{Cidr, CidrError};
+use crate::ip::IpAddr;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// an access list: rules that allow or deny addresses and networks, read
+// from a file like
+//
+//     # the office, except the printer
+//     allow 192.168.1.0/24
+//     deny  192.168.1.5
+//     allow 2001:db8::/32   # the VPN
+//     default deny
+//
+// of the rules that match an address, the one with the longest prefix
+// decides, as in a routing table. So an exception can be written before
+// or after the network it is cut out of, and the order only matters
+// between rules for the very same network, where the first one wins.
+// Addresses that no rule matches get the default, which is `deny` unless
+// a `default` line says otherwise.
+//
+// an IPv4-mapped address (`::ffff:10.0.0.1`) is the IPv4 address it
+// holds, as for `Cidr::contains`, so the IPv4 rules decide about it.
+// Only when none of them matches do IPv6 rules such as `::ffff:0:0/96`
+// get a say, before the default.
+//
+// the rules are kept in a binary trie per family, one level per bit of
+// the address, with each rule at the node its prefix leads to. Checking
+// an address walks down its bits once, so it costs at most 32 or 128
+// steps however many rules there are.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    // the line in the file, from 1. Rules added with `Acl::push` are
+    // numbered after the last one
+    pub line: usize,
+    pub action: Action,
+    pub cidr: Cidr,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {} {}", self.line, self.action, self.cidr)
+    }
+}
+
+// the outcome of a check, with the rule that decided it, or `None` when
+// the default did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision<'a> {
+    pub action: Action,
+    pub rule: Option<&'a Rule>,
+}
+
+impl fmt::Display for Decision<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rule {
+            Some(rule) => write!(f, "{} ({})", self.action, rule),
+            None => write!(f, "{} (default)", self.action),
+        }
+    }
+}
+
+// a rule that never decides anything, because an earlier rule is for the
+// same network, or because rules with longer prefixes cover all of its
+// addresses between them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowed<'a> {
+    pub rule: &'a Rule,
+    // the rules that decide for its addresses instead
+    pub by: Vec<&'a Rule>,
+    // whether those rules all do what it would have done, in which case
+    // it is only redundant, and not a mistake that changes the outcome
+    pub same_action: bool,
+}
+
+#[derive(Debug)]
+pub enum AclError {
+    Io(io::Error),
+    UnknownAction { line: usize, word: String },
+    MissingAddress { line: usize },
+    TrailingText { line: usize, text: String },
+    Address { line: usize, error: CidrError },
+}
+
+impl fmt::Display for AclError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AclError::Io(e) => write!(f, "{}", e),
+            AclError::UnknownAction { line, word } => write!(
+                f,
+                "line {}: expected `allow`, `deny` or `default`, found `{}`",
+                line, word
+            ),
+            AclError::MissingAddress { line } => write!(f, "line {}: missing address", line),
+            AclError::TrailingText { line, text } => {
+                write!(f, "line {}: unexpected `{}`", line, text)
+            }
+            AclError::Address { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl Error for AclError {}
+
+impl From<io::Error> for AclError {
+    fn from(e: io::Error) -> AclError {
+        AclError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Acl {
+    rules: Vec<Rule>,
+    default: Action,
+    v4: Trie,
+    v6: Trie,
+}
+
+impl Acl {
+    pub fn new(default: Action) -> Acl {
+        Acl {
+            rules: Vec::new(),
+            default,
+            v4: Trie::new(32),
+            v6: Trie::new(128),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Acl, AclError> {
+        let mut acl = Acl::new(Action::Deny);
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let content = line.split('#').next().unwrap_or("");
+            let mut words = content.split_whitespace();
+            let Some(word) = words.next() else {
+                continue;
+            };
+
+            let action = match word {
+                "allow" => Action::Allow,
+                "deny" => Action::Deny,
+                "default" => {
+                    let action = match words.next() {
+                        Some("allow") => Action::Allow,
+                        Some("deny") => Action::Deny,
+                        other => {
+                            return Err(AclError::UnknownAction {
+                                line: line_number,
+                                word: other.unwrap_or("").to_string(),
+                            })
+                        }
+                    };
+                    acl.default = action;
+                    if let Some(text) = words.next() {
+                        return Err(AclError::TrailingText {
+                            line: line_number,
+                            text: text.to_string(),
+                        });
+                    }
+                    continue;
+                }
+                other => {
+                    return Err(AclError::UnknownAction {
+                        line: line_number,
+                        word: other.to_string(),
+                    })
+                }
+            };
+
+            let address = words
+                .next()
+                .ok_or(AclError::MissingAddress { line: line_number })?;
+            if let Some(text) = words.next() {
+                return Err(AclError::TrailingText {
+                    line: line_number,
+                    text: text.to_string(),
+                });
+            }
+            let cidr: Cidr = address.parse().map_err(|error| AclError::Address {
+                line: line_number,
+                error,
+            })?;
+            acl.insert(Rule {
+                line: line_number,
+                action,
+                cidr,
+            });
+        }
+        Ok(acl)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Acl, AclError> {
+        Acl::parse(&fs::read_to_string(path)?)
+    }
+
+    // adds a rule after the existing ones
+    pub fn push(&mut self, action: Action, cidr: Cidr) {
+        let line = self.rules.last().map_or(1, |r| r.line + 1);
+        self.insert(Rule { line, action, cidr });
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn default_action(&self) -> Action {
+        self.default
+    }
+
+    pub fn check(&self, address: &IpAddr) -> Decision<'_> {
+        let found = match (address, address.to_ipv4_mapped()) {
+            (IpAddr::V4(_), _) => self.v4.longest_match(address.to_bits()),
+            (_, Some(v4)) => self
+                .v4
+                .longest_match(v4.to_bits())
+                .or_else(|| self.v6.longest_match(address.to_bits())),
+            _ => self.v6.longest_match(address.to_bits()),
+        };
+        match found {
+            Some(i) => Decision {
+                action: self.rules[i].action,
+                rule: Some(&self.rules[i]),
+            },
+            None => Decision {
+                action: self.default,
+                rule: None,
+            },
+        }
+    }
+
+    // every rule that is never the longest match, see `Shadowed`. This
+    // also finds a rule hidden behind several smaller ones that only
+    // cover it together, wherever they are in the list. Each rule is
+    // compared with all the others, so this is quadratic in the number
+    // of rules
+    pub fn shadowed(&self) -> Vec<Shadowed<'_>> {
+        let mut shadowed = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if let Some(same) = self.rules[..i].iter().find(|r| r.cidr == rule.cidr) {
+                shadowed.push(Shadowed {
+                    rule,
+                    by: vec![same],
+                    same_action: same.action == rule.action,
+                });
+                continue;
+            }
+
+            // the part of the rule that no more specific rule matches yet,
+            // and the rules that took a piece of it
+            let mut left = vec![rule.cidr.clone()];
+            let mut by = Vec::new();
+            let inside = self.rules.iter().filter(|r| {
+                r.cidr.prefix() > rule.cidr.prefix() && rule.cidr.contains_cidr(&r.cidr)
+            });
+            for smaller in inside {
+                if !left.iter().any(|piece| piece.overlaps(&smaller.cidr)) {
+                    continue;
+                }
+                by.push(smaller);
+                left = left
+                    .iter()
+                    .flat_map(|piece| subtract(piece, &smaller.cidr))
+                    .collect();
+                if left.is_empty() {
+                    break;
+                }
+            }
+
+            if left.is_empty() {
+                let same_action = by.iter().all(|r| r.action == rule.action);
+                shadowed.push(Shadowed {
+                    rule,
+                    by,
+                    same_action,
+                });
+            }
+        }
+        shadowed
+    }
+
+    fn insert(&mut self, rule: Rule) {
+        let index = self.rules.len();
+        let trie = if rule.cidr.is_ipv4() {
+            &mut self.v4
+        } else {
+            &mut self.v6
+        };
+        trie.insert(rule.cidr.network().to_bits(), rule.cidr.prefix(), index);
+        self.rules.push(rule);
+    }
+}
+
+// the blocks that make up `piece` without `hole`. When the hole is inside
+// the piece, the piece is halved until the halves are either the hole or
+// clear of it, which leaves one block per bit of difference in prefix
+fn subtract(piece: &Cidr, hole: &Cidr) -> Vec<Cidr> {
+    if !piece.overlaps(hole) {
+        return vec![piece.clone()];
+    }
+    if hole.contains_cidr(piece) {
+        return Vec::new();
+    }
+    // the piece is larger, so it has a longer prefix to be split into
+    piece
+        .subnets(piece.prefix() + 1)
+        .into_iter()
+        .flatten()
+        .flat_map(|half| subtract(&half, hole))
+        .collect()
+}
+
+// a binary trie over the bits of an address, most significant first.
+// Nodes live in a vector and refer to each other by index
+#[derive(Debug, Clone)]
+struct Trie {
+    width: u8,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: [Option<u32>; 2],
+    // the first rule with exactly this prefix; later ones with the same
+    // prefix never decide anything
+    rule: Option<usize>,
+}
+
+impl Trie {
+    fn new(width: u8) -> Trie {
+        Trie {
+            width,
+            nodes: vec![Node::default()],
+        }
+    }
+
+    fn bit(&self, bits: u128, depth: u8) -> usize {
+        ((bits >> (self.width - 1 - depth)) & 1) as usize
+    }
+
+    fn insert(&mut self, bits: u128, prefix: u8, rule: usize) {
+        let mut node = 0;
+        for depth in 0..prefix {
+            let b = self.bit(bits, depth);
+            node = match self.nodes[node].children[b] {
+                Some(child) => child as usize,
+                None => {
+                    self.nodes.push(Node::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[b] = Some(child as u32);
+                    child
+                }
+            };
+        }
+        self.nodes[node].rule.get_or_insert(rule);
+    }
+
+    // the deepest rule on the path to `bits`, since every rule on the way
+    // down has a prefix of the address and the deeper ones are longer
+    fn longest_match(&self, bits: u128) -> Option<usize> {
+        let mut best = self.nodes[0].rule;
+        let mut node = 0;
+        for depth in 0..self.width {
+            match self.nodes[node].children[self.bit(bits, depth)] {
+                Some(child) => node = child as usize,
+                None => break,
+            }
+            best = self.nodes[node].rule.or(best);
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(acl: &Acl, address: &str) -> (Action, Option<usize>) {
+        let decision = acl.check(&address.parse().expect("valid address"));
+        (decision.action, decision.rule.map(|r| r.line))
+    }
+
+    fn shadowed(acl: &Acl) -> Vec<(usize, Vec<usize>, bool)> {
+        acl.shadowed()
+            .iter()
+            .map(|s| {
+                (
+                    s.rule.line,
+                    s.by.iter().map(|r| r.line).collect(),
+                    s.same_action,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_longest_prefix_decides_in_any_order() {
+        for text in [
+            "allow 10.0.0.0/8\ndeny 10.1.0.0/16\nallow 10.1.2.0/24",
+            "allow 10.1.2.0/24\ndeny 10.1.0.0/16\nallow 10.0.0.0/8",
+        ] {
+            let acl = Acl::parse(text).expect("valid rules");
+            assert_eq!(check(&acl, "10.1.2.3").0, Action::Allow);
+            assert_eq!(check(&acl, "10.1.3.3").0, Action::Deny);
+            assert_eq!(check(&acl, "10.2.0.1").0, Action::Allow);
+            assert_eq!(check(&acl, "11.0.0.1"), (Action::Deny, None));
+        }
+    }
+
+    #[test]
+    fn the_first_rule_wins_for_the_same_network() {
+        let acl =
+            Acl::parse("deny 10.0.0.0/8\nallow 10.0.0.0/8\ndefault allow").expect("valid rules");
+        assert_eq!(check(&acl, "10.0.0.1"), (Action::Deny, Some(1)));
+        assert_eq!(shadowed(&acl), vec![(2, vec![1], false)]);
+    }
+
+    #[test]
+    fn rules_covered_by_more_specific_ones_are_shadowed() {
+        let acl = Acl::parse(
+            "allow 10.0.0.128/25
+             deny  10.0.0.0/24
+             allow 10.0.0.0/25
+             allow 2001:db8::/32",
+        )
+        .expect("valid rules");
+        assert_eq!(shadowed(&acl), vec![(2, vec![1, 3], false)]);
+
+        // a rule with a longer prefix after a shorter one is not shadowed
+        let acl = Acl::parse("allow 10.0.0.0/8\ndeny 10.0.0.5").expect("valid rules");
+        assert!(shadowed(&acl).is_empty());
+        assert_eq!(check(&acl, "10.0.0.5"), (Action::Deny, Some(2)));
+    }
+
+    #[test]
+    fn families_are_kept_apart() {
+        let acl = Acl::parse("allow 0.0.0.0/0\ndeny ::/0").expect("valid rules");
+        assert_eq!(check(&acl, "192.0.2.1").0, Action::Allow);
+        assert_eq!(check(&acl, "2001:db8::1").0, Action::Deny);
+        assert!(shadowed(&acl).is_empty());
+    }
+
+    #[test]
+    fn mapped_addresses_follow_the_ipv4_rules() {
+        let acl = Acl::parse(
+            "deny 10.0.0.0/8
+default allow",
+        )
+        .expect("valid rules");
+        assert_eq!(check(&acl, "::ffff:10.0.0.1"), (Action::Deny, Some(1)));
+        assert_eq!(check(&acl, "::ffff:a00:1"), (Action::Deny, Some(1)));
+        assert_eq!(check(&acl, "::ffff:11.0.0.1"), (Action::Allow, None));
+        // not mapped, only the same bits
+        assert_eq!(check(&acl, "::a00:1"), (Action::Allow, None));
+
+        let acl = Acl::parse(
+            "allow 10.0.0.0/8
+deny ::ffff:0:0/96
+allow ::/0",
+        )
+        .expect("valid rules");
+        assert_eq!(check(&acl, "::ffff:10.0.0.1"), (Action::Allow, Some(1)));
+        assert_eq!(check(&acl, "::ffff:11.0.0.1"), (Action::Deny, Some(2)));
+        assert_eq!(check(&acl, "11.0.0.1"), (Action::Deny, None));
+    }
+}
@@ -0,0 +1,645 @@
+use crate::message::Message;
+use std::error::Error;
+use std::fmt;
+
+// a turtle drawing driven by messages. The turtle has a position and a
+// pen colour, and each message does one thing:
+//
+//     Move { x, y }         walks x across and y down, drawing a line
+//     Write(text)           stamps the text where the turtle is, and
+//                           leaves it after the last character. A line
+//                           break starts a new line under the first one
+//     ChangeColor(r, g, b)  picks the pen colour, each from 0 to 255
+//     Quit                  finishes the drawing; nothing is accepted
+//                           after it
+//
+// positions are in cells, one character wide, with y growing downwards
+// as on a screen.
+//
+// the canvas keeps every message it accepted, and the drawing is what
+// they add up to. Undoing one takes it off the end and rebuilds the
+// drawing from the messages that are left, starting from the nearest
+// snapshot so that it never replays more than `SNAPSHOT_EVERY` of them.
+// Messages only ever add marks, so a snapshot does not copy them: it
+// keeps how many there were, and going back to it cuts the list short
+
+pub const SNAPSHOT_EVERY: usize = 32;
+
+// the largest PPM image, in pixels
+pub const MAX_PIXELS: u64 = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb(0, 0, 0);
+    pub const WHITE: Rgb = Rgb(255, 255, 255);
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+// something drawn on the canvas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mark {
+    Line {
+        from: (i32, i32),
+        to: (i32, i32),
+        color: Rgb,
+    },
+    // one line of text, starting in the cell at `at`
+    Text {
+        at: (i32, i32),
+        text: String,
+        color: Rgb,
+    },
+}
+
+// the drawing after some number of messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub cursor: (i32, i32),
+    pub pen: Rgb,
+    pub marks: Vec<Mark>,
+    pub finished: bool,
+}
+
+impl State {
+    pub fn new() -> State {
+        State {
+            cursor: (0, 0),
+            pen: Rgb::BLACK,
+            marks: Vec::new(),
+            finished: false,
+        }
+    }
+
+    // the state after `message`, or an error that leaves it unchanged
+    pub fn apply(&mut self, message: &Message) -> Result<(), CanvasError> {
+        if self.finished {
+            return Err(CanvasError::Finished);
+        }
+        match message {
+            Message::Quit => self.finished = true,
+            Message::Move { x, y } => {
+                let to = offset(self.cursor, *x, *y)?;
+                self.marks.push(Mark::Line {
+                    from: self.cursor,
+                    to,
+                    color: self.pen,
+                });
+                self.cursor = to;
+            }
+            Message::Write(text) => {
+                // worked out in full first, so that an error changes nothing
+                let mut marks = Vec::new();
+                let mut at = self.cursor;
+                for (i, line) in text.split('\n').enumerate() {
+                    if i > 0 {
+                        at = offset((self.cursor.0, at.1), 0, 1)?;
+                    }
+                    if !line.is_empty() {
+                        marks.push(Mark::Text {
+                            at,
+                            text: line.to_string(),
+                            color: self.pen,
+                        });
+                    }
+                    let width =
+                        i32::try_from(line.chars().count()).map_err(|_| CanvasError::OffCanvas)?;
+                    at = offset(at, width, 0)?;
+                }
+                self.marks.extend(marks);
+                self.cursor = at;
+            }
+            Message::ChangeColor(r, g, b) => {
+                let [r, g, b] = [*r, *g, *b].map(u8::try_from);
+                match (r, g, b) {
+                    (Ok(r), Ok(g), Ok(b)) => self.pen = Rgb(r, g, b),
+                    _ => return Err(CanvasError::ColorOutOfRange),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the smallest box of cells that holds every mark and the cursor, as
+    // its top left and bottom right cells
+    pub fn bounds(&self) -> ((i32, i32), (i32, i32)) {
+        let mut min = self.cursor;
+        let mut max = self.cursor;
+        let mut include = |(x, y): (i32, i32)| {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        };
+        for mark in &self.marks {
+            match mark {
+                Mark::Line { from, to, .. } => {
+                    include(*from);
+                    include(*to);
+                }
+                Mark::Text { at, text, .. } => {
+                    include(*at);
+                    let width = text.chars().count().min(i32::MAX as usize) as i32;
+                    include((at.0.saturating_add(width - 1), at.1));
+                }
+            }
+        }
+        (min, max)
+    }
+
+    pub fn to_svg(&self) -> String {
+        // a cell of margin all round, and coordinates counted from there
+        let (min, max) = self.bounds();
+        let (left, top) = (min.0 as i64 - 1, min.1 as i64 - 1);
+        let width = max.0 as i64 - left + 2;
+        let height = max.1 as i64 - top + 2;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+            width,
+            height,
+            Rgb::WHITE
+        );
+        for mark in &self.marks {
+            match mark {
+                // lines run through the centres of the cells
+                Mark::Line { from, to, color } => svg.push_str(&format!(
+                    "<line x1=\"{}.5\" y1=\"{}.5\" x2=\"{}.5\" y2=\"{}.5\" \
+                     stroke=\"{}\" stroke-width=\"0.2\" stroke-linecap=\"round\"/>\n",
+                    from.0 as i64 - left,
+                    from.1 as i64 - top,
+                    to.0 as i64 - left,
+                    to.1 as i64 - top,
+                    color
+                )),
+                // `textLength` stretches the text to one cell a character
+                Mark::Text { at, text, color } => svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}.8\" fill=\"{}\" font-family=\"monospace\" \
+                     font-size=\"1\" textLength=\"{}\" xml:space=\"preserve\">{}</text>\n",
+                    at.0 as i64 - left,
+                    at.1 as i64 - top,
+                    color,
+                    text.chars().count(),
+                    escape_xml(text)
+                )),
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // a binary PPM (P6) with `scale` pixels to a cell. There is no font,
+    // so each character other than a space is drawn as a block
+    pub fn to_ppm(&self, scale: u32) -> Result<Vec<u8>, CanvasError> {
+        let scale = scale.max(1) as u64;
+        let (min, max) = self.bounds();
+        let (left, top) = (min.0 as i64 - 1, min.1 as i64 - 1);
+        let cells_wide = (max.0 as i64 - left + 2) as u64;
+        let cells_high = (max.1 as i64 - top + 2) as u64;
+        // a canvas as wide as the range of i32 times the largest scale is
+        // more than a u64 can hold
+        let (width, height) = match (cells_wide.checked_mul(scale), cells_high.checked_mul(scale)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(CanvasError::TooLarge),
+        };
+        if width.saturating_mul(height) > MAX_PIXELS {
+            return Err(CanvasError::TooLarge);
+        }
+
+        let mut image = Image {
+            width: width as usize,
+            pixels: vec![Rgb::WHITE; (width * height) as usize],
+        };
+        // a square from (x0, y0) to (x1, y1) pixels into cell (x, y)
+        let mut fill = |(x, y): (i32, i32), (x0, y0): (u64, u64), (x1, y1): (u64, u64), color| {
+            let cx = (x as i64 - left) as u64 * scale;
+            let cy = (y as i64 - top) as u64 * scale;
+            for py in cy + y0..cy + y1 {
+                for px in cx + x0..cx + x1 {
+                    image.set(px as usize, py as usize, color);
+                }
+            }
+        };
+
+        // a dot in the middle of each cell a line passes through, a fifth
+        // of the cell wide but at least one pixel
+        let dot = (scale / 5).max(1);
+        let (d0, d1) = ((scale - dot) / 2, (scale - dot) / 2 + dot);
+        // characters are blocks with a fifth of a cell to either side and
+        // a tenth above and below
+        let (cx0, cx1) = (scale / 5, scale - scale / 5);
+        let (cy0, cy1) = (scale / 10, scale - scale / 10);
+
+        for mark in &self.marks {
+            match mark {
+                Mark::Line { from, to, color } => {
+                    for cell in line_cells(*from, *to) {
+                        fill(cell, (d0, d0), (d1, d1), *color);
+                    }
+                }
+                Mark::Text { at, text, color } => {
+                    for (i, c) in text.chars().enumerate() {
+                        if !c.is_whitespace() {
+                            let cell = (at.0 + i as i32, at.1);
+                            fill(
+                                cell,
+                                (cx0, cy0),
+                                (cx1.max(cx0 + 1), cy1.max(cy0 + 1)),
+                                *color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        for Rgb(r, g, b) in image.pixels {
+            ppm.extend_from_slice(&[r, g, b]);
+        }
+        Ok(ppm)
+    }
+}
+
+impl Default for State {
+    fn default() -> State {
+        State::new()
+    }
+}
+
+struct Image {
+    width: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    fn set(&mut self, x: usize, y: usize, color: Rgb) {
+        if let Some(p) = self.pixels.get_mut(y * self.width + x) {
+            *p = color;
+        }
+    }
+}
+
+fn offset((x, y): (i32, i32), dx: i32, dy: i32) -> Result<(i32, i32), CanvasError> {
+    match (x.checked_add(dx), y.checked_add(dy)) {
+        (Some(x), Some(y)) => Ok((x, y)),
+        _ => Err(CanvasError::OffCanvas),
+    }
+}
+
+// the cells on the straight line between two cells, by Bresenham's
+// algorithm: one step at a time along the longer axis, with a step along
+// the other whenever the error builds up past half a cell
+fn line_cells(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+    let (x1, y1) = (to.0 as i64, to.1 as i64);
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut error = dx + dy;
+    let mut cells = Vec::new();
+    loop {
+        cells.push((x as i32, y as i32));
+        if x == x1 && y == y1 {
+            return cells;
+        }
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            // XML 1.0 has no way to write most control characters
+            c if c.is_control() => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CanvasError {
+    // a message after `Quit`
+    Finished,
+    ColorOutOfRange,
+    // a move or text that would take the cursor past the range of i32
+    OffCanvas,
+    NothingToUndo,
+    NothingToRedo,
+    // a PPM with more than `MAX_PIXELS` pixels
+    TooLarge,
+    // a message that could not be replayed, by its index
+    Replay {
+        index: usize,
+        error: Box<CanvasError>,
+    },
+}
+
+impl fmt::Display for CanvasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanvasError::Finished => write!(f, "the drawing is finished"),
+            CanvasError::ColorOutOfRange => {
+                write!(f, "colour components must be between 0 and 255")
+            }
+            CanvasError::OffCanvas => write!(f, "the cursor would leave the canvas"),
+            CanvasError::NothingToUndo => write!(f, "nothing to undo"),
+            CanvasError::NothingToRedo => write!(f, "nothing to redo"),
+            CanvasError::TooLarge => {
+                write!(f, "the image would have more than {} pixels", MAX_PIXELS)
+            }
+            CanvasError::Replay { index, error } => write!(f, "message {}: {}", index + 1, error),
+        }
+    }
+}
+
+impl Error for CanvasError {}
+
+// the messages so far and the drawing they make, with undo and redo
+#[derive(Debug, Clone, Default)]
+pub struct Canvas {
+    history: Vec<Message>,
+    // undone messages, the most recent last
+    undone: Vec<Message>,
+    state: State,
+    // the state after each multiple of `SNAPSHOT_EVERY` messages, after
+    // the first
+    snapshots: Vec<Snapshot>,
+}
+
+// a `State` without its marks, which are the first `marks` of any later
+// state's
+#[derive(Debug, Clone, Copy)]
+struct Snapshot {
+    cursor: (i32, i32),
+    pen: Rgb,
+    finished: bool,
+    marks: usize,
+}
+
+impl Snapshot {
+    fn of(state: &State) -> Snapshot {
+        Snapshot {
+            cursor: state.cursor,
+            pen: state.pen,
+            finished: state.finished,
+            marks: state.marks.len(),
+        }
+    }
+
+    // takes a later state back to this one
+    fn restore(&self, state: &mut State) {
+        state.cursor = self.cursor;
+        state.pen = self.pen;
+        state.finished = self.finished;
+        state.marks.truncate(self.marks);
+    }
+}
+
+impl Canvas {
+    pub fn new() -> Canvas {
+        Canvas::default()
+    }
+
+    // a canvas that has been sent `messages`, in order
+    pub fn replay<'a, I>(messages: I) -> Result<Canvas, CanvasError>
+    where
+        I: IntoIterator<Item = &'a Message>,
+    {
+        let mut canvas = Canvas::new();
+        for (index, message) in messages.into_iter().enumerate() {
+            canvas.apply(message).map_err(|error| CanvasError::Replay {
+                index,
+                error: Box::new(error),
+            })?;
+        }
+        Ok(canvas)
+    }
+
+    // applies the message and adds it to the history. What was undone can
+    // no longer be redone
+    pub fn apply(&mut self, message: &Message) -> Result<(), CanvasError> {
+        self.state.apply(message)?;
+        self.history.push(message.clone());
+        self.undone.clear();
+        if self.history.len().is_multiple_of(SNAPSHOT_EVERY) {
+            self.snapshots.push(Snapshot::of(&self.state));
+        }
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Result<&Message, CanvasError> {
+        let message = self.history.pop().ok_or(CanvasError::NothingToUndo)?;
+        self.undone.push(message);
+        self.snapshots.truncate(self.history.len() / SNAPSHOT_EVERY);
+        let (snapshot, from) = self.snapshot_before(self.history.len());
+        snapshot.restore(&mut self.state);
+        for message in &self.history[from..] {
+            self.state
+                .apply(message)
+                .expect("messages in the history applied before");
+        }
+        Ok(&self.undone[self.undone.len() - 1])
+    }
+
+    pub fn redo(&mut self) -> Result<&Message, CanvasError> {
+        let message = self.undone.pop().ok_or(CanvasError::NothingToRedo)?;
+        // a message in the history applied before, and applies again to
+        // the same state
+        let undone = std::mem::take(&mut self.undone);
+        let result = self.apply(&message);
+        self.undone = undone;
+        result?;
+        Ok(&self.history[self.history.len() - 1])
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    pub fn history(&self) -> &[Message] {
+        &self.history
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    // the drawing as it was after the first `n` messages of the history,
+    // built from the last snapshot before them
+    pub fn state_at(&self, n: usize) -> State {
+        let n = n.min(self.history.len());
+        let (snapshot, from) = self.snapshot_before(n);
+        let mut state = State {
+            marks: self.state.marks[..snapshot.marks].to_vec(),
+            ..State::new()
+        };
+        snapshot.restore(&mut state);
+        for message in &self.history[from..n] {
+            state
+                .apply(message)
+                .expect("messages in the history applied before");
+        }
+        state
+    }
+
+    // the last snapshot at or before the first `n` messages, and the
+    // number of messages it is after
+    fn snapshot_before(&self, n: usize) -> (Snapshot, usize) {
+        match (n / SNAPSHOT_EVERY).min(self.snapshots.len()) {
+            0 => (Snapshot::of(&State::new()), 0),
+            k => (self.snapshots[k - 1], k * SNAPSHOT_EVERY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // messages that always apply: short moves, colours in range and a
+    // few words of text, with no `Quit`
+    fn message() -> impl Strategy<Value = Message> {
+        prop_oneof![
+            (-5..5, -5..5).prop_map(|(x, y)| Message::Move { x, y }),
+            "[a-c ]{0,4}(\n[a-c]{0,3})?".prop_map(Message::Write),
+            (0..=255, 0..=255, 0..=255).prop_map(|(r, g, b)| Message::ChangeColor(r, g, b)),
+        ]
+    }
+
+    fn moves(count: i32) -> Vec<Message> {
+        (0..count).map(|i| Message::Move { x: i, y: 1 }).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn undo_matches_a_full_replay(
+            messages in prop::collection::vec(message(), 0..80),
+            undos in 0usize..80,
+        ) {
+            let mut canvas = Canvas::replay(&messages).expect("messages apply");
+            let undos = undos.min(messages.len());
+            for _ in 0..undos {
+                canvas.undo().expect("something to undo");
+            }
+            let kept = messages.len() - undos;
+            let replayed = Canvas::replay(&messages[..kept]).expect("messages apply");
+            prop_assert_eq!(canvas.state(), replayed.state());
+            prop_assert_eq!(canvas.snapshots.len(), kept / SNAPSHOT_EVERY);
+        }
+
+        #[test]
+        fn state_at_matches_a_full_replay(messages in prop::collection::vec(message(), 0..80)) {
+            let canvas = Canvas::replay(&messages).expect("messages apply");
+            for n in 0..=messages.len() {
+                let replayed = Canvas::replay(&messages[..n]).expect("messages apply");
+                prop_assert_eq!(&canvas.state_at(n), replayed.state());
+            }
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_across_a_snapshot() {
+        let messages = moves(SNAPSHOT_EVERY as i32 + 8);
+        let mut canvas = Canvas::replay(&messages).expect("messages apply");
+        let full = canvas.state().clone();
+
+        // back to before the first snapshot, and forward again
+        for _ in 0..10 {
+            canvas.undo().expect("something to undo");
+        }
+        let before = Canvas::replay(&messages[..SNAPSHOT_EVERY - 2]).expect("messages apply");
+        assert_eq!(canvas.state(), before.state());
+        for _ in 0..10 {
+            canvas.redo().expect("something to redo");
+        }
+        assert_eq!(canvas.state(), &full);
+        assert_eq!(canvas.history(), &messages[..]);
+
+        // a new message after undoing past the snapshot makes a new one
+        for _ in 0..10 {
+            canvas.undo().expect("something to undo");
+        }
+        for message in &messages[SNAPSHOT_EVERY - 2..] {
+            canvas.apply(message).expect("message applies");
+        }
+        assert!(!canvas.can_redo());
+        assert_eq!(canvas.state(), &full);
+        let snapshot = Canvas::replay(&messages[..SNAPSHOT_EVERY]).expect("messages apply");
+        assert_eq!(&canvas.state_at(SNAPSHOT_EVERY), snapshot.state());
+    }
+
+    #[test]
+    fn undo_and_redo_at_the_ends() {
+        let mut canvas = Canvas::new();
+        assert_eq!(canvas.undo(), Err(CanvasError::NothingToUndo));
+        canvas.apply(&Message::Quit).expect("quit applies");
+        assert_eq!(canvas.redo(), Err(CanvasError::NothingToRedo));
+        assert_eq!(
+            canvas.apply(&Message::Move { x: 1, y: 1 }),
+            Err(CanvasError::Finished)
+        );
+        assert_eq!(canvas.undo(), Ok(&Message::Quit));
+        assert!(canvas.apply(&Message::Move { x: 1, y: 1 }).is_ok());
+    }
+
+    #[test]
+    fn svg_snapshot() {
+        let canvas = Canvas::replay(&[
+            Message::ChangeColor(255, 0, 0),
+            Message::Move { x: 2, y: 1 },
+            Message::Write("a<b".to_string()),
+        ])
+        .expect("messages apply");
+        assert_eq!(
+            canvas.state().to_svg(),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 8 4\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>\n\
+             <line x1=\"1.5\" y1=\"1.5\" x2=\"3.5\" y2=\"2.5\" \
+             stroke=\"#ff0000\" stroke-width=\"0.2\" stroke-linecap=\"round\"/>\n\
+             <text x=\"3\" y=\"2.8\" fill=\"#ff0000\" font-family=\"monospace\" \
+             font-size=\"1\" textLength=\"3\" xml:space=\"preserve\">a&lt;b</text>\n\
+             </svg>\n"
+        );
+    }
+
+    #[test]
+    fn huge_images_are_refused_without_overflowing() {
+        let canvas = Canvas::replay(&[
+            Message::Move { x: i32::MIN, y: 0 },
+            Message::Move { x: i32::MAX, y: 0 },
+            Message::Move { x: i32::MAX, y: 0 },
+        ])
+        .expect("messages apply");
+        assert_eq!(canvas.state().to_ppm(u32::MAX), Err(CanvasError::TooLarge));
+        assert_eq!(canvas.state().to_ppm(1), Err(CanvasError::TooLarge));
+
+        let small = Canvas::replay(&[Message::Move { x: 1, y: 0 }]).expect("message applies");
+        let ppm = small.state().to_ppm(10).expect("small enough");
+        assert!(ppm.starts_with(b"P6\n40 30\n255\n"));
+        assert_eq!(ppm.len(), b"P6\n40 30\n255\n".len() + 40 * 30 * 3);
+    }
+}
@@ -0,0 +1,594 @@
+use crate::ip::{IpAddr, ParseIpError};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// a block of addresses written as `network/prefix`, like `10.0.0.0/8`:
+// the addresses whose first `prefix` bits are those of the network. The
+// other bits are the host part.
+//
+// the arithmetic is done on the address as a number (`IpAddr::to_bits`),
+// in a u128 for both families, with the width (32 or 128) kept apart
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cidr {
+    // always with the host bits cleared, and never with a zone
+    network: IpAddr,
+    prefix: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CidrError {
+    Address(ParseIpError),
+    InvalidPrefix(String),
+    PrefixTooLong { prefix: u8, max: u8 },
+    // a subnet prefix shorter than the network's own
+    PrefixTooShort { prefix: u8, min: u8 },
+    ZoneNotAllowed,
+}
+
+impl fmt::Display for CidrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CidrError::Address(e) => write!(f, "{}", e),
+            CidrError::InvalidPrefix(p) => write!(f, "invalid prefix length `{}`", p),
+            CidrError::PrefixTooLong { prefix, max } => {
+                write!(f, "prefix /{} is longer than /{}", prefix, max)
+            }
+            CidrError::PrefixTooShort { prefix, min } => {
+                write!(f, "prefix /{} is shorter than /{}", prefix, min)
+            }
+            CidrError::ZoneNotAllowed => write!(f, "a network cannot have a zone"),
+        }
+    }
+}
+
+impl Error for CidrError {}
+
+impl From<ParseIpError> for CidrError {
+    fn from(e: ParseIpError) -> CidrError {
+        CidrError::Address(e)
+    }
+}
+
+impl Cidr {
+    // the network `address` is in. Host bits in `address` are cleared
+    pub fn new(address: &IpAddr, prefix: u8) -> Result<Cidr, CidrError> {
+        if address.zone().is_some() {
+            return Err(CidrError::ZoneNotAllowed);
+        }
+        let max = width(address);
+        if prefix > max {
+            return Err(CidrError::PrefixTooLong { prefix, max });
+        }
+        let network = from_bits(address, address.to_bits() & mask(max, prefix));
+        Ok(Cidr { network, prefix })
+    }
+
+    // `10.1.2.3/8` as the address and the network it is in, which a plain
+    // parse forgets. A bare address is a network of its own, a /32 or /128
+    pub fn parse_host(s: &str) -> Result<(IpAddr, Cidr), CidrError> {
+        let (address, prefix) = match s.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let address: IpAddr = address.parse()?;
+        let prefix = match prefix {
+            None => width(&address),
+            Some(p) => {
+                let digits = !p.is_empty() && p.len() <= 3 && p.bytes().all(|b| b.is_ascii_digit());
+                if !digits {
+                    return Err(CidrError::InvalidPrefix(p.to_string()));
+                }
+                p.parse()
+                    .map_err(|_| CidrError::InvalidPrefix(p.to_string()))?
+            }
+        };
+        let cidr = Cidr::new(&address, prefix)?;
+        Ok((address, cidr))
+    }
+
+    pub fn network(&self) -> &IpAddr {
+        &self.network
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn is_ipv4(&self) -> bool {
+        self.network.is_ipv4()
+    }
+
+    // the prefix as an address, `255.255.255.0` for a /24
+    pub fn netmask(&self) -> IpAddr {
+        from_bits(&self.network, mask(self.width(), self.prefix))
+    }
+
+    // the host bits as an address, `0.0.0.255` for a /24
+    pub fn hostmask(&self) -> IpAddr {
+        from_bits(
+            &self.network,
+            !mask(self.width(), self.prefix) & all(self.width()),
+        )
+    }
+
+    // the highest address in the block
+    pub fn last(&self) -> IpAddr {
+        from_bits(&self.network, self.last_bits())
+    }
+
+    // IPv4 networks send to every host at the highest address. IPv6 has no
+    // broadcast, and /31 and /32 networks have no room for one
+    pub fn broadcast(&self) -> Option<IpAddr> {
+        (self.is_ipv4() && self.prefix < 31).then(|| self.last())
+    }
+
+    // the first and last address a host can have. In IPv4 the network and
+    // broadcast addresses are left out, except in a /31 (RFC 3021), where
+    // both addresses are hosts, and a /32. In IPv6 every address can be a
+    // host
+    pub fn hosts(&self) -> (IpAddr, IpAddr) {
+        let (first, last) = (self.network.to_bits(), self.last_bits());
+        if self.is_ipv4() && self.prefix < 31 {
+            (
+                from_bits(&self.network, first + 1),
+                from_bits(&self.network, last - 1),
+            )
+        } else {
+            (self.network.clone(), self.last())
+        }
+    }
+
+    // the number of addresses, or `None` for `::/0`, whose 2^128 addresses
+    // do not fit
+    pub fn size(&self) -> Option<u128> {
+        1u128.checked_shl((self.width() - self.prefix) as u32)
+    }
+
+    // the number of host addresses, as counted by `hosts`
+    pub fn host_count(&self) -> Option<u128> {
+        match self.size()? {
+            n if self.is_ipv4() && self.prefix < 31 => Some(n - 2),
+            n => Some(n),
+        }
+    }
+
+    // as everywhere else (see `Category`), an IPv4-mapped address stands
+    // for the IPv4 address it holds: `::ffff:10.0.0.1` is in `10.0.0.0/8`.
+    // It is still an IPv6 address too, and so also in `::ffff:0:0/96`. A
+    // plain IPv4 address is never in an IPv6 block
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        let mapped;
+        let address = match address.to_ipv4_mapped() {
+            Some(v4) if self.is_ipv4() => {
+                mapped = v4;
+                &mapped
+            }
+            _ => address,
+        };
+        address.is_ipv4() == self.is_ipv4()
+            && address.to_bits() & mask(self.width(), self.prefix) == self.network.to_bits()
+    }
+
+    // whether every address of `other` is in this block. Blocks of the
+    // other family are never inside, mapped or not: their prefixes count
+    // different bits
+    pub fn contains_cidr(&self, other: &Cidr) -> bool {
+        other.is_ipv4() == self.is_ipv4()
+            && other.prefix >= self.prefix
+            && self.contains(&other.network)
+    }
+
+    pub fn overlaps(&self, other: &Cidr) -> bool {
+        self.contains_cidr(other) || other.contains_cidr(self)
+    }
+
+    // the block cut into blocks with the longer `prefix`, in order
+    pub fn subnets(&self, prefix: u8) -> Result<Subnets, CidrError> {
+        let max = self.width();
+        if prefix > max {
+            return Err(CidrError::PrefixTooLong { prefix, max });
+        }
+        if prefix < self.prefix {
+            return Err(CidrError::PrefixTooShort {
+                prefix,
+                min: self.prefix,
+            });
+        }
+        Ok(Subnets {
+            family: self.network.clone(),
+            prefix,
+            next: Some(self.network.to_bits()),
+            last: self.last_bits(),
+        })
+    }
+
+    fn width(&self) -> u8 {
+        width(&self.network)
+    }
+
+    fn last_bits(&self) -> u128 {
+        self.network.to_bits() | (!mask(self.width(), self.prefix) & all(self.width()))
+    }
+}
+
+pub struct Subnets {
+    // an address of the right family, to build the others from
+    family: IpAddr,
+    prefix: u8,
+    next: Option<u128>,
+    last: u128,
+}
+
+impl Iterator for Subnets {
+    type Item = Cidr;
+
+    fn next(&mut self) -> Option<Cidr> {
+        let start = self.next?;
+        let width = width(&self.family);
+        let step_minus_one = !mask(width, self.prefix) & all(width);
+        let end = start + step_minus_one;
+        self.next = if end >= self.last {
+            None
+        } else {
+            Some(end + 1)
+        };
+        Some(Cidr {
+            network: from_bits(&self.family, start),
+            prefix: self.prefix,
+        })
+    }
+}
+
+// the fewest blocks that cover exactly the addresses of `cidrs`. Blocks
+// that overlap or touch are joined, and each run of addresses is then cut
+// into the largest aligned blocks that fit. IPv4 blocks come first
+pub fn merge(cidrs: &[Cidr]) -> Vec<Cidr> {
+    let mut ranges: Vec<(bool, u128, u128)> = cidrs
+        .iter()
+        .map(|c| (!c.is_ipv4(), c.network.to_bits(), c.last_bits()))
+        .collect();
+    ranges.sort_unstable();
+
+    // join overlapping and adjacent ranges of the same family
+    let mut joined: Vec<(bool, u128, u128)> = Vec::new();
+    for (v6, start, end) in ranges {
+        match joined.last_mut() {
+            Some((last_v6, _, last_end))
+                if *last_v6 == v6 && last_end.checked_add(1).is_none_or(|next| start <= next) =>
+            {
+                *last_end = (*last_end).max(end);
+            }
+            _ => joined.push((v6, start, end)),
+        }
+    }
+
+    let mut merged = Vec::new();
+    for (v6, start, end) in joined {
+        let family = if v6 {
+            IpAddr::V6([0; 8], None)
+        } else {
+            IpAddr::V4([0; 4])
+        };
+        let width = width(&family);
+        let mut start = start;
+        loop {
+            // the largest block that starts at `start` and ends by `end`:
+            // aligned, so no longer than the trailing zeros allow, and no
+            // longer than what is left
+            let mut size_bits = if start == 0 {
+                width as u32
+            } else {
+                start.trailing_zeros().min(width as u32)
+            };
+            while size_bits > 0 && block_end(start, size_bits) > end {
+                size_bits -= 1;
+            }
+            merged.push(Cidr {
+                network: from_bits(&family, start),
+                prefix: width - size_bits as u8,
+            });
+
+            let block_last = block_end(start, size_bits);
+            if block_last >= end {
+                break;
+            }
+            start = block_last + 1;
+        }
+    }
+    merged
+}
+
+// the last address of the block of 2^size_bits addresses at `start`
+fn block_end(start: u128, size_bits: u32) -> u128 {
+    match 1u128.checked_shl(size_bits) {
+        Some(size) => start.saturating_add(size - 1),
+        None => u128::MAX,
+    }
+}
+
+fn width(address: &IpAddr) -> u8 {
+    if address.is_ipv4() {
+        32
+    } else {
+        128
+    }
+}
+
+// all ones in the low `width` bits
+fn all(width: u8) -> u128 {
+    u128::MAX >> (128 - width as u32)
+}
+
+// the first `prefix` of the `width` bits set
+fn mask(width: u8, prefix: u8) -> u128 {
+    let host_bits = (width - prefix) as u32;
+    all(width) & u128::MAX.checked_shl(host_bits).unwrap_or(0)
+}
+
+// an address of the same family as `like` with the given bits
+fn from_bits(like: &IpAddr, bits: u128) -> IpAddr {
+    if like.is_ipv4() {
+        IpAddr::V4((bits as u32).to_be_bytes())
+    } else {
+        let mut segments = [0; 8];
+        for (i, s) in segments.iter_mut().enumerate() {
+            *s = (bits >> (112 - 16 * i)) as u16;
+        }
+        IpAddr::V6(segments, None)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Cidr, CidrError> {
+        Cidr::parse_host(s).map(|(_, cidr)| cidr)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+// a subnet calculator's summary of `cidr`, with `address` as the one the
+// user typed in
+pub fn report(address: &IpAddr, cidr: &Cidr) -> String {
+    let count = |n: Option<u128>| n.map_or_else(|| String::from("2^128"), |n| n.to_string());
+    let (first, last) = cidr.hosts();
+
+    let mut lines = vec![
+        ("Address", address.to_string()),
+        ("Network", cidr.to_string()),
+        ("Netmask", format!("{} = {}", cidr.netmask(), cidr.prefix())),
+        ("Wildcard", cidr.hostmask().to_string()),
+    ];
+    if let Some(broadcast) = cidr.broadcast() {
+        lines.push(("Broadcast", broadcast.to_string()));
+    } else {
+        lines.push(("Last", cidr.last().to_string()));
+    }
+    lines.push(("First host", first.to_string()));
+    lines.push(("Last host", last.to_string()));
+    lines.push(("Addresses", count(cidr.size())));
+    lines.push(("Hosts", count(cidr.host_count())));
+    lines.push(("Category", cidr.network().category().to_string()));
+
+    lines
+        .iter()
+        .map(|(label, value)| format!("{:<11} {}\n", format!("{}:", label), value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().expect("valid network")
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().expect("valid address")
+    }
+
+    fn all(cidrs: &[&str]) -> Vec<Cidr> {
+        cidrs.iter().map(|c| cidr(c)).collect()
+    }
+
+    #[test]
+    fn hosts_of_small_ipv4_networks() {
+        let net = cidr("192.0.2.4/30");
+        assert_eq!(net.hosts(), (ip("192.0.2.5"), ip("192.0.2.6")));
+        assert_eq!((net.size(), net.host_count()), (Some(4), Some(2)));
+        assert_eq!(net.broadcast(), Some(ip("192.0.2.7")));
+
+        // RFC 3021: both addresses of a /31 are hosts
+        let net = cidr("192.0.2.4/31");
+        assert_eq!(net.hosts(), (ip("192.0.2.4"), ip("192.0.2.5")));
+        assert_eq!((net.size(), net.host_count()), (Some(2), Some(2)));
+        assert_eq!(net.broadcast(), None);
+
+        let net = cidr("192.0.2.4/32");
+        assert_eq!(net.hosts(), (ip("192.0.2.4"), ip("192.0.2.4")));
+        assert_eq!((net.size(), net.host_count()), (Some(1), Some(1)));
+        assert_eq!(net.broadcast(), None);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(cidr("::/0").size(), None);
+        assert_eq!(cidr("::/0").host_count(), None);
+        assert_eq!(cidr("::/1").size(), Some(1 << 127));
+        assert_eq!(cidr("0.0.0.0/0").size(), Some(1 << 32));
+        assert_eq!(cidr("0.0.0.0/0").host_count(), Some((1 << 32) - 2));
+        assert_eq!(cidr("2001:db8::/64").host_count(), Some(1 << 64));
+    }
+
+    #[test]
+    fn masks_and_host_bits() {
+        let net = cidr("10.1.2.3/20");
+        assert_eq!(net.to_string(), "10.1.0.0/20");
+        assert_eq!(net.netmask(), ip("255.255.240.0"));
+        assert_eq!(net.hostmask(), ip("0.0.15.255"));
+        assert_eq!(net.last(), ip("10.1.15.255"));
+        assert_eq!(cidr("2001:db8::1/32").netmask(), ip("ffff:ffff::"));
+    }
+
+    #[test]
+    fn subnets_reach_the_end_of_the_address_space() {
+        let subnets: Vec<String> = cidr("255.255.255.0/24")
+            .subnets(26)
+            .expect("longer prefix")
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            subnets,
+            [
+                "255.255.255.0/26",
+                "255.255.255.64/26",
+                "255.255.255.128/26",
+                "255.255.255.192/26"
+            ]
+        );
+
+        let last: Vec<Cidr> = cidr("ffff:ffff:ffff:ffff:ffff:ffff:ffff:fff0/124")
+            .subnets(128)
+            .expect("longer prefix")
+            .collect();
+        assert_eq!(last.len(), 16);
+        assert_eq!(
+            last[15],
+            cidr("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff/128")
+        );
+
+        assert_eq!(
+            cidr("10.0.0.0/8").subnets(8).expect("same prefix").count(),
+            1
+        );
+        assert_eq!(
+            cidr("10.0.0.0/8").subnets(7).err(),
+            Some(CidrError::PrefixTooShort { prefix: 7, min: 8 })
+        );
+        assert_eq!(
+            cidr("10.0.0.0/8").subnets(33).err(),
+            Some(CidrError::PrefixTooLong {
+                prefix: 33,
+                max: 32
+            })
+        );
+    }
+
+    #[test]
+    fn merge_joins_adjacent_and_overlapping_blocks() {
+        let merged = merge(&all(&["10.0.0.0/25", "10.0.0.128/25"]));
+        assert_eq!(merged, all(&["10.0.0.0/24"]));
+
+        let merged = merge(&all(&["10.0.0.0/24", "10.0.0.64/26", "10.0.1.0/25"]));
+        assert_eq!(merged, all(&["10.0.0.0/24", "10.0.1.0/25"]));
+
+        // not aligned as one block, so it stays two
+        let merged = merge(&all(&["10.0.1.0/24", "10.0.2.0/24"]));
+        assert_eq!(merged, all(&["10.0.1.0/24", "10.0.2.0/24"]));
+
+        let merged = merge(&all(&["10.0.0.0/24", "0.0.0.0/0", "192.0.2.0/24"]));
+        assert_eq!(merged, all(&["0.0.0.0/0"]));
+
+        let merged = merge(&all(&[
+            "2001:db8::/33",
+            "10.0.0.0/8",
+            "2001:db8:8000::/33",
+            "::/0",
+        ]));
+        assert_eq!(merged, all(&["10.0.0.0/8", "::/0"]));
+
+        // the same bits in different families are not joined
+        let merged = merge(&all(&["0.0.0.0/1", "::/97", "128.0.0.0/1"]));
+        assert_eq!(merged, all(&["0.0.0.0/0", "::/97"]));
+
+        assert_eq!(merge(&[]), Vec::new());
+    }
+
+    #[test]
+    fn parse_host_keeps_the_address() {
+        let (address, net) = Cidr::parse_host(" 10.1.2.3/8 ").expect("valid");
+        assert_eq!((address, net), (ip("10.1.2.3"), cidr("10.0.0.0/8")));
+        let (_, net) = Cidr::parse_host("2001:db8::1").expect("valid");
+        assert_eq!(net.prefix(), 128);
+
+        let cases = [
+            ("10.0.0.0/", CidrError::InvalidPrefix(String::new())),
+            ("10.0.0.0/+8", CidrError::InvalidPrefix("+8".to_string())),
+            (
+                "10.0.0.0/0008",
+                CidrError::InvalidPrefix("0008".to_string()),
+            ),
+            ("10.0.0.0/300", CidrError::InvalidPrefix("300".to_string())),
+            (
+                "10.0.0.0/33",
+                CidrError::PrefixTooLong {
+                    prefix: 33,
+                    max: 32,
+                },
+            ),
+            (
+                "::/129",
+                CidrError::PrefixTooLong {
+                    prefix: 129,
+                    max: 128,
+                },
+            ),
+            ("fe80::1%eth0/64", CidrError::ZoneNotAllowed),
+            (
+                "10.0.0/8",
+                CidrError::Address(ParseIpError::WrongOctetCount(3)),
+            ),
+        ];
+        for (input, error) in cases {
+            assert_eq!(Cidr::parse_host(input), Err(error), "{input}");
+        }
+    }
+
+    #[test]
+    fn mapped_addresses_are_in_ipv4_blocks() {
+        let net = cidr("10.0.0.0/8");
+        assert!(net.contains(&ip("::ffff:10.0.0.1")));
+        assert!(!net.contains(&ip("::ffff:11.0.0.1")));
+        assert!(cidr("::ffff:0:0/96").contains(&ip("::ffff:10.0.0.1")));
+        assert!(!cidr("::ffff:0:0/96").contains(&ip("10.0.0.1")));
+        assert!(!cidr("::/0").contains(&ip("10.0.0.1")));
+        // the bits of a plain IPv6 address do not make it an IPv4 one
+        assert!(!net.contains(&ip("::a00:1")));
+
+        assert!(!net.contains_cidr(&cidr("::ffff:10.0.0.0/120")));
+        assert!(!cidr("::/0").overlaps(&net));
+    }
+
+    #[test]
+    fn the_report() {
+        let (address, net) = Cidr::parse_host("192.0.2.130/25").expect("valid");
+        assert_eq!(
+            report(&address, &net),
+            "\
+Address:    192.0.2.130
+Network:    192.0.2.128/25
+Netmask:    255.255.255.128 = 25
+Wildcard:   0.0.0.127
+Broadcast:  192.0.2.255
+First host: 192.0.2.129
+Last host:  192.0.2.254
+Addresses:  128
+Hosts:      126
+Category:   documentation
+"
+        );
+
+        let (address, net) = Cidr::parse_host("::/0").expect("valid");
+        let report = report(&address, &net);
+        assert!(report.contains("Last:       ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff\n"));
+        assert!(report.contains("Addresses:  2^128\n"));
+        assert!(report.contains("Hosts:      2^128\n"));
+    }
+}
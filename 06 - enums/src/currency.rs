@@ -0,0 +1,765 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// `value_in_cents` knows four coins. A `Currency` is any set of coins,
+// with their values in the smallest unit (cents for the dollar and the
+// euro), and can work out change with them.
+//
+// other systems are read from a file like
+//
+//     # pre-decimal British coins, in pence
+//     name     sterling
+//     symbol   d
+//     decimals 0
+//     coins    1 3 6 12 24 30
+//
+// the usual way of giving change is greedy: take the largest coin that
+// fits, and repeat. For the dollar and the euro that always gives the
+// fewest coins, but not for every system. With coins of 1, 3 and 4,
+// greedy gives 6 as 4 + 1 + 1 where 3 + 3 would do. A system where greedy
+// always gives the fewest coins is called canonical
+
+// the largest amount `min_coins`, `ways` and `counterexample` work out
+// change for. They keep a table entry for every amount up to it
+pub const MAX_AMOUNT: u64 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Currency {
+    name: String,
+    symbol: String,
+    // digits after the decimal point, 2 for cents
+    decimals: u32,
+    // largest first, without repeats
+    coins: Vec<u64>,
+}
+
+// some coins, as (value, how many) with the largest value first
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Change {
+    pub coins: Vec<(u64, u64)>,
+}
+
+impl Change {
+    // the number of coins
+    pub fn count(&self) -> u64 {
+        self.coins.iter().map(|(_, n)| n).sum()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.coins.iter().map(|(value, n)| value * n).sum()
+    }
+}
+
+// the greedy change and the fewest coins for the same amount. Either is
+// `None` when it cannot be made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub amount: u64,
+    pub greedy: Option<Change>,
+    pub optimal: Option<Change>,
+}
+
+impl Comparison {
+    pub fn greedy_is_optimal(&self) -> bool {
+        self.greedy.as_ref().map(Change::count) == self.optimal.as_ref().map(Change::count)
+    }
+}
+
+#[derive(Debug)]
+pub enum CurrencyError {
+    Io(io::Error),
+    UnknownKey { line: usize, key: String },
+    MissingValue { line: usize, key: String },
+    InvalidNumber { line: usize, text: String },
+    NoCoins,
+    ZeroCoin,
+    // more decimals than a u64 can hold
+    TooManyDecimals(u32),
+}
+
+impl fmt::Display for CurrencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CurrencyError::Io(e) => write!(f, "{}", e),
+            CurrencyError::UnknownKey { line, key } => write!(
+                f,
+                "line {}: expected `name`, `symbol`, `decimals` or `coins`, found `{}`",
+                line, key
+            ),
+            CurrencyError::MissingValue { line, key } => {
+                write!(f, "line {}: missing value for `{}`", line, key)
+            }
+            CurrencyError::InvalidNumber { line, text } => {
+                write!(f, "line {}: invalid number `{}`", line, text)
+            }
+            CurrencyError::NoCoins => write!(f, "a currency needs at least one coin"),
+            CurrencyError::ZeroCoin => write!(f, "a coin cannot be worth 0"),
+            CurrencyError::TooManyDecimals(d) => write!(f, "{} decimals is too many", d),
+        }
+    }
+}
+
+impl Error for CurrencyError {}
+
+impl From<io::Error> for CurrencyError {
+    fn from(e: io::Error) -> CurrencyError {
+        CurrencyError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseAmountError {
+    Empty,
+    InvalidNumber(String),
+    // more digits after the point than the currency has
+    TooManyDecimals { found: usize, max: u32 },
+    Overflow,
+}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseAmountError::Empty => write!(f, "empty amount"),
+            ParseAmountError::InvalidNumber(s) => write!(f, "invalid amount `{}`", s),
+            ParseAmountError::TooManyDecimals { found, max } => write!(
+                f,
+                "{} digits after the point, the currency has {}",
+                found, max
+            ),
+            ParseAmountError::Overflow => write!(f, "amount is too large"),
+        }
+    }
+}
+
+impl Error for ParseAmountError {}
+
+// an amount over `MAX_AMOUNT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooLarge(pub u64);
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "amount {} is more than {}", self.0, MAX_AMOUNT)
+    }
+}
+
+impl Error for TooLarge {}
+
+// why `counterexample` could not tell whether a system is canonical
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanonicalError {
+    TooLarge(TooLarge),
+    // the smallest coin does not divide the others, so there is no known
+    // bound on the amounts to try, and none of those tried went wrong
+    NoBound,
+}
+
+impl fmt::Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanonicalError::TooLarge(e) => write!(f, "{}", e),
+            CanonicalError::NoBound => write!(
+                f,
+                "the smallest coin does not divide the others, so not every amount can be tried"
+            ),
+        }
+    }
+}
+
+impl Error for CanonicalError {}
+
+impl From<TooLarge> for CanonicalError {
+    fn from(e: TooLarge) -> CanonicalError {
+        CanonicalError::TooLarge(e)
+    }
+}
+
+impl Currency {
+    pub fn new(
+        name: &str,
+        symbol: &str,
+        decimals: u32,
+        coins: &[u64],
+    ) -> Result<Currency, CurrencyError> {
+        if coins.is_empty() {
+            return Err(CurrencyError::NoCoins);
+        }
+        if coins.contains(&0) {
+            return Err(CurrencyError::ZeroCoin);
+        }
+        if 10u64.checked_pow(decimals).is_none() {
+            return Err(CurrencyError::TooManyDecimals(decimals));
+        }
+        let mut coins = coins.to_vec();
+        coins.sort_unstable_by(|a, b| b.cmp(a));
+        coins.dedup();
+        Ok(Currency {
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            decimals,
+            coins,
+        })
+    }
+
+    // the coins in circulation, with the half dollar and the dollar coin
+    // though they are rarely seen
+    pub fn usd() -> Currency {
+        Currency::new("US dollar", "$", 2, &[1, 5, 10, 25, 50, 100]).expect("valid coins")
+    }
+
+    pub fn eur() -> Currency {
+        Currency::new("euro", "€", 2, &[1, 2, 5, 10, 20, 50, 100, 200]).expect("valid coins")
+    }
+
+    // a currency in the file format above. `name` and `symbol` may be
+    // left out, `decimals` defaults to 2
+    pub fn parse(text: &str) -> Result<Currency, CurrencyError> {
+        let (mut name, mut symbol, mut decimals) = ("", "", 2);
+        let mut coins = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let content = line.split('#').next().unwrap_or("");
+            let mut words = content.split_whitespace();
+            let Some(key) = words.next() else {
+                continue;
+            };
+            let rest: Vec<&str> = words.collect();
+            if rest.is_empty() {
+                return Err(CurrencyError::MissingValue {
+                    line: line_number,
+                    key: key.to_string(),
+                });
+            }
+            let number = |text: &str| {
+                text.parse::<u64>()
+                    .map_err(|_| CurrencyError::InvalidNumber {
+                        line: line_number,
+                        text: text.to_string(),
+                    })
+            };
+            match key {
+                "name" => name = content.trim_start()[key.len()..].trim(),
+                "symbol" => symbol = rest[0],
+                // anything too large is refused by `new`
+                "decimals" => decimals = number(rest[0])?.min(u32::MAX as u64) as u32,
+                "coins" => {
+                    for word in rest {
+                        coins.push(number(word)?);
+                    }
+                }
+                other => {
+                    return Err(CurrencyError::UnknownKey {
+                        line: line_number,
+                        key: other.to_string(),
+                    })
+                }
+            }
+        }
+        Currency::new(name, symbol, decimals, &coins)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Currency, CurrencyError> {
+        Currency::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn coins(&self) -> &[u64] {
+        &self.coins
+    }
+
+    // an amount like `$1.37`, `1.37` or `1,37 €` as a number of the
+    // smallest unit, here 137. The symbol can come before or after the
+    // number, and the point can be a comma. `2` is two whole units
+    pub fn parse_amount(&self, s: &str) -> Result<u64, ParseAmountError> {
+        let mut number = s.trim();
+        if !self.symbol.is_empty() {
+            number = number.strip_prefix(self.symbol.as_str()).unwrap_or(number);
+            number = number.strip_suffix(self.symbol.as_str()).unwrap_or(number);
+        }
+        let number = number.trim();
+        if number.is_empty() {
+            return Err(ParseAmountError::Empty);
+        }
+        let invalid = || ParseAmountError::InvalidNumber(s.trim().to_string());
+
+        let (whole, fraction) = match number.split_once(['.', ',']) {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (number, ""),
+        };
+        let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !digits(whole) || !digits(fraction) {
+            return Err(invalid());
+        }
+        if fraction.len() > self.decimals as usize {
+            return Err(ParseAmountError::TooManyDecimals {
+                found: fraction.len(),
+                max: self.decimals,
+            });
+        }
+
+        // `1.5` is 1 and 50 hundredths, so the fraction is padded to
+        // the currency's number of decimals
+        let unit = 10u64.pow(self.decimals);
+        let whole: u64 = match whole {
+            "" => 0,
+            w => w.parse().map_err(|_| ParseAmountError::Overflow)?,
+        };
+        let fraction: u64 = match fraction {
+            "" => 0,
+            f => {
+                f.parse::<u64>().map_err(|_| invalid())? * 10u64.pow(self.decimals - f.len() as u32)
+            }
+        };
+        whole
+            .checked_mul(unit)
+            .and_then(|w| w.checked_add(fraction))
+            .ok_or(ParseAmountError::Overflow)
+    }
+
+    // `137` as `$1.37`
+    pub fn format_amount(&self, amount: u64) -> String {
+        let unit = 10u64.pow(self.decimals);
+        match self.decimals {
+            0 => format!("{}{}", self.symbol, amount),
+            d => format!(
+                "{}{}.{:0width$}",
+                self.symbol,
+                amount / unit,
+                amount % unit,
+                width = d as usize
+            ),
+        }
+    }
+
+    // like `2 × $0.25 + 1 × $0.10`
+    pub fn format_change(&self, change: &Change) -> String {
+        let parts: Vec<String> = change
+            .coins
+            .iter()
+            .map(|&(value, n)| format!("{} × {}", n, self.format_amount(value)))
+            .collect();
+        match parts.is_empty() {
+            true => String::from("no coins"),
+            false => parts.join(" + "),
+        }
+    }
+
+    // the largest coin that fits, again and again. `None` when what is
+    // left is smaller than every coin
+    pub fn greedy(&self, amount: u64) -> Option<Change> {
+        let mut left = amount;
+        let mut change = Change::default();
+        for &coin in &self.coins {
+            if left >= coin {
+                change.coins.push((coin, left / coin));
+                left %= coin;
+            }
+        }
+        (left == 0).then_some(change)
+    }
+
+    // the change with the fewest coins, or `None` when there is none
+    pub fn min_coins(&self, amount: u64) -> Result<Option<Change>, TooLarge> {
+        let table = self.fewest(amount)?;
+        Ok(table.change(amount, &self.coins))
+    }
+
+    // the number of different ways to make `amount`, where only how many
+    // of each coin counts and not their order. `None` if it does not fit
+    // in a u128
+    pub fn ways(&self, amount: u64) -> Result<Option<u128>, TooLarge> {
+        let amount = checked(amount)?;
+        // ways[a] counts the ways to make `a` with the coins seen so far;
+        // adding a coin lets every amount use it on top of a smaller one
+        let mut ways: Vec<Option<u128>> = vec![Some(0); amount + 1];
+        ways[0] = Some(1);
+        for &coin in &self.coins {
+            let coin = coin as usize;
+            for a in coin..=amount {
+                ways[a] = match (ways[a], ways[a - coin]) {
+                    (Some(w), Some(v)) => w.checked_add(v),
+                    _ => None,
+                };
+            }
+        }
+        Ok(ways[amount])
+    }
+
+    pub fn compare(&self, amount: u64) -> Result<Comparison, TooLarge> {
+        Ok(Comparison {
+            amount,
+            greedy: self.greedy(amount),
+            optimal: self.min_coins(amount)?,
+        })
+    }
+
+    // the smallest amount where greedy change is not the fewest coins, or
+    // `None` for a canonical system.
+    //
+    // Kozen and Zaks showed that when there is one, with a coin of 1, it
+    // is below the sum of the two largest coins, so only those amounts
+    // need to be tried. The same holds when the smallest coin divides the
+    // others, since that is a system with a coin of 1 counted in larger
+    // units. For other systems the same amounts are tried, and finding
+    // nothing proves nothing: that is `NoBound`.
+    //
+    // the table covers every amount tried, so for coins whose two largest
+    // add up to more than `MAX_AMOUNT + 1` this is `TooLarge`, even if
+    // the system is canonical
+    pub fn counterexample(&self) -> Result<Option<u64>, CanonicalError> {
+        let bound = match self.coins[..] {
+            [largest, second, ..] => largest.saturating_add(second),
+            // with one coin, greedy can only do what is possible
+            _ => return Ok(None),
+        };
+        let table = self.fewest(bound - 1)?;
+        let found = (1..bound).find(|&amount| {
+            let greedy = self.greedy(amount).map(|c| c.count());
+            let optimal = table.count(amount);
+            greedy != optimal
+        });
+        let smallest = self.coins[self.coins.len() - 1];
+        match found {
+            None if self.coins.iter().any(|coin| coin % smallest != 0) => {
+                Err(CanonicalError::NoBound)
+            }
+            found => Ok(found),
+        }
+    }
+
+    pub fn is_canonical(&self) -> Result<bool, CanonicalError> {
+        Ok(self.counterexample()?.is_none())
+    }
+
+    // the fewest coins for every amount up to `amount`, built up from 0:
+    // the best for `a` is one coin more than the best for `a - coin`, for
+    // whichever coin gives the fewest
+    fn fewest(&self, amount: u64) -> Result<Table, TooLarge> {
+        let amount = checked(amount)?;
+        let mut table = Table {
+            count: vec![u32::MAX; amount + 1],
+            last: vec![0; amount + 1],
+        };
+        table.count[0] = 0;
+        for a in 1..=amount {
+            for &coin in &self.coins {
+                let coin = coin as usize;
+                if coin <= a && table.count[a - coin] != u32::MAX {
+                    let count = table.count[a - coin] + 1;
+                    if count < table.count[a] {
+                        table.count[a] = count;
+                        table.last[a] = coin as u64;
+                    }
+                }
+            }
+        }
+        Ok(table)
+    }
+}
+
+fn checked(amount: u64) -> Result<usize, TooLarge> {
+    match amount {
+        a if a > MAX_AMOUNT => Err(TooLarge(a)),
+        a => Ok(a as usize),
+    }
+}
+
+// for each amount, the fewest coins that make it, or `u32::MAX`, and the
+// last coin taken to get there
+struct Table {
+    count: Vec<u32>,
+    last: Vec<u64>,
+}
+
+impl Table {
+    fn count(&self, amount: u64) -> Option<u64> {
+        match self.count[amount as usize] {
+            u32::MAX => None,
+            n => Some(n as u64),
+        }
+    }
+
+    // follows the last coins back down to 0
+    fn change(&self, amount: u64, coins: &[u64]) -> Option<Change> {
+        self.count(amount)?;
+        let mut counts = vec![0; coins.len()];
+        let mut left = amount;
+        while left > 0 {
+            let coin = self.last[left as usize];
+            let i = coins.iter().position(|&c| c == coin)?;
+            counts[i] += 1;
+            left -= coin;
+        }
+        Some(Change {
+            coins: coins
+                .iter()
+                .zip(counts)
+                .filter(|&(_, n)| n > 0)
+                .map(|(&coin, n)| (coin, n))
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let coins: Vec<String> = self
+            .coins
+            .iter()
+            .rev()
+            .map(|&c| self.format_amount(c))
+            .collect();
+        match self.name.as_str() {
+            "" => write!(f, "{}", coins.join(", ")),
+            name => write!(f, "{}: {}", name, coins.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn amounts_with_a_part_missing() {
+        let usd = Currency::usd();
+        assert_eq!(usd.parse_amount(".5"), Ok(50));
+        assert_eq!(usd.parse_amount("5."), Ok(500));
+        assert_eq!(usd.parse_amount("$.05"), Ok(5));
+        assert_eq!(usd.parse_amount("2"), Ok(200));
+        assert_eq!(
+            usd.parse_amount("."),
+            Err(ParseAmountError::InvalidNumber(".".to_string()))
+        );
+        assert_eq!(usd.parse_amount(" $ "), Err(ParseAmountError::Empty));
+    }
+
+    #[test]
+    fn symbols_and_commas() {
+        let eur = Currency::eur();
+        assert_eq!(eur.parse_amount("1,37 €"), Ok(137));
+        assert_eq!(eur.parse_amount("€1.37"), Ok(137));
+        assert_eq!(
+            eur.parse_amount("1.000,00"),
+            Err(ParseAmountError::InvalidNumber("1.000,00".to_string()))
+        );
+        assert_eq!(
+            eur.parse_amount("1,3 $"),
+            Err(ParseAmountError::InvalidNumber("1,3 $".to_string()))
+        );
+    }
+
+    #[test]
+    fn too_many_decimals_and_too_large() {
+        let usd = Currency::usd();
+        assert_eq!(
+            usd.parse_amount("1.234"),
+            Err(ParseAmountError::TooManyDecimals { found: 3, max: 2 })
+        );
+        let sterling = Currency::new("sterling", "d", 0, &[1, 3, 6, 12]).expect("valid coins");
+        assert_eq!(
+            sterling.parse_amount("1.5"),
+            Err(ParseAmountError::TooManyDecimals { found: 1, max: 0 })
+        );
+        assert_eq!(
+            usd.parse_amount("184467440737095516.16"),
+            Err(ParseAmountError::Overflow)
+        );
+        assert_eq!(usd.parse_amount("184467440737095516.15"), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn formatted_amounts_parse_back() {
+        let usd = Currency::usd();
+        for amount in [0, 5, 50, 137, 100_000] {
+            assert_eq!(usd.parse_amount(&usd.format_amount(amount)), Ok(amount));
+        }
+    }
+
+    #[test]
+    fn canonical_systems() {
+        assert_eq!(Currency::usd().counterexample(), Ok(None));
+        assert_eq!(Currency::eur().counterexample(), Ok(None));
+        let odd = Currency::new("odd", "", 0, &[1, 3, 4]).expect("valid coins");
+        assert_eq!(odd.counterexample(), Ok(Some(6)));
+
+        // the table only has to reach one below the sum of the two largest
+        let edge = Currency::new("edge", "", 0, &[1, MAX_AMOUNT / 2 + 1, MAX_AMOUNT / 2])
+            .expect("valid coins");
+        assert!(edge.counterexample().is_ok());
+        let big = Currency::new("big", "", 0, &[1, MAX_AMOUNT, 2]).expect("valid coins");
+        assert_eq!(
+            big.counterexample(),
+            Err(CanonicalError::TooLarge(TooLarge(MAX_AMOUNT + 1)))
+        );
+    }
+
+    #[test]
+    fn systems_without_a_coin_of_1() {
+        // 1, 3 and 4 counted in pairs goes wrong at 12
+        let even = Currency::new("even", "", 0, &[2, 6, 8]).expect("valid coins");
+        assert_eq!(even.counterexample(), Ok(Some(12)));
+        let tens = Currency::new("tens", "", 0, &[10, 50, 100]).expect("valid coins");
+        assert_eq!(tens.is_canonical(), Ok(true));
+
+        // greedy takes 5 for 6 and is left with 1, where 3 + 3 would do.
+        // What is found is a counterexample whatever the coins
+        let gaps = Currency::new("gaps", "", 0, &[3, 5]).expect("valid coins");
+        assert_eq!(gaps.counterexample(), Ok(Some(6)));
+        let pairs = Currency::new("pairs", "", 0, &[2, 3]).expect("valid coins");
+        assert_eq!(pairs.is_canonical(), Ok(false));
+        let one = Currency::new("one", "", 0, &[7]).expect("valid coins");
+        assert_eq!(one.is_canonical(), Ok(true));
+    }
+
+    #[test]
+    fn fewest_coins() {
+        let usd = Currency::usd();
+        let change = usd.min_coins(137).expect("small enough").expect("possible");
+        assert_eq!(change.coins, vec![(100, 1), (25, 1), (10, 1), (1, 2)]);
+        assert_eq!((change.count(), change.total()), (5, 137));
+        assert_eq!(
+            usd.format_change(&change),
+            "1 × $1.00 + 1 × $0.25 + 1 × $0.10 + 2 × $0.01"
+        );
+        assert_eq!(usd.min_coins(0), Ok(Some(Change::default())));
+        assert_eq!(usd.format_change(&Change::default()), "no coins");
+        assert_eq!(usd.min_coins(MAX_AMOUNT + 1), Err(TooLarge(MAX_AMOUNT + 1)));
+
+        let odd = Currency::new("odd", "", 0, &[1, 3, 4]).expect("valid coins");
+        assert_eq!(
+            odd.min_coins(6)
+                .expect("small enough")
+                .expect("possible")
+                .coins,
+            vec![(3, 2)]
+        );
+        let gaps = Currency::new("gaps", "", 0, &[3, 5]).expect("valid coins");
+        assert_eq!(gaps.min_coins(7), Ok(None));
+        assert_eq!(
+            gaps.format_change(&gaps.min_coins(11).unwrap().unwrap()),
+            "1 × 5 + 2 × 3"
+        );
+    }
+
+    #[test]
+    fn greedy_change() {
+        let usd = Currency::usd();
+        let change = usd.greedy(137).expect("possible");
+        assert_eq!(change.coins, vec![(100, 1), (25, 1), (10, 1), (1, 2)]);
+        assert_eq!(usd.greedy(0), Some(Change::default()));
+
+        let gaps = Currency::new("gaps", "", 0, &[3, 5]).expect("valid coins");
+        assert_eq!(gaps.greedy(6), None);
+        assert_eq!(gaps.greedy(13).map(|c| c.coins), Some(vec![(5, 2), (3, 1)]));
+    }
+
+    #[test]
+    fn counting_ways() {
+        let usd = Currency::usd();
+        assert_eq!(usd.ways(0), Ok(Some(1)));
+        assert_eq!(usd.ways(4), Ok(Some(1)));
+        assert_eq!(usd.ways(10), Ok(Some(4)));
+        assert_eq!(usd.ways(100), Ok(Some(293)));
+        assert_eq!(usd.ways(MAX_AMOUNT + 1), Err(TooLarge(MAX_AMOUNT + 1)));
+
+        let gaps = Currency::new("gaps", "", 0, &[3, 5]).expect("valid coins");
+        assert_eq!(gaps.ways(7), Ok(Some(0)));
+        assert_eq!(gaps.ways(15), Ok(Some(2)));
+
+        // more ways than a u128 holds
+        let one =
+            Currency::new("one", "", 0, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).expect("valid coins");
+        assert_eq!(one.ways(MAX_AMOUNT), Ok(None));
+    }
+
+    #[test]
+    fn greedy_against_the_fewest() {
+        let odd = Currency::new("odd", "", 0, &[1, 3, 4]).expect("valid coins");
+        let comparison = odd.compare(6).expect("small enough");
+        assert_eq!(comparison.amount, 6);
+        assert_eq!(comparison.greedy.as_ref().map(Change::count), Some(3));
+        assert_eq!(comparison.optimal.as_ref().map(Change::count), Some(2));
+        assert!(!comparison.greedy_is_optimal());
+        assert!(odd.compare(7).expect("small enough").greedy_is_optimal());
+
+        let gaps = Currency::new("gaps", "", 0, &[3, 5]).expect("valid coins");
+        assert!(!gaps.compare(6).expect("small enough").greedy_is_optimal());
+        // neither can make 7, which counts as the same
+        assert!(gaps.compare(7).expect("small enough").greedy_is_optimal());
+    }
+
+    #[test]
+    fn currency_files() {
+        let sterling = Currency::parse(
+            "# pre-decimal British coins, in pence\n\
+             name     sterling pounds  # the name is the rest of the line\n\
+             symbol   d\n\
+             \n\
+             decimals 0\n\
+             coins    30 1 3 6 12 24 6\n",
+        )
+        .expect("a valid file");
+        assert_eq!(sterling.name(), "sterling pounds");
+        assert_eq!(sterling.coins(), &[30, 24, 12, 6, 3, 1]);
+        assert_eq!(sterling.format_amount(48), "d48");
+        assert_eq!(sterling.counterexample(), Ok(Some(48)));
+
+        let parsed = Currency::parse("coins 1 5 10 25 50 100").expect("a valid file");
+        assert_eq!(parsed.format_amount(137), "1.37");
+        assert_eq!(parsed.to_string(), "0.01, 0.05, 0.10, 0.25, 0.50, 1.00");
+    }
+
+    #[test]
+    fn bad_currency_files() {
+        let error = |text: &str| Currency::parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("coins 1 2\nvalue 3"),
+            "line 2: expected `name`, `symbol`, `decimals` or `coins`, found `value`"
+        );
+        assert_eq!(
+            error("# none yet\ncoins"),
+            "line 2: missing value for `coins`"
+        );
+        assert_eq!(error("coins 1 two"), "line 1: invalid number `two`");
+        assert_eq!(error("coins -1"), "line 1: invalid number `-1`");
+        assert!(matches!(
+            Currency::parse("name nothing"),
+            Err(CurrencyError::NoCoins)
+        ));
+        assert!(matches!(
+            Currency::parse("coins 0 1"),
+            Err(CurrencyError::ZeroCoin)
+        ));
+        assert!(matches!(
+            Currency::parse("decimals 20\ncoins 1"),
+            Err(CurrencyError::TooManyDecimals(20))
+        ));
+        assert!(matches!(
+            Currency::parse("decimals 99999999999\ncoins 1"),
+            Err(CurrencyError::TooManyDecimals(u32::MAX))
+        ));
+    }
+
+    #[test]
+    fn loading_currency_files() {
+        let path = env::temp_dir().join(format!("enums-currency-{}", process::id()));
+        fs::write(&path, "name odd\ncoins 1 3 4\n").expect("writes");
+        let loaded = Currency::load(&path);
+        fs::remove_file(&path).expect("removes");
+        assert_eq!(loaded.expect("a valid file").coins(), &[4, 3, 1]);
+
+        match Currency::load(&path) {
+            Err(CurrencyError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("expected a missing file, got {:?}", other),
+        }
+    }
+}
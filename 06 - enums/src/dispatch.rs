@@ -0,0 +1,436 @@
+use crate::message::{Kind, Message, ParseMessageError};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+// sends each message to the handler registered for its variant. Handlers
+// all return the same type `R`, which comes back from `dispatch`.
+//
+// middleware sees every message on the way in, and can drop it, and every
+// outcome on the way out. After a `Quit` gets through, the dispatcher is
+// stopped: the queue is cleared and later messages are not handled
+pub struct Dispatcher<R> {
+    handlers: HashMap<Kind, Handler<R>>,
+    middleware: Vec<Box<dyn Middleware<R>>>,
+    queue: VecDeque<Message>,
+    stopped: bool,
+}
+
+type Handler<R> = Box<dyn FnMut(&Message) -> R>;
+
+// what happened to a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome<R> {
+    Handled(R),
+    // no handler is registered for the variant
+    Unhandled,
+    // a middleware dropped it
+    Filtered,
+    // it came after a `Quit`
+    Stopped,
+}
+
+// both methods do nothing by default, so a middleware only implements the
+// one it needs
+pub trait Middleware<R> {
+    // `false` drops the message, and the middleware after this one and
+    // the handler never see it
+    fn before(&mut self, _message: &Message) -> bool {
+        true
+    }
+
+    // called for every message that got to `before`, in reverse order
+    fn after(&mut self, _message: &Message, _outcome: &Outcome<R>) {}
+}
+
+impl<R> Dispatcher<R> {
+    pub fn new() -> Dispatcher<R> {
+        Dispatcher {
+            handlers: HashMap::new(),
+            middleware: Vec::new(),
+            queue: VecDeque::new(),
+            stopped: false,
+        }
+    }
+
+    // the handler for a kind of message, replacing any earlier one. The
+    // `on_` methods below hand the handler the variant's data instead
+    pub fn on<F>(&mut self, kind: Kind, handler: F) -> &mut Dispatcher<R>
+    where
+        F: FnMut(&Message) -> R + 'static,
+    {
+        self.handlers.insert(kind, Box::new(handler));
+        self
+    }
+
+    pub fn on_quit<F>(&mut self, mut handler: F) -> &mut Dispatcher<R>
+    where
+        F: FnMut() -> R + 'static,
+    {
+        self.on(Kind::Quit, move |_| handler())
+    }
+
+    pub fn on_move<F>(&mut self, mut handler: F) -> &mut Dispatcher<R>
+    where
+        F: FnMut(i32, i32) -> R + 'static,
+    {
+        self.on(Kind::Move, move |message| match message {
+            Message::Move { x, y } => handler(*x, *y),
+            _ => unreachable!("handlers only get their own kind"),
+        })
+    }
+
+    pub fn on_write<F>(&mut self, mut handler: F) -> &mut Dispatcher<R>
+    where
+        F: FnMut(&str) -> R + 'static,
+    {
+        self.on(Kind::Write, move |message| match message {
+            Message::Write(text) => handler(text),
+            _ => unreachable!("handlers only get their own kind"),
+        })
+    }
+
+    pub fn on_change_color<F>(&mut self, mut handler: F) -> &mut Dispatcher<R>
+    where
+        F: FnMut(i32, i32, i32) -> R + 'static,
+    {
+        self.on(Kind::ChangeColor, move |message| match message {
+            Message::ChangeColor(r, g, b) => handler(*r, *g, *b),
+            _ => unreachable!("handlers only get their own kind"),
+        })
+    }
+
+    // middleware runs in the order it is added
+    pub fn with<M>(&mut self, middleware: M) -> &mut Dispatcher<R>
+    where
+        M: Middleware<R> + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn dispatch(&mut self, message: &Message) -> Outcome<R> {
+        if self.stopped {
+            return Outcome::Stopped;
+        }
+
+        // the middleware that let the message through, which are the ones
+        // told about the outcome
+        let mut passed = 0;
+        let mut outcome = None;
+        for m in self.middleware.iter_mut() {
+            passed += 1;
+            if !m.before(message) {
+                outcome = Some(Outcome::Filtered);
+                break;
+            }
+        }
+        let outcome = outcome.unwrap_or_else(|| {
+            if let Message::Quit = message {
+                self.stopped = true;
+                self.queue.clear();
+            }
+            match self.handlers.get_mut(&message.kind()) {
+                Some(handler) => Outcome::Handled(handler(message)),
+                None => Outcome::Unhandled,
+            }
+        });
+
+        for m in self.middleware[..passed].iter_mut().rev() {
+            m.after(message, &outcome);
+        }
+        outcome
+    }
+
+    // adds a message to the end of the queue, for `run`
+    pub fn post(&mut self, message: Message) {
+        if !self.stopped {
+            self.queue.push_back(message);
+        }
+    }
+
+    // dispatches the queued messages in order until the queue is empty or
+    // a `Quit` stops it
+    pub fn run(&mut self) -> Vec<Outcome<R>> {
+        let mut outcomes = Vec::new();
+        while let Some(message) = self.queue.pop_front() {
+            outcomes.push(self.dispatch(&message));
+        }
+        outcomes
+    }
+
+    // feeds the queue one line at a time, in the text form of `Message`,
+    // until the input ends or a `Quit` is handled. Nothing after the
+    // `Quit` is read. Blank lines and lines starting with `#` are skipped
+    pub fn run_lines<B: BufRead>(&mut self, input: B) -> Result<Vec<Outcome<R>>, RunError> {
+        let mut outcomes = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let message = line
+                .parse()
+                .map_err(|error| RunError::Parse { line: i + 1, error })?;
+            self.post(message);
+            outcomes.extend(self.run());
+            if self.stopped {
+                break;
+            }
+        }
+        Ok(outcomes)
+    }
+}
+
+impl<R> Default for Dispatcher<R> {
+    fn default() -> Dispatcher<R> {
+        Dispatcher::new()
+    }
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        error: ParseMessageError,
+    },
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Io(e) => write!(f, "{}", e),
+            RunError::Parse { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+impl From<io::Error> for RunError {
+    fn from(e: io::Error) -> RunError {
+        RunError::Io(e)
+    }
+}
+
+// writes each message and its outcome to `out`, a line for each
+pub struct Logger<W> {
+    out: W,
+}
+
+impl<W: Write> Logger<W> {
+    pub fn new(out: W) -> Logger<W> {
+        Logger { out }
+    }
+}
+
+impl<R: fmt::Debug, W: Write> Middleware<R> for Logger<W> {
+    // a log that cannot be written is not a reason to stop handling
+    // messages, so errors are ignored
+    fn after(&mut self, message: &Message, outcome: &Outcome<R>) {
+        let _ = writeln!(self.out, "[{}] {:?}", message, outcome);
+    }
+}
+
+// lets through only the messages `keep` returns `true` for
+pub struct Filter<F> {
+    keep: F,
+}
+
+impl<F: FnMut(&Message) -> bool> Filter<F> {
+    pub fn new(keep: F) -> Filter<F> {
+        Filter { keep }
+    }
+}
+
+impl<R, F: FnMut(&Message) -> bool> Middleware<R> for Filter<F> {
+    fn before(&mut self, message: &Message) -> bool {
+        (self.keep)(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // every handler registered, each saying which one it was
+    fn dispatcher() -> Dispatcher<String> {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher
+            .on_quit(|| "quit".to_string())
+            .on_move(|x, y| format!("move {} {}", x, y))
+            .on_write(|text| format!("write {}", text))
+            .on_change_color(|r, g, b| format!("color {} {} {}", r, g, b));
+        dispatcher
+    }
+
+    // notes what each call sees in a shared log, and drops the messages
+    // `drop` says to
+    struct Record {
+        name: &'static str,
+        log: Rc<RefCell<Vec<String>>>,
+        drop: fn(&Message) -> bool,
+    }
+
+    impl Middleware<String> for Record {
+        fn before(&mut self, message: &Message) -> bool {
+            self.log
+                .borrow_mut()
+                .push(format!("{} before {}", self.name, message));
+            !(self.drop)(message)
+        }
+
+        fn after(&mut self, message: &Message, outcome: &Outcome<String>) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} after {} {:?}", self.name, message, outcome));
+        }
+    }
+
+    #[test]
+    fn each_kind_goes_to_its_handler() {
+        let mut dispatcher = dispatcher();
+        let cases = [
+            (Message::Move { x: 1, y: -2 }, "move 1 -2"),
+            (Message::Write("hi there".to_string()), "write hi there"),
+            (Message::ChangeColor(1, 2, 3), "color 1 2 3"),
+            (Message::Quit, "quit"),
+        ];
+        for (message, expected) in cases {
+            assert_eq!(
+                message.call(&mut dispatcher),
+                Outcome::Handled(expected.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn messages_without_a_handler_are_unhandled() {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.on_move(|x, y| x + y);
+        assert_eq!(
+            dispatcher.dispatch(&Message::Move { x: 2, y: 3 }),
+            Outcome::Handled(5)
+        );
+        assert_eq!(
+            dispatcher.dispatch(&Message::Write("hi".to_string())),
+            Outcome::Unhandled
+        );
+        // a quit stops the dispatcher even without a handler
+        assert_eq!(dispatcher.dispatch(&Message::Quit), Outcome::Unhandled);
+        assert!(dispatcher.is_stopped());
+    }
+
+    #[test]
+    fn filters_drop_messages() {
+        let mut dispatcher = dispatcher();
+        dispatcher.with(Filter::new(|m: &Message| m.kind() != Kind::Write));
+        assert_eq!(
+            dispatcher.dispatch(&Message::Write("hi".to_string())),
+            Outcome::Filtered
+        );
+        assert_eq!(
+            dispatcher.dispatch(&Message::Move { x: 0, y: 0 }),
+            Outcome::Handled("move 0 0".to_string())
+        );
+    }
+
+    #[test]
+    fn after_runs_in_reverse_for_the_middleware_reached() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut dispatcher = dispatcher();
+        for (name, drop) in [
+            ("a", (|_| false) as fn(&Message) -> bool),
+            ("b", |m| m.kind() == Kind::Write),
+            ("c", |_| false),
+        ] {
+            dispatcher.with(Record {
+                name,
+                log: Rc::clone(&log),
+                drop,
+            });
+        }
+
+        dispatcher.dispatch(&Message::Move { x: 1, y: 2 });
+        assert_eq!(
+            log.take(),
+            [
+                "a before move 1 2",
+                "b before move 1 2",
+                "c before move 1 2",
+                "c after move 1 2 Handled(\"move 1 2\")",
+                "b after move 1 2 Handled(\"move 1 2\")",
+                "a after move 1 2 Handled(\"move 1 2\")",
+            ]
+        );
+
+        // `c` never sees the message `b` drops, so it hears nothing of it
+        dispatcher.dispatch(&Message::Write("hi".to_string()));
+        assert_eq!(
+            log.take(),
+            [
+                "a before write hi",
+                "b before write hi",
+                "b after write hi Filtered",
+                "a after write hi Filtered",
+            ]
+        );
+    }
+
+    #[test]
+    fn quit_clears_the_queue_and_stops() {
+        let mut dispatcher = dispatcher();
+        dispatcher.post(Message::Move { x: 1, y: 1 });
+        dispatcher.post(Message::Quit);
+        dispatcher.post(Message::Move { x: 2, y: 2 });
+        assert_eq!(
+            dispatcher.run(),
+            [
+                Outcome::Handled("move 1 1".to_string()),
+                Outcome::Handled("quit".to_string()),
+            ]
+        );
+        assert!(dispatcher.is_stopped());
+
+        assert_eq!(
+            dispatcher.dispatch(&Message::Move { x: 3, y: 3 }),
+            Outcome::Stopped
+        );
+        dispatcher.post(Message::Quit);
+        assert!(dispatcher.run().is_empty());
+    }
+
+    #[test]
+    fn run_lines_skips_comments_and_stops_at_quit() {
+        let input = "# a comment\n\nmove 1 2\n   # indented\nwrite hi\nquit\nnot read\n";
+        let outcomes = dispatcher().run_lines(input.as_bytes()).expect("runs");
+        assert_eq!(
+            outcomes,
+            [
+                Outcome::Handled("move 1 2".to_string()),
+                Outcome::Handled("write hi".to_string()),
+                Outcome::Handled("quit".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_lines_reports_the_line_that_does_not_parse() {
+        let input = "# a comment\nmove 1 2\n\nmove 1\nquit\n";
+        match dispatcher().run_lines(input.as_bytes()) {
+            Err(RunError::Parse { line, error }) => {
+                assert_eq!(line, 4);
+                assert_eq!(error, ParseMessageError::MissingArgument(Kind::Move));
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
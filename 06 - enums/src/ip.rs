@@ -0,0 +1,540 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::net;
+use std::str::FromStr;
+
+// the chapter's `IpAddr` held the address as a `String`. Here each
+// variant holds the address itself: four octets for IPv4 and eight 16-bit
+// segments for IPv6, which also carries the zone (`fe80::1%eth0`) when
+// there is one. A zone names the network interface a link-local address
+// belongs to, and means nothing outside of that machine.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IpAddr {
+    V4([u8; 4]),
+    V6([u16; 8], Option<String>),
+}
+
+// what an address is for, by the IANA special-purpose registries. An
+// address that is in none of the ranges below is `Global`. IPv4-mapped
+// addresses (`::ffff:127.0.0.1`) are the IPv4 address they hold, and are
+// put in its category
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    Unspecified,
+    Loopback,
+    Private,
+    LinkLocal,
+    Multicast,
+    Documentation,
+    Global,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Category::Unspecified => "unspecified",
+            Category::Loopback => "loopback",
+            Category::Private => "private",
+            Category::LinkLocal => "link-local",
+            Category::Multicast => "multicast",
+            Category::Documentation => "documentation",
+            Category::Global => "global",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseIpError {
+    Empty,
+    // an IPv4 part that is not a number from 0 to 255, or has a leading
+    // zero, which some tools would read as octal
+    InvalidOctet(String),
+    WrongOctetCount(usize),
+    // an IPv6 part that is not one to four hex digits
+    InvalidSegment(String),
+    WrongSegmentCount(usize),
+    MultipleDoubleColons,
+    // an embedded IPv4 address that is not at the end
+    MisplacedIpv4,
+    InvalidZone(String),
+    ZoneOnIpv4,
+}
+
+impl fmt::Display for ParseIpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseIpError::Empty => write!(f, "empty address"),
+            ParseIpError::InvalidOctet(s) => write!(f, "invalid IPv4 octet `{}`", s),
+            ParseIpError::WrongOctetCount(n) => {
+                write!(f, "an IPv4 address has 4 octets, found {}", n)
+            }
+            ParseIpError::InvalidSegment(s) => write!(f, "invalid IPv6 segment `{}`", s),
+            ParseIpError::WrongSegmentCount(n) => {
+                write!(f, "an IPv6 address has 8 segments, found {}", n)
+            }
+            ParseIpError::MultipleDoubleColons => write!(f, "`::` can only appear once"),
+            ParseIpError::MisplacedIpv4 => {
+                write!(f, "an embedded IPv4 address must come last")
+            }
+            ParseIpError::InvalidZone(z) => write!(f, "invalid zone `{}`", z),
+            ParseIpError::ZoneOnIpv4 => write!(f, "only IPv6 addresses have zones"),
+        }
+    }
+}
+
+impl Error for ParseIpError {}
+
+impl IpAddr {
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, IpAddr::V4(_))
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, IpAddr::V6(..))
+    }
+
+    pub fn zone(&self) -> Option<&str> {
+        match self {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(_, zone) => zone.as_deref(),
+        }
+    }
+
+    // the IPv4 address in an IPv4-mapped one, `::ffff:a.b.c.d`
+    pub fn to_ipv4_mapped(&self) -> Option<IpAddr> {
+        match self {
+            IpAddr::V6(s, _) if s[..6] == [0, 0, 0, 0, 0, 0xffff] => {
+                let [a, b] = s[6].to_be_bytes();
+                let [c, d] = s[7].to_be_bytes();
+                Some(IpAddr::V4([a, b, c, d]))
+            }
+            _ => None,
+        }
+    }
+
+    // what the `is_` methods below look at: the mapped IPv4 address if
+    // there is one, and the address itself otherwise
+    fn unmapped(&self) -> Cow<'_, IpAddr> {
+        match self.to_ipv4_mapped() {
+            Some(v4) => Cow::Owned(v4),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    // `0.0.0.0` and `::`
+    pub fn is_unspecified(&self) -> bool {
+        match &*self.unmapped() {
+            IpAddr::V4(o) => *o == [0; 4],
+            IpAddr::V6(s, _) => *s == [0; 8],
+        }
+    }
+
+    // `127.0.0.0/8` and `::1`
+    pub fn is_loopback(&self) -> bool {
+        match &*self.unmapped() {
+            IpAddr::V4(o) => o[0] == 127,
+            IpAddr::V6(s, _) => *s == [0, 0, 0, 0, 0, 0, 0, 1],
+        }
+    }
+
+    // `10.0.0.0/8`, `172.16.0.0/12` and `192.168.0.0/16` (RFC 1918), and
+    // the IPv6 unique local addresses `fc00::/7` (RFC 4193)
+    pub fn is_private(&self) -> bool {
+        match &*self.unmapped() {
+            IpAddr::V4(o) => {
+                o[0] == 10 || (o[0] == 172 && o[1] & 0xf0 == 16) || (o[0] == 192 && o[1] == 168)
+            }
+            IpAddr::V6(s, _) => s[0] & 0xfe00 == 0xfc00,
+        }
+    }
+
+    // `169.254.0.0/16` and `fe80::/10`
+    pub fn is_link_local(&self) -> bool {
+        match &*self.unmapped() {
+            IpAddr::V4(o) => o[0] == 169 && o[1] == 254,
+            IpAddr::V6(s, _) => s[0] & 0xffc0 == 0xfe80,
+        }
+    }
+
+    // `224.0.0.0/4` and `ff00::/8`
+    pub fn is_multicast(&self) -> bool {
+        match &*self.unmapped() {
+            IpAddr::V4(o) => o[0] & 0xf0 == 224,
+            IpAddr::V6(s, _) => s[0] & 0xff00 == 0xff00,
+        }
+    }
+
+    // the ranges set aside for examples: `192.0.2.0/24`, `198.51.100.0/24`
+    // and `203.0.113.0/24` (RFC 5737), `2001:db8::/32` (RFC 3849) and
+    // `3fff::/20` (RFC 9637)
+    pub fn is_documentation(&self) -> bool {
+        match &*self.unmapped() {
+            IpAddr::V4(o) => matches!(
+                (o[0], o[1], o[2]),
+                (192, 0, 2) | (198, 51, 100) | (203, 0, 113)
+            ),
+            IpAddr::V6(s, _) => {
+                (s[0] == 0x2001 && s[1] == 0x0db8) || (s[0] == 0x3fff && s[1] & 0xf000 == 0)
+            }
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        if self.is_unspecified() {
+            Category::Unspecified
+        } else if self.is_loopback() {
+            Category::Loopback
+        } else if self.is_private() {
+            Category::Private
+        } else if self.is_link_local() {
+            Category::LinkLocal
+        } else if self.is_multicast() {
+            Category::Multicast
+        } else if self.is_documentation() {
+            Category::Documentation
+        } else {
+            Category::Global
+        }
+    }
+
+    // the address as one number, IPv4 in the low 32 bits
+    pub fn to_bits(&self) -> u128 {
+        match self {
+            IpAddr::V4(o) => u32::from_be_bytes(*o) as u128,
+            IpAddr::V6(s, _) => s.iter().fold(0, |bits, &s| bits << 16 | s as u128),
+        }
+    }
+}
+
+impl FromStr for IpAddr {
+    type Err = ParseIpError;
+
+    fn from_str(s: &str) -> Result<IpAddr, ParseIpError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseIpError::Empty);
+        }
+
+        let (address, zone) = match s.split_once('%') {
+            Some((address, zone)) => {
+                let valid = !zone.is_empty()
+                    && zone
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+                if !valid {
+                    return Err(ParseIpError::InvalidZone(zone.to_string()));
+                }
+                (address, Some(zone.to_string()))
+            }
+            None => (s, None),
+        };
+
+        if address.contains(':') {
+            Ok(IpAddr::V6(parse_v6(address)?, zone))
+        } else if zone.is_some() {
+            Err(ParseIpError::ZoneOnIpv4)
+        } else {
+            Ok(IpAddr::V4(parse_v4(address)?))
+        }
+    }
+}
+
+fn parse_v4(s: &str) -> Result<[u8; 4], ParseIpError> {
+    let parts: Vec<&str> = s.split('.').collect();
+    if parts.len() != 4 {
+        return Err(ParseIpError::WrongOctetCount(parts.len()));
+    }
+
+    let mut octets = [0; 4];
+    for (octet, part) in octets.iter_mut().zip(&parts) {
+        let invalid = || ParseIpError::InvalidOctet(part.to_string());
+        let digits =
+            !part.is_empty() && part.len() <= 3 && part.bytes().all(|b| b.is_ascii_digit());
+        if !digits || (part.len() > 1 && part.starts_with('0')) {
+            return Err(invalid());
+        }
+        *octet = part.parse().map_err(|_| invalid())?;
+    }
+    Ok(octets)
+}
+
+// the segments on both sides of a `::` are read separately, and the gap
+// between them is filled with zeros. `::` stands for at least one segment
+fn parse_v6(s: &str) -> Result<[u16; 8], ParseIpError> {
+    let (head, tail) = match s.split_once("::") {
+        Some((head, tail)) => {
+            if tail.contains("::") {
+                return Err(ParseIpError::MultipleDoubleColons);
+            }
+            (head, Some(tail))
+        }
+        None => (s, None),
+    };
+
+    // an IPv4 address can only end the whole address, so not a head
+    // that is followed by `::`
+    let head = segments(head, tail.is_none())?;
+    let segments = match tail {
+        None => {
+            if head.len() != 8 {
+                return Err(ParseIpError::WrongSegmentCount(head.len()));
+            }
+            head
+        }
+        Some(tail) => {
+            let tail = segments(tail, true)?;
+            let given = head.len() + tail.len();
+            // counting the `::` as one segment, the least it can be
+            if given > 7 {
+                return Err(ParseIpError::WrongSegmentCount(given + 1));
+            }
+            let mut all = head;
+            all.resize(8 - tail.len(), 0);
+            all.extend(tail);
+            all
+        }
+    };
+
+    let mut out = [0; 8];
+    out.copy_from_slice(&segments);
+    Ok(out)
+}
+
+// the segments of a colon-separated list, which may be empty. When
+// `allow_ipv4` is set the last one may be an IPv4 address, taking up two
+// segments
+fn segments(s: &str, allow_ipv4: bool) -> Result<Vec<u16>, ParseIpError> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let parts: Vec<&str> = s.split(':').collect();
+    let mut segments = Vec::with_capacity(8);
+    for (i, part) in parts.iter().enumerate() {
+        if part.contains('.') {
+            if !allow_ipv4 || i + 1 != parts.len() {
+                return Err(ParseIpError::MisplacedIpv4);
+            }
+            let o = parse_v4(part)?;
+            segments.push(u16::from_be_bytes([o[0], o[1]]));
+            segments.push(u16::from_be_bytes([o[2], o[3]]));
+        } else {
+            let hex =
+                !part.is_empty() && part.len() <= 4 && part.bytes().all(|b| b.is_ascii_hexdigit());
+            if !hex {
+                return Err(ParseIpError::InvalidSegment(part.to_string()));
+            }
+            // four hex digits always fit
+            segments.push(u16::from_str_radix(part, 16).unwrap_or(0));
+        }
+        if segments.len() > 8 {
+            return Err(ParseIpError::WrongSegmentCount(segments.len()));
+        }
+    }
+    Ok(segments)
+}
+
+// RFC 5952: lowercase hex without leading zeros, and the longest run of
+// two or more zero segments (the first, on a tie) written as `::`.
+// IPv4-mapped addresses end in dotted form, as `::ffff:192.0.2.1`
+impl fmt::Display for IpAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (s, zone) = match self {
+            IpAddr::V4(o) => return write!(f, "{}.{}.{}.{}", o[0], o[1], o[2], o[3]),
+            IpAddr::V6(s, zone) => (s, zone),
+        };
+
+        if s[..6] == [0, 0, 0, 0, 0, 0xffff] {
+            let [a, b] = s[6].to_be_bytes();
+            let [c, d] = s[7].to_be_bytes();
+            write!(f, "::ffff:{}.{}.{}.{}", a, b, c, d)?;
+        } else {
+            // the longest run of zeros, as (start, length)
+            let mut best = (0, 0);
+            let mut i = 0;
+            while i < 8 {
+                let start = i;
+                while i < 8 && s[i] == 0 {
+                    i += 1;
+                }
+                if i - start > best.1 {
+                    best = (start, i - start);
+                }
+                i += 1;
+            }
+
+            if best.1 >= 2 {
+                let (start, end) = (best.0, best.0 + best.1);
+                write_segments(f, &s[..start])?;
+                f.write_str("::")?;
+                write_segments(f, &s[end..])?;
+            } else {
+                write_segments(f, s)?;
+            }
+        }
+
+        if let Some(zone) = zone {
+            write!(f, "%{}", zone)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_segments(f: &mut fmt::Formatter, segments: &[u16]) -> fmt::Result {
+    for (i, s) in segments.iter().enumerate() {
+        if i > 0 {
+            f.write_str(":")?;
+        }
+        write!(f, "{:x}", s)?;
+    }
+    Ok(())
+}
+
+impl From<net::IpAddr> for IpAddr {
+    fn from(ip: net::IpAddr) -> IpAddr {
+        match ip {
+            net::IpAddr::V4(v4) => IpAddr::V4(v4.octets()),
+            net::IpAddr::V6(v6) => IpAddr::V6(v6.segments(), None),
+        }
+    }
+}
+
+impl From<net::Ipv4Addr> for IpAddr {
+    fn from(ip: net::Ipv4Addr) -> IpAddr {
+        IpAddr::V4(ip.octets())
+    }
+}
+
+impl From<net::Ipv6Addr> for IpAddr {
+    fn from(ip: net::Ipv6Addr) -> IpAddr {
+        IpAddr::V6(ip.segments(), None)
+    }
+}
+
+// `std::net::IpAddr` has no zone, so an address with one does not convert
+// and is given back in the error
+impl TryFrom<IpAddr> for net::IpAddr {
+    type Error = IpAddr;
+
+    fn try_from(ip: IpAddr) -> Result<net::IpAddr, IpAddr> {
+        match ip {
+            IpAddr::V4(o) => Ok(net::IpAddr::V4(o.into())),
+            IpAddr::V6(s, None) => Ok(net::IpAddr::V6(s.into())),
+            zoned => Err(zoned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn category(s: &str) -> Category {
+        s.parse::<IpAddr>().expect("valid address").category()
+    }
+
+    #[test]
+    fn documentation_ranges() {
+        assert_eq!(category("2001:db8::1"), Category::Documentation);
+        assert_eq!(category("3fff::1"), Category::Documentation);
+        assert_eq!(category("3fff:fff::1"), Category::Documentation);
+        // just past `3fff::/20`, and the rest of `3ff0::/12` around it
+        assert_eq!(category("3fff:1000::1"), Category::Global);
+        assert_eq!(category("3ff0::1"), Category::Global);
+        assert_eq!(category("203.0.113.9"), Category::Documentation);
+    }
+
+    #[test]
+    fn mapped_addresses_are_their_ipv4_address() {
+        assert_eq!(category("::ffff:127.0.0.1"), Category::Loopback);
+        assert_eq!(category("::ffff:10.1.2.3"), Category::Private);
+        assert_eq!(category("::ffff:169.254.0.1"), Category::LinkLocal);
+        assert_eq!(category("::ffff:192.0.2.1"), Category::Documentation);
+        assert_eq!(category("::ffff:8.8.8.8"), Category::Global);
+        assert_eq!(category("::ffff:0.0.0.0"), Category::Unspecified);
+
+        let mapped: IpAddr = "::ffff:c000:201".parse().expect("valid address");
+        assert_eq!(mapped.to_ipv4_mapped(), Some(IpAddr::V4([192, 0, 2, 1])));
+        assert_eq!(mapped.to_string(), "::ffff:192.0.2.1");
+        // only `::ffff:0:0/96` is mapped
+        assert_eq!(category("::127.0.0.1"), Category::Global);
+    }
+
+    #[test]
+    fn display_follows_rfc_5952() {
+        for (input, shown) in [
+            ("2001:0DB8:0:0:1:0:0:1", "2001:db8::1:0:0:1"),
+            ("fe80::1%eth0", "fe80::1%eth0"),
+            ("::", "::"),
+            ("1:0:2:3:4:5:6:7", "1:0:2:3:4:5:6:7"),
+        ] {
+            let ip: IpAddr = input.parse().expect("valid address");
+            assert_eq!(ip.to_string(), shown);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        use ParseIpError::*;
+
+        let cases = [
+            ("", Empty),
+            ("1::2::3", MultipleDoubleColons),
+            ("::1::", MultipleDoubleColons),
+            ("1.2.3.4::", MisplacedIpv4),
+            ("::1.2.3.4:5", MisplacedIpv4),
+            ("1.2.3.4:1::", MisplacedIpv4),
+            ("01.2.3.4", InvalidOctet("01".to_string())),
+            ("1.2.3.00", InvalidOctet("00".to_string())),
+            ("::ffff:1.02.3.4", InvalidOctet("02".to_string())),
+            ("1.2.3.256", InvalidOctet("256".to_string())),
+            ("1.2.+3.4", InvalidOctet("+3".to_string())),
+            ("1.2.3", WrongOctetCount(3)),
+            ("1.2.3.4%eth0", ZoneOnIpv4),
+            ("fe80::1%", InvalidZone(String::new())),
+            ("fe80::1%a b", InvalidZone("a b".to_string())),
+            ("1:2:3:4:5:6:7", WrongSegmentCount(7)),
+            ("1:2:3:4:5:6:7::8", WrongSegmentCount(9)),
+            ("12345::", InvalidSegment("12345".to_string())),
+            ("1:2:3:4:5:6:7:", InvalidSegment(String::new())),
+        ];
+        for (input, error) in cases {
+            assert_eq!(input.parse::<IpAddr>(), Err(error), "{input:?}");
+        }
+
+        // a single zero is fine, only leading zeros are refused
+        assert_eq!("0.0.0.0".parse(), Ok(IpAddr::V4([0; 4])));
+        assert_eq!(
+            "1:2:3:4:5:6::7"
+                .parse::<IpAddr>()
+                .map(|ip| ip.to_bits() & 0xffff),
+            Ok(7)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn std_addresses_round_trip(bits: u128, v4: u32) {
+            for std in [
+                net::IpAddr::V6(net::Ipv6Addr::from(bits)),
+                net::IpAddr::V4(net::Ipv4Addr::from(v4)),
+                net::IpAddr::V6(net::Ipv4Addr::from(v4).to_ipv6_mapped()),
+            ] {
+                let ip = IpAddr::from(std);
+                prop_assert_eq!(net::IpAddr::try_from(ip.clone()), Ok(std));
+                prop_assert_eq!(ip.to_string().parse::<IpAddr>(), Ok(ip.clone()));
+                // both sides read what the other writes
+                prop_assert_eq!(std.to_string().parse::<IpAddr>(), Ok(ip.clone()));
+                prop_assert_eq!(ip.to_string().parse::<net::IpAddr>(), Ok(std));
+            }
+        }
+    }
+
+    #[test]
+    fn zones_do_not_convert_to_std() {
+        let zoned: IpAddr = "fe80::1%eth0".parse().expect("valid address");
+        assert_eq!(net::IpAddr::try_from(zoned.clone()), Err(zoned));
+    }
+}
@@ -0,0 +1,19 @@
+// the address, message and currency types live in the library crate,
+// main.rs shows them
+pub mod acl;
+pub mod canvas;
+pub mod cidr;
+pub mod currency;
+pub mod dispatch;
+pub mod ip;
+pub mod message;
+pub mod wire;
+
+pub use crate::acl::{Acl, Action, Decision};
+pub use crate::canvas::{Canvas, CanvasError};
+pub use crate::cidr::{Cidr, CidrError};
+pub use crate::currency::{CanonicalError, Change, Currency, CurrencyError, ParseAmountError};
+pub use crate::dispatch::{Dispatcher, Middleware, Outcome, RunError};
+pub use crate::ip::{Category, IpAddr, ParseIpError};
+pub use crate::message::{Kind, Message, ParseMessageError};
+pub use crate::wire::{DecodeError, EncodeError, FrameError, WireError};
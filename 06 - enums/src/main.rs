@@ -1,23 +1,22 @@
 #![allow(unused)]
 
+use enums::dispatch::{Filter, Logger};
+use enums::wire;
+use enums::{Acl, Canvas, Cidr, Currency, Dispatcher, IpAddr, Message, Outcome};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::{env, process};
+
 enum IpAddrKind {
     V4,
     V6,
 }
 
-enum IpAddr {
-    // putting data directly into each enum variant is possible too
-    V4(String),
-    V6(String),
-}
+// putting data directly into each enum variant is possible too, see
+// `IpAddr` in ip.rs: `V4` holds four octets and `V6` eight segments
 
-// enum variants with different types
-enum Message {
-    Quit,
-    Move { x: i32, y: i32 },
-    Write(String),
-    ChangeColor(i32, i32, i32),
-}
+// enum variants with different types, see `Message` in message.rs:
+// `Quit`, `Move { x, y }`, `Write(String)` and `ChangeColor(r, g, b)`
 
 #[derive(Debug)]
 enum UsState {
@@ -33,12 +32,8 @@ enum Coin {
     Quarter(UsState),
 }
 
-// enums can have methods too
-impl Message {
-    fn call(&self) {
-        // method body would be defined here
-    }
-}
+// enums can have methods too, `Message::call` hands the message to
+// a `Dispatcher` with a handler for each variant
 
 // since rust does not have nulls Option enum is used
 // to indicate if a value is absent at the moment
@@ -67,11 +62,111 @@ fn main() {
         address: String::from("::1"),
     };
 
-    // IpAddr::V4() is a function call that takes a String argument
+    // IpAddr::V4() is a function call that takes the four octets
     // and returns an instance of the IpAddr. A constructor!
-    let home = IpAddr::V4(String::from("127.0.0.1"));
+    let home = IpAddr::V4([127, 0, 0, 1]);
+
+    // addresses can also be parsed from text, and are printed in their
+    // shortest form
+    let loopback: IpAddr = "0:0:0:0:0:0:0:1".parse().expect("valid address");
+    println!("{} is {}", home, home.category());
+    println!("{} is {}", loopback, loopback.category());
+    for text in [
+        "fe80::1%eth0",
+        "2001:DB8:0:0:1::1",
+        "::ffff:192.168.1.10",
+        "1::2::3",
+    ] {
+        match text.parse::<IpAddr>() {
+            Ok(ip) => println!("{} is {}", ip, ip.category()),
+            Err(e) => println!("{}: {}", text, e),
+        }
+    }
+
+    // a network is an address and a prefix length
+    let lan: Cidr = "192.168.1.0/24".parse().expect("valid network");
+    println!(
+        "{} holds {} addresses, {} of them for hosts",
+        lan,
+        lan.size().unwrap_or(0),
+        lan.host_count().unwrap_or(0)
+    );
+    println!("{} in {}: {}", home, lan, lan.contains(&home));
+    let blocks: Vec<Cidr> = ["10.0.0.0/25", "10.0.0.128/25", "10.0.1.0/24", "10.0.3.0/24"]
+        .iter()
+        .map(|s| s.parse().expect("valid network"))
+        .collect();
+    let merged: Vec<String> = enums::cidr::merge(&blocks)
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    println!("merged: {}", merged.join(", "));
+
+    // an access list: the matching rule with the longest prefix decides
+    let acl = Acl::parse(
+        "allow 192.168.1.0/24
+         deny  192.168.1.5      # the printer, an exception to the line above
+         deny  192.168.1.0/24   # never used, line 1 is for the same network
+         allow 2001:db8::/32",
+    )
+    .expect("valid rules");
+    for text in ["192.168.1.5", "192.168.1.20", "10.0.0.1", "2001:db8::1"] {
+        let ip: IpAddr = text.parse().expect("valid address");
+        println!("{}: {}", ip, acl.check(&ip));
+    }
+    for s in acl.shadowed() {
+        println!("{} is shadowed", s.rule);
+    }
+
+    // a dispatcher routes each message to the handler for its variant
+    let mut dispatcher = printer();
+    dispatcher.with(Filter::new(
+        |m| !matches!(m, Message::Write(t) if t.is_empty()),
+    ));
+    for message in [
+        Message::Move { x: 3, y: 4 },
+        Message::Write(String::new()),
+        Message::ChangeColor(0, 128, 255),
+        Message::Quit,
+        Message::Write(String::from("too late")),
+    ] {
+        match message.call(&mut dispatcher) {
+            Outcome::Handled(line) => println!("{}", line),
+            other => println!("[{}] {:?}", message, other),
+        }
+    }
 
-    let loopback = IpAddr::V6(String::from("::1"));
+    // messages as bytes, framed one after another as on a socket
+    let mut stream = Vec::new();
+    for message in [
+        Message::Move { x: -1, y: 300 },
+        Message::Write(String::from("two\nlines")),
+    ] {
+        println!("{} is {:?}", message, wire::encode(&message));
+        wire::write_frame(&mut stream, &message).expect("a valid message");
+    }
+    for message in wire::FrameReader::new(&stream[..]) {
+        println!("read back: {}", message.expect("the frames just written"));
+    }
+    println!("{:?}", wire::encode(&Message::ChangeColor(0, 0, 256)));
+    println!("{:?}", wire::decode(&[1, 9]));
+
+    // messages drive a turtle that draws
+    let mut canvas = Canvas::new();
+    for message in [
+        Message::Move { x: 4, y: 0 },
+        Message::ChangeColor(200, 0, 0),
+        Message::Write(String::from("hi")),
+        Message::Move { x: 0, y: 3 },
+    ] {
+        canvas.apply(&message).expect("a valid message");
+    }
+    println!("cursor at {:?}", canvas.state().cursor);
+    canvas.undo().expect("something to undo");
+    println!("after undo, cursor at {:?}", canvas.state().cursor);
+    canvas.apply(&Message::Quit).expect("not finished yet");
+    println!("{:?}", canvas.apply(&Message::Move { x: 1, y: 1 }));
+    println!("{:?}", canvas.redo());
 
     // the Option enum examples
     let absent_number: Option<i32> = None;
@@ -79,6 +174,21 @@ fn main() {
 
     println!("{}", value_in_cents(Coin::Nickel));
 
+    // a currency knows its coins, and the fewest of them for an amount
+    let usd = Currency::usd();
+    let amount = usd.parse_amount("$1.37").expect("a valid amount");
+    if let Ok(Some(change)) = usd.min_coins(amount) {
+        println!(
+            "{} is {}",
+            usd.format_amount(amount),
+            usd.format_change(&change)
+        );
+    }
+    // with coins of 1, 3 and 4, taking the largest coin first is not best
+    let odd = Currency::new("odd", "", 0, &[1, 3, 4]).expect("valid coins");
+    println!("{:?}", odd.counterexample());
+    println!("{:?}", odd.compare(6));
+
     // using Option<T>
     let five = Some(5);
     let six = plus_one(five);
@@ -99,6 +209,203 @@ fn main() {
     if let Coin::Quarter(state) = coin {
         println!("State quarter from {:?}!", state);
     }
+
+    // usage: cargo run -- subnet <address/prefix> [subnet prefix]
+    //        cargo run -- acl <rules file> <address>...
+    //        cargo run -- messages [file], reading stdin without a file
+    //        cargo run -- draw <messages file> <picture.svg or .ppm>
+    //        cargo run -- change <amount> [usd, eur or a coins file]
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = run(&args) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// at most this many subnets are listed
+const MAX_LISTED: usize = 64;
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [command, network] if command == "subnet" => subnet(network, None),
+        [command, network, split] if command == "subnet" => subnet(network, Some(split)),
+        [command, rules, addresses @ ..] if command == "acl" && !addresses.is_empty() => {
+            acl(rules, addresses)
+        }
+        [command, input, output] if command == "draw" => draw(input, output),
+        [command, amount] if command == "change" => change(amount, None),
+        [command, amount, coins] if command == "change" => change(amount, Some(coins)),
+        [command] if command == "messages" => messages(None),
+        [command, path] if command == "messages" => messages(Some(path)),
+        _ => Err(String::from(
+            "usage: enums subnet <address/prefix> [subnet prefix]\n       \
+             enums acl <rules file> <address>...\n       \
+             enums messages [file]\n       \
+             enums draw <messages file> <picture.svg or .ppm>\n       \
+             enums change <amount> [usd, eur or a coins file]",
+        )),
+    }
+}
+
+fn subnet(network: &str, split: Option<&String>) -> Result<(), String> {
+    let (address, cidr) = Cidr::parse_host(network).map_err(|e| format!("{}: {}", network, e))?;
+    print!("{}", enums::cidr::report(&address, &cidr));
+
+    if let Some(split) = split {
+        let prefix: u8 = split
+            .trim_start_matches('/')
+            .parse()
+            .map_err(|_| format!("invalid subnet prefix: {}", split))?;
+        let subnets = cidr.subnets(prefix).map_err(|e| e.to_string())?;
+        println!();
+        // there can be far too many to collect, so they are counted as they
+        // go by instead
+        let mut count: u128 = 0;
+        for subnet in subnets {
+            if count < MAX_LISTED as u128 {
+                println!("{}", subnet);
+            }
+            count += 1;
+            if count > MAX_LISTED as u128 {
+                let total = 1u128.checked_shl((prefix - cidr.prefix()) as u32);
+                match total {
+                    Some(total) => println!("... {} subnets in all", total),
+                    None => println!("... 2^128 subnets in all"),
+                }
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn acl(rules: &str, addresses: &[String]) -> Result<(), String> {
+    let acl = Acl::load(rules).map_err(|e| format!("{}: {}", rules, e))?;
+    // a shadowed rule is not an error, but it is most likely a mistake
+    for s in acl.shadowed() {
+        let lines: Vec<String> = s.by.iter().map(|r| r.line.to_string()).collect();
+        let kind = if s.same_action {
+            "redundant"
+        } else {
+            "never used"
+        };
+        eprintln!(
+            "warning: {}: {} {}, covered by {} {}",
+            rules,
+            s.rule,
+            kind,
+            if lines.len() == 1 { "line" } else { "lines" },
+            lines.join(", ")
+        );
+    }
+    for text in addresses {
+        let ip: IpAddr = text.parse().map_err(|e| format!("{}: {}", text, e))?;
+        println!("{} {}", ip, acl.check(&ip));
+    }
+    Ok(())
+}
+
+// a dispatcher whose handlers describe each message
+fn printer() -> Dispatcher<String> {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher
+        .on_quit(|| String::from("quitting"))
+        .on_move(|x, y| format!("moving to ({}, {})", x, y))
+        .on_write(|text| format!("writing {:?}", text))
+        .on_change_color(|r, g, b| format!("changing the colour to ({}, {}, {})", r, g, b));
+    dispatcher
+}
+
+fn messages(path: Option<&String>) -> Result<(), String> {
+    let mut dispatcher = printer();
+    dispatcher.with(Logger::new(io::stderr()));
+    let outcomes = match path {
+        Some(path) => {
+            let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+            dispatcher.run_lines(BufReader::new(file))
+        }
+        None => dispatcher.run_lines(io::stdin().lock()),
+    }
+    .map_err(|e| e.to_string())?;
+
+    for outcome in outcomes {
+        if let Outcome::Handled(line) = outcome {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+// draws the messages in `input`, one per line, where a line can also say
+// `undo` or `redo`
+fn draw(input: &str, output: &str) -> Result<(), String> {
+    let text = fs::read_to_string(input).map_err(|e| format!("{}: {}", input, e))?;
+    let mut canvas = Canvas::new();
+    for (i, line) in text.lines().enumerate() {
+        let result = match line.trim() {
+            "" => continue,
+            line if line.starts_with('#') => continue,
+            "undo" => canvas.undo().map(|_| ()),
+            "redo" => canvas.redo().map(|_| ()),
+            line => {
+                let message: Message = line
+                    .parse()
+                    .map_err(|e| format!("{}: line {}: {}", input, i + 1, e))?;
+                canvas.apply(&message)
+            }
+        };
+        result.map_err(|e| format!("{}: line {}: {}", input, i + 1, e))?;
+    }
+
+    let picture = if output.ends_with(".svg") {
+        canvas.state().to_svg().into_bytes()
+    } else if output.ends_with(".ppm") {
+        canvas.state().to_ppm(10).map_err(|e| e.to_string())?
+    } else {
+        return Err(format!("{}: expected a .svg or .ppm file", output));
+    };
+    fs::write(output, picture).map_err(|e| format!("{}: {}", output, e))
+}
+
+fn change(amount: &str, coins: Option<&String>) -> Result<(), String> {
+    let currency = match coins.map(|c| c.as_str()) {
+        None | Some("usd") => Currency::usd(),
+        Some("eur") => Currency::eur(),
+        Some(path) => Currency::load(path).map_err(|e| format!("{}: {}", path, e))?,
+    };
+    let amount = currency
+        .parse_amount(amount)
+        .map_err(|e| format!("{}: {}", amount, e))?;
+    let comparison = currency.compare(amount).map_err(|e| e.to_string())?;
+    let describe = |change: &Option<enums::Change>| match change {
+        Some(change) => format!(
+            "{} ({} coins)",
+            currency.format_change(change),
+            change.count()
+        ),
+        None => String::from("not possible"),
+    };
+
+    println!("{}", currency);
+    println!("amount:  {}", currency.format_amount(amount));
+    println!("fewest:  {}", describe(&comparison.optimal));
+    if !comparison.greedy_is_optimal() {
+        println!("greedy:  {}", describe(&comparison.greedy));
+    }
+    if let Ok(Some(ways)) = currency.ways(amount) {
+        println!("ways:    {}", ways);
+    }
+    match currency.counterexample() {
+        Ok(Some(bad)) => println!(
+            "not canonical: greedy change for {} is not the fewest coins",
+            currency.format_amount(bad)
+        ),
+        Ok(None) => println!("canonical: greedy change is always the fewest coins"),
+        Err(e) => println!("canonical: unknown, {}", e),
+    }
+    Ok(())
 }
 
 fn value_in_cents(coin: Coin) -> u8 {
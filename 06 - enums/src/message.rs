@@ -0,0 +1,334 @@
+use crate::dispatch::{Dispatcher, Outcome};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// the chapter's enum with variants of different shapes: one with no data,
+// one with named fields like a struct, one with a single value and one
+// with three, like a tuple struct
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    Quit,
+    Move { x: i32, y: i32 },
+    Write(String),
+    ChangeColor(i32, i32, i32),
+}
+
+// which variant a message is, without its data. Handlers are registered
+// for a kind, since they cannot be registered for a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Quit,
+    Move,
+    Write,
+    ChangeColor,
+}
+
+impl Message {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Message::Quit => Kind::Quit,
+            Message::Move { .. } => Kind::Move,
+            Message::Write(_) => Kind::Write,
+            Message::ChangeColor(..) => Kind::ChangeColor,
+        }
+    }
+
+    // the chapter's method, now with something to call: the handler the
+    // dispatcher has for this message
+    pub fn call<R>(&self, dispatcher: &mut Dispatcher<R>) -> Outcome<R> {
+        dispatcher.dispatch(self)
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Quit => "quit",
+            Kind::Move => "move",
+            Kind::Write => "write",
+            Kind::ChangeColor => "color",
+        })
+    }
+}
+
+// messages are written one per line, as a command and its arguments:
+//
+//     move 10 -4
+//     write hello, world
+//     color 255 128 0
+//     quit
+//
+// the text of `write` is the rest of the line after the space. Text that
+// would not survive that, because it is empty, has spaces at either end,
+// holds a line break or other control character, or starts with a quote,
+// is written in double quotes instead, with `\\`, `\"`, `\n`, `\r`, `\t`
+// and `\u{..}` escapes:
+//
+//     write "  indented\nand on two lines"
+//
+// so that parsing what is written gives back the same message. See wire.rs
+// for a binary form
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Quit => write!(f, "quit"),
+            Message::Move { x, y } => write!(f, "move {} {}", x, y),
+            Message::Write(text) if needs_quotes(text) => write!(f, "write {}", quote(text)),
+            Message::Write(text) => write!(f, "write {}", text),
+            Message::ChangeColor(r, g, b) => write!(f, "color {} {} {}", r, g, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseMessageError {
+    Empty,
+    UnknownCommand(String),
+    MissingArgument(Kind),
+    InvalidNumber(String),
+    TrailingText(String),
+    UnterminatedString,
+    InvalidEscape(String),
+}
+
+impl fmt::Display for ParseMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseMessageError::Empty => write!(f, "empty message"),
+            ParseMessageError::UnknownCommand(c) => write!(
+                f,
+                "expected `quit`, `move`, `write` or `color`, found `{}`",
+                c
+            ),
+            ParseMessageError::MissingArgument(kind) => {
+                write!(f, "missing argument to `{}`", kind)
+            }
+            ParseMessageError::InvalidNumber(n) => write!(f, "invalid number `{}`", n),
+            ParseMessageError::TrailingText(t) => write!(f, "unexpected `{}`", t),
+            ParseMessageError::UnterminatedString => write!(f, "missing closing `\"`"),
+            ParseMessageError::InvalidEscape(e) => write!(f, "invalid escape `{}`", e),
+        }
+    }
+}
+
+impl Error for ParseMessageError {}
+
+impl FromStr for Message {
+    type Err = ParseMessageError;
+
+    fn from_str(s: &str) -> Result<Message, ParseMessageError> {
+        let s = s.trim_start();
+        let (command, rest) = s.split_once(' ').unwrap_or((s, ""));
+        match command.trim_end() {
+            "" => Err(ParseMessageError::Empty),
+            "quit" => {
+                numbers::<0>(Kind::Quit, rest)?;
+                Ok(Message::Quit)
+            }
+            "move" => {
+                let [x, y] = numbers(Kind::Move, rest)?;
+                Ok(Message::Move { x, y })
+            }
+            "write" if rest.starts_with('"') => Ok(Message::Write(unquote(rest)?)),
+            // the text is kept as it is, spaces and all
+            "write" => Ok(Message::Write(rest.to_string())),
+            "color" => {
+                let [r, g, b] = numbers(Kind::ChangeColor, rest)?;
+                Ok(Message::ChangeColor(r, g, b))
+            }
+            other => Err(ParseMessageError::UnknownCommand(other.to_string())),
+        }
+    }
+}
+
+// exactly `N` whole numbers separated by spaces
+fn numbers<const N: usize>(kind: Kind, s: &str) -> Result<[i32; N], ParseMessageError> {
+    let mut words = s.split_whitespace();
+    let mut values = [0; N];
+    for value in values.iter_mut() {
+        let word = words
+            .next()
+            .ok_or(ParseMessageError::MissingArgument(kind))?;
+        *value = word
+            .parse()
+            .map_err(|_| ParseMessageError::InvalidNumber(word.to_string()))?;
+    }
+    match words.next() {
+        Some(word) => Err(ParseMessageError::TrailingText(word.to_string())),
+        None => Ok(values),
+    }
+}
+
+fn needs_quotes(text: &str) -> bool {
+    text.is_empty()
+        || text.starts_with('"')
+        || text.trim() != text
+        || text.chars().any(char::is_control)
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// the text inside the quotes at the start of `s`, which may only be
+// followed by spaces
+fn unquote(s: &str) -> Result<String, ParseMessageError> {
+    let mut text = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        if c == '"' {
+            let rest = s[i + 1..].trim();
+            if !rest.is_empty() {
+                return Err(ParseMessageError::TrailingText(rest.to_string()));
+            }
+            return Ok(text);
+        }
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        let c = match chars.next() {
+            Some((_, '\\')) => '\\',
+            Some((_, '"')) => '"',
+            Some((_, 'n')) => '\n',
+            Some((_, 'r')) => '\r',
+            Some((_, 't')) => '\t',
+            // `\u{` and one to six hex digits up to a `}`
+            Some((_, 'u')) => {
+                let escape: String = chars
+                    .by_ref()
+                    .map(|(_, c)| c)
+                    .take_while(|&c| c != '}')
+                    .take(8)
+                    .collect();
+                escape
+                    .strip_prefix('{')
+                    .filter(|hex| (1..=6).contains(&hex.len()))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| ParseMessageError::InvalidEscape(format!("\\u{}", escape)))?
+            }
+            Some((_, c)) => return Err(ParseMessageError::InvalidEscape(format!("\\{}", c))),
+            None => return Err(ParseMessageError::UnterminatedString),
+        };
+        text.push(c);
+    }
+    Err(ParseMessageError::UnterminatedString)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn message() -> impl Strategy<Value = Message> {
+        prop_oneof![
+            Just(Message::Quit),
+            (any::<i32>(), any::<i32>()).prop_map(|(x, y)| Message::Move { x, y }),
+            any::<String>().prop_map(Message::Write),
+            // text that is nearly all quotes, escapes and spaces
+            "[ \"\\\\\n\r\t\u{0}\u{7f}a]{0,12}".prop_map(Message::Write),
+            (any::<i32>(), any::<i32>(), any::<i32>())
+                .prop_map(|(r, g, b)| Message::ChangeColor(r, g, b)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn messages_round_trip_through_text(m in message()) {
+            prop_assert_eq!(m.to_string().parse(), Ok(m));
+        }
+
+        #[test]
+        fn garbage_does_not_panic(s in "\\PC*") {
+            let _ = s.parse::<Message>();
+        }
+    }
+
+    #[test]
+    fn text_is_quoted_only_when_it_has_to_be() {
+        let written = |text: &str| Message::Write(text.to_string()).to_string();
+        assert_eq!(written("hello, world"), "write hello, world");
+        assert_eq!(written("say \"hi\""), "write say \"hi\"");
+        assert_eq!(written(""), "write \"\"");
+        assert_eq!(written(" padded "), "write \" padded \"");
+        assert_eq!(written("\"quoted\""), "write \"\\\"quoted\\\"\"");
+        assert_eq!(written("a\tb\u{1}"), "write \"a\\tb\\u{1}\"");
+    }
+
+    #[test]
+    fn escapes_are_read() {
+        assert_eq!(
+            r#"write "a\\b\"c\nd\re\tf\u{1F600}""#.parse(),
+            Ok(Message::Write("a\\b\"c\nd\re\tf\u{1F600}".to_string()))
+        );
+        assert_eq!(
+            r#"write "x"   "#.parse(),
+            Ok(Message::Write("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn bad_escapes_are_errors() {
+        let error = |s: &str| s.parse::<Message>().unwrap_err();
+        let invalid = |e: &str| ParseMessageError::InvalidEscape(e.to_string());
+        assert_eq!(error(r#"write "\q""#), invalid("\\q"));
+        assert_eq!(error(r#"write "\u{}""#), invalid("\\u{"));
+        assert_eq!(error(r#"write "\u{1234567}""#), invalid("\\u{1234567"));
+        assert_eq!(error(r#"write "\u{zz}""#), invalid("\\u{zz"));
+        assert_eq!(error(r#"write "\u{d800}""#), invalid("\\u{d800"));
+        assert_eq!(error(r#"write "\u41""#), invalid("\\u41\""));
+        assert_eq!(
+            error(r#"write "abc"#),
+            ParseMessageError::UnterminatedString
+        );
+        assert_eq!(
+            error(r#"write "abc\"#),
+            ParseMessageError::UnterminatedString
+        );
+        assert_eq!(
+            error(r#"write "abc" def"#),
+            ParseMessageError::TrailingText("def".to_string())
+        );
+    }
+
+    #[test]
+    fn commands_are_checked() {
+        let error = |s: &str| s.parse::<Message>().unwrap_err();
+        assert_eq!(error("   "), ParseMessageError::Empty);
+        assert_eq!(
+            error("jump 1"),
+            ParseMessageError::UnknownCommand("jump".to_string())
+        );
+        assert_eq!(
+            error("move 1"),
+            ParseMessageError::MissingArgument(Kind::Move)
+        );
+        assert_eq!(
+            error("move 1 x"),
+            ParseMessageError::InvalidNumber("x".to_string())
+        );
+        assert_eq!(
+            error("color 1 2 3 4"),
+            ParseMessageError::TrailingText("4".to_string())
+        );
+        assert_eq!(
+            error("quit now"),
+            ParseMessageError::TrailingText("now".to_string())
+        );
+    }
+}
@@ -0,0 +1,598 @@
+use crate::message::Message;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+// a compact binary form of `Message`, for sending messages between
+// processes. A message is
+//
+//     version  tag  payload
+//
+// with one byte each for the version and the tag, and a payload that
+// depends on the tag:
+//
+//     0 Quit         nothing
+//     1 Move         x and y as varints
+//     2 Write        the length in bytes as a varint, then the UTF-8 text
+//     3 ChangeColor  r, g and b as one byte each
+//
+// a varint stores a number in 7-bit groups, lowest first, with the top bit
+// of each byte set when another byte follows. Signed numbers are zigzag
+// encoded first (0, -1, 1, -2 become 0, 1, 2, 3) so that small negative
+// numbers are short too. An i32 takes one to five bytes.
+//
+// decoding checks everything and returns an error for any input it does
+// not accept, so it can be handed bytes straight off the network
+
+pub const VERSION: u8 = 1;
+
+// the longest `Write` text, and with room for the rest the longest frame.
+// Longer lengths are rejected before anything is allocated for them
+pub const MAX_TEXT: usize = 1 << 20;
+pub const MAX_FRAME: usize = MAX_TEXT + 16;
+
+const QUIT: u8 = 0;
+const MOVE: u8 = 1;
+const WRITE: u8 = 2;
+const CHANGE_COLOR: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    // a `ChangeColor` component outside of 0 to 255
+    ColorOutOfRange(i32),
+    TextTooLong(usize),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::ColorOutOfRange(c) => {
+                write!(f, "colour component {} is not between 0 and 255", c)
+            }
+            EncodeError::TextTooLong(n) => {
+                write!(f, "text of {} bytes is longer than {}", n, MAX_TEXT)
+            }
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // the input stops in the middle of a message
+    UnexpectedEnd,
+    UnsupportedVersion(u8),
+    UnknownTag(u8),
+    // a varint longer than its type, or with bits that do not fit
+    InvalidVarint,
+    InvalidUtf8,
+    TooLong { length: u64, max: usize },
+    // bytes left over after a message that should fill the input
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            DecodeError::UnknownTag(t) => write!(f, "unknown message tag {}", t),
+            DecodeError::InvalidVarint => write!(f, "invalid varint"),
+            DecodeError::InvalidUtf8 => write!(f, "text is not valid UTF-8"),
+            DecodeError::TooLong { length, max } => {
+                write!(f, "length {} is longer than {}", length, max)
+            }
+            DecodeError::TrailingBytes(n) => write!(f, "{} bytes after the message", n),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+// what `FrameDecoder::next_message` ran into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    // the frame's length is bad. It cannot be skipped, so the stream is
+    // out of step and nothing after it can be read
+    Length(DecodeError),
+    // the frame does not hold a valid message. It has been dropped, and
+    // the frames after it can still be read
+    Message(DecodeError),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Length(e) => write!(f, "bad frame length: {}", e),
+            FrameError::Message(e) => write!(f, "bad frame: {}", e),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+pub fn encode(message: &Message) -> Result<Vec<u8>, EncodeError> {
+    let mut out = Vec::new();
+    encode_into(message, &mut out)?;
+    Ok(out)
+}
+
+// appends the message to `out`, leaving it as it was on an error
+pub fn encode_into(message: &Message, out: &mut Vec<u8>) -> Result<(), EncodeError> {
+    // checked first, so that nothing is written for a bad message
+    match message {
+        Message::ChangeColor(r, g, b) => {
+            for c in [*r, *g, *b] {
+                if !(0..=255).contains(&c) {
+                    return Err(EncodeError::ColorOutOfRange(c));
+                }
+            }
+        }
+        Message::Write(text) if text.len() > MAX_TEXT => {
+            return Err(EncodeError::TextTooLong(text.len()));
+        }
+        _ => {}
+    }
+
+    out.push(VERSION);
+    match message {
+        Message::Quit => out.push(QUIT),
+        Message::Move { x, y } => {
+            out.push(MOVE);
+            put_varint(out, zigzag(*x));
+            put_varint(out, zigzag(*y));
+        }
+        Message::Write(text) => {
+            out.push(WRITE);
+            put_varint(out, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Message::ChangeColor(r, g, b) => {
+            out.extend_from_slice(&[CHANGE_COLOR, *r as u8, *g as u8, *b as u8]);
+        }
+    }
+    Ok(())
+}
+
+// a message that takes up all of `bytes`
+pub fn decode(bytes: &[u8]) -> Result<Message, DecodeError> {
+    let (message, used) = decode_prefix(bytes)?;
+    match bytes.len() - used {
+        0 => Ok(message),
+        n => Err(DecodeError::TrailingBytes(n)),
+    }
+}
+
+// the message at the start of `bytes`, and the number of bytes it took
+pub fn decode_prefix(bytes: &[u8]) -> Result<(Message, usize), DecodeError> {
+    let mut input = Input { bytes, at: 0 };
+    let version = input.byte()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let message = match input.byte()? {
+        QUIT => Message::Quit,
+        MOVE => Message::Move {
+            x: input.i32()?,
+            y: input.i32()?,
+        },
+        WRITE => {
+            let length = input.length(MAX_TEXT)?;
+            let text =
+                std::str::from_utf8(input.take(length)?).map_err(|_| DecodeError::InvalidUtf8)?;
+            Message::Write(text.to_string())
+        }
+        CHANGE_COLOR => {
+            let [r, g, b] = [input.byte()?, input.byte()?, input.byte()?];
+            Message::ChangeColor(r as i32, g as i32, b as i32)
+        }
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+    Ok((message, input.at))
+}
+
+// the bytes being decoded, and how far decoding has got
+struct Input<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.at..)
+            .and_then(|rest| rest.get(..n))
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self, max_bytes: u32) -> Result<u64, DecodeError> {
+        let (value, used) = get_varint(&self.bytes[self.at..], max_bytes)?;
+        self.at += used;
+        Ok(value)
+    }
+
+    fn i32(&mut self) -> Result<i32, DecodeError> {
+        let value = self.varint(5)?;
+        let value = u32::try_from(value).map_err(|_| DecodeError::InvalidVarint)?;
+        Ok(unzigzag(value))
+    }
+
+    fn length(&mut self, max: usize) -> Result<usize, DecodeError> {
+        let length = self.varint(10)?;
+        if length > max as u64 {
+            return Err(DecodeError::TooLong { length, max });
+        }
+        Ok(length as usize)
+    }
+}
+
+fn zigzag(n: i32) -> u64 {
+    ((n << 1) ^ (n >> 31)) as u32 as u64
+}
+
+fn unzigzag(n: u32) -> i32 {
+    (n >> 1) as i32 ^ -((n & 1) as i32)
+}
+
+fn put_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+// the varint at the start of `bytes` and its length, in at most
+// `max_bytes` bytes. A last byte of zero after the first is refused, so
+// that every number has only one encoding
+fn get_varint(bytes: &[u8], max_bytes: u32) -> Result<(u64, usize), DecodeError> {
+    let mut value: u64 = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        let shift = 7 * i as u32;
+        if i as u32 >= max_bytes || (shift == 63 && b > 1) {
+            return Err(DecodeError::InvalidVarint);
+        }
+        value |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            if b == 0 && i > 0 {
+                return Err(DecodeError::InvalidVarint);
+            }
+            return Ok((value, i + 1));
+        }
+    }
+    Err(DecodeError::UnexpectedEnd)
+}
+
+// streams carry one message after another, each in a frame that starts
+// with its length as a varint. The length lets a reader skip a message it
+// cannot decode, and know when a whole one has arrived
+
+pub fn write_frame<W: Write>(out: &mut W, message: &Message) -> Result<(), WireError> {
+    let body = encode(message)?;
+    let mut frame = Vec::with_capacity(body.len() + 4);
+    put_varint(&mut frame, body.len() as u64);
+    frame.extend_from_slice(&body);
+    out.write_all(&frame)?;
+    Ok(())
+}
+
+// collects bytes as they arrive, in pieces of any size, and hands out the
+// messages in them once they are complete
+#[derive(Debug, Clone, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> FrameDecoder {
+        FrameDecoder::default()
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    // the bytes of a frame that has not arrived in full
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    // the next message, or `None` until its frame is complete. A frame
+    // that does not hold a valid message is dropped with the error, so
+    // the messages after it can still be read. A bad length cannot be
+    // skipped, and leaves the decoder returning the same error
+    pub fn next_message(&mut self) -> Result<Option<Message>, FrameError> {
+        let (length, used) = match get_varint(&self.buffer, 10) {
+            Ok(varint) => varint,
+            Err(DecodeError::UnexpectedEnd) => return Ok(None),
+            Err(e) => return Err(FrameError::Length(e)),
+        };
+        if length > MAX_FRAME as u64 {
+            return Err(FrameError::Length(DecodeError::TooLong {
+                length,
+                max: MAX_FRAME,
+            }));
+        }
+        let end = used + length as usize;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+        let message = decode(&self.buffer[used..end]);
+        self.buffer.drain(..end);
+        message.map(Some).map_err(FrameError::Message)
+    }
+}
+
+// the messages framed in a byte stream, such as a socket
+pub struct FrameReader<R> {
+    input: R,
+    decoder: FrameDecoder,
+    done: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(input: R) -> FrameReader<R> {
+        FrameReader {
+            input,
+            decoder: FrameDecoder::new(),
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<Message, WireError>;
+
+    // ends when the stream does, which must be between frames
+    fn next(&mut self) -> Option<Result<Message, WireError>> {
+        let mut chunk = [0; 4096];
+        loop {
+            if self.done {
+                return None;
+            }
+            match self.decoder.next_message() {
+                Ok(Some(message)) => return Some(Ok(message)),
+                Ok(None) => {}
+                // the stream is out of step and cannot be read any further
+                Err(FrameError::Length(e)) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+                Err(FrameError::Message(e)) => return Some(Err(e.into())),
+            }
+
+            match self.input.read(&mut chunk) {
+                Ok(0) => {
+                    self.done = true;
+                    return match self.decoder.pending() {
+                        0 => None,
+                        _ => Some(Err(DecodeError::UnexpectedEnd.into())),
+                    };
+                }
+                Ok(n) => self.decoder.extend(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WireError {
+    Io(io::Error),
+    Encode(EncodeError),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WireError::Io(e) => write!(f, "{}", e),
+            WireError::Encode(e) => write!(f, "{}", e),
+            WireError::Decode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for WireError {}
+
+impl From<io::Error> for WireError {
+    fn from(e: io::Error) -> WireError {
+        WireError::Io(e)
+    }
+}
+
+impl From<EncodeError> for WireError {
+    fn from(e: EncodeError) -> WireError {
+        WireError::Encode(e)
+    }
+}
+
+impl From<DecodeError> for WireError {
+    fn from(e: DecodeError) -> WireError {
+        WireError::Decode(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // every message that encodes: colours have to fit in a byte
+    fn message() -> impl Strategy<Value = Message> {
+        prop_oneof![
+            Just(Message::Quit),
+            (any::<i32>(), any::<i32>()).prop_map(|(x, y)| Message::Move { x, y }),
+            any::<String>().prop_map(Message::Write),
+            (0..=255, 0..=255, 0..=255).prop_map(|(r, g, b)| Message::ChangeColor(r, g, b)),
+        ]
+    }
+
+    // reads frames until the decoder wants more bytes, or is stuck on an
+    // error it cannot skip
+    fn drain(decoder: &mut FrameDecoder) -> Vec<Result<Message, FrameError>> {
+        let mut results = Vec::new();
+        loop {
+            let pending = decoder.pending();
+            match decoder.next_message() {
+                Ok(None) => break,
+                Ok(Some(message)) => results.push(Ok(message)),
+                Err(e @ FrameError::Length(_)) => {
+                    results.push(Err(e));
+                    assert_eq!(decoder.pending(), pending, "a bad length is kept");
+                    break;
+                }
+                Err(e) => results.push(Err(e)),
+            }
+        }
+        results
+    }
+
+    proptest! {
+        #[test]
+        fn messages_round_trip(message in message()) {
+            let bytes = encode(&message).expect("encodes");
+            prop_assert_eq!(decode(&bytes), Ok(message));
+        }
+
+        #[test]
+        fn frames_round_trip_in_any_pieces(
+            messages in prop::collection::vec(message(), 0..8),
+            piece in 1usize..16,
+        ) {
+            let mut stream = Vec::new();
+            for message in &messages {
+                write_frame(&mut stream, message).expect("writes");
+            }
+
+            let mut decoder = FrameDecoder::new();
+            let mut decoded = Vec::new();
+            for chunk in stream.chunks(piece) {
+                decoder.extend(chunk);
+                decoded.extend(drain(&mut decoder));
+            }
+            let messages: Vec<_> = messages.into_iter().map(Ok).collect();
+            prop_assert_eq!(decoded, messages);
+            prop_assert_eq!(decoder.pending(), 0);
+        }
+
+        #[test]
+        fn truncated_messages_are_errors(message in message()) {
+            let bytes = encode(&message).expect("encodes");
+            for end in 0..bytes.len() {
+                prop_assert!(decode(&bytes[..end]).is_err());
+            }
+        }
+
+        #[test]
+        fn garbage_does_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            let _ = decode(&bytes);
+            let _ = decode_prefix(&bytes);
+
+            let mut decoder = FrameDecoder::new();
+            decoder.extend(&bytes);
+            drain(&mut decoder);
+        }
+
+        #[test]
+        fn garbage_after_a_version_does_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            let mut input = vec![VERSION];
+            input.extend(&bytes);
+            let _ = decode(&input);
+        }
+    }
+
+    #[test]
+    fn lengths_past_the_limit_are_rejected_before_reading() {
+        let mut bytes = vec![VERSION, WRITE];
+        put_varint(&mut bytes, MAX_TEXT as u64 + 1);
+        assert!(matches!(decode(&bytes), Err(DecodeError::TooLong { .. })));
+
+        let mut frame = Vec::new();
+        put_varint(&mut frame, u64::MAX);
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&frame);
+        assert!(matches!(
+            decoder.next_message(),
+            Err(FrameError::Length(DecodeError::TooLong { .. }))
+        ));
+    }
+
+    #[test]
+    fn a_bad_frame_is_skipped() {
+        let mut stream = Vec::new();
+        // a frame with an unknown tag, then a good one
+        stream.extend([2, VERSION, 9]);
+        write_frame(&mut stream, &Message::Quit).expect("writes");
+
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&stream);
+        assert_eq!(
+            drain(&mut decoder),
+            vec![
+                Err(FrameError::Message(DecodeError::UnknownTag(9))),
+                Ok(Message::Quit)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_bad_varint_inside_a_frame_is_skipped() {
+        let mut stream = Vec::new();
+        // a move whose x ends in a zero byte, which is not allowed
+        stream.extend([4, VERSION, MOVE, 0x80, 0x00]);
+        write_frame(&mut stream, &Message::Quit).expect("writes");
+
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&stream);
+        assert_eq!(
+            drain(&mut decoder),
+            vec![
+                Err(FrameError::Message(DecodeError::InvalidVarint)),
+                Ok(Message::Quit)
+            ]
+        );
+
+        let read: Vec<_> = FrameReader::new(&stream[..])
+            .map(|result| match result {
+                Ok(message) => Ok(message),
+                Err(WireError::Decode(e)) => Err(e),
+                Err(e) => panic!("unexpected error {:?}", e),
+            })
+            .collect();
+        assert_eq!(
+            read,
+            vec![Err(DecodeError::InvalidVarint), Ok(Message::Quit)]
+        );
+    }
+
+    #[test]
+    fn a_bad_length_stops_the_reader() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, &Message::Quit).expect("writes");
+        // a length whose last byte is zero, then a good frame that cannot
+        // be found any more
+        stream.extend([0x80, 0x00]);
+        write_frame(&mut stream, &Message::Quit).expect("writes");
+
+        let mut reader = FrameReader::new(&stream[..]);
+        assert!(matches!(reader.next(), Some(Ok(Message::Quit))));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(WireError::Decode(DecodeError::InvalidVarint)))
+        ));
+        assert!(reader.next().is_none());
+    }
+}